@@ -0,0 +1,207 @@
+//! Command-line argument parsing
+//!
+//! SaltPass is interactive by default; arguments only select a profile or run one of
+//! the maintenance commands instead of the interactive menu.
+
+use std::path::PathBuf;
+
+/// Usage text printed by `--help`
+pub const USAGE: &str = "\
+Usage: SaltPass [--profile <name>] [command]
+
+Options:
+  -p, --profile <name>   Use the named profile instead of the default store
+  -h, --help             Show this help
+  -V, --version          Show the version
+
+Commands:
+  (none)                          Start the interactive menu
+  profile list                    List profiles
+  profile create <name>           Create a profile
+  profile rename <old> <new>      Rename a profile
+  profile delete <name>           Delete a profile
+  merge <base> <ours> <theirs>    Three-way merge of diverged features files
+        [-o, --output <file>]     (the result is written to <ours> by default)
+  audit                           Show the audit log and verify its hash chain
+  export-backup <file>            Write a passphrase-encrypted backup archive
+  import-backup <file>            Restore, merge or replace from a backup archive";
+
+/// Parsed command line
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+    pub profile: Option<String>,
+    pub command: Command,
+}
+
+/// What SaltPass should do
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Interactive,
+    Profile(ProfileCommand),
+    Merge {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        output: Option<PathBuf>,
+    },
+    Audit,
+    ExportBackup(PathBuf),
+    ImportBackup(PathBuf),
+    Help,
+    Version,
+}
+
+/// Profile management subcommands
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfileCommand {
+    List,
+    Create(String),
+    Rename(String, String),
+    Delete(String),
+}
+
+impl Args {
+    /// Parse arguments (without the program name)
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut profile = None;
+        let mut output = None;
+        let mut positional = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-p" | "--profile" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| format!("Missing profile name after '{}'", arg))?;
+                    profile = Some(name);
+                }
+                "-o" | "--output" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("Missing file after '{}'", arg))?;
+                    output = Some(PathBuf::from(path));
+                }
+                "-h" | "--help" => return Ok(Self::command(profile, Command::Help)),
+                "-V" | "--version" => return Ok(Self::command(profile, Command::Version)),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+                _ => positional.push(arg),
+            }
+        }
+
+        let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+        let command = match positional.as_slice() {
+            [] => Command::Interactive,
+            ["profile", "list"] => Command::Profile(ProfileCommand::List),
+            ["profile", "create", name] => {
+                Command::Profile(ProfileCommand::Create(name.to_string()))
+            }
+            ["profile", "rename", old, new] => {
+                Command::Profile(ProfileCommand::Rename(old.to_string(), new.to_string()))
+            }
+            ["profile", "delete", name] => {
+                Command::Profile(ProfileCommand::Delete(name.to_string()))
+            }
+            ["profile", ..] => return Err("Invalid profile command".to_string()),
+            ["merge", base, ours, theirs] => Command::Merge {
+                base: PathBuf::from(base),
+                ours: PathBuf::from(ours),
+                theirs: PathBuf::from(theirs),
+                output: output.take(),
+            },
+            ["merge", ..] => return Err("merge needs <base> <ours> <theirs>".to_string()),
+            ["audit"] => Command::Audit,
+            ["export-backup", file] => Command::ExportBackup(PathBuf::from(file)),
+            ["import-backup", file] => Command::ImportBackup(PathBuf::from(file)),
+            ["export-backup" | "import-backup", ..] => {
+                return Err(format!("{} needs a <file>", positional[0]));
+            }
+            [other, ..] => return Err(format!("Unknown command '{}'", other)),
+        };
+
+        if output.is_some() {
+            return Err("'--output' is only valid with merge".to_string());
+        }
+
+        Ok(Self::command(profile, command))
+    }
+
+    fn command(profile: Option<String>, command: Command) -> Self {
+        Self { profile, command }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_profile_flag() {
+        let args = parse(&["--profile", "work"]).unwrap();
+        assert_eq!(args.profile.as_deref(), Some("work"));
+        assert_eq!(args.command, Command::Interactive);
+        assert!(parse(&["-p"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile_commands() {
+        assert_eq!(
+            parse(&["profile", "rename", "a", "b"]).unwrap().command,
+            Command::Profile(ProfileCommand::Rename("a".to_string(), "b".to_string()))
+        );
+        assert_eq!(
+            parse(&["profile", "list"]).unwrap().command,
+            Command::Profile(ProfileCommand::List)
+        );
+        assert!(parse(&["profile", "create"]).is_err());
+        assert!(parse(&["profile", "list", "-o", "x"]).is_err());
+        assert!(parse(&["bogus"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_merge() {
+        assert_eq!(
+            parse(&[
+                "merge", "b.toml", "a.toml", "c.toml", "--output", "out.toml"
+            ])
+            .unwrap()
+            .command,
+            Command::Merge {
+                base: PathBuf::from("b.toml"),
+                ours: PathBuf::from("a.toml"),
+                theirs: PathBuf::from("c.toml"),
+                output: Some(PathBuf::from("out.toml")),
+            }
+        );
+        assert!(parse(&["merge", "b.toml", "a.toml"]).is_err());
+        assert_eq!(
+            parse(&["-p", "work", "audit"]).unwrap(),
+            Args {
+                profile: Some("work".to_string()),
+                command: Command::Audit,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_backup_commands() {
+        assert_eq!(
+            parse(&["export-backup", "saltpass.backup"])
+                .unwrap()
+                .command,
+            Command::ExportBackup(PathBuf::from("saltpass.backup"))
+        );
+        assert_eq!(
+            parse(&["-p", "work", "import-backup", "saltpass.backup"])
+                .unwrap()
+                .command,
+            Command::ImportBackup(PathBuf::from("saltpass.backup"))
+        );
+        assert!(parse(&["import-backup"]).is_err());
+    }
+}
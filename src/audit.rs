@@ -0,0 +1,294 @@
+//! Tamper-evident audit log of store mutations
+//!
+//! Every mutation appends a record with the time, the action, the identity of the
+//! feature and the host name to a `<file>.audit` sidecar, one JSON record per line.
+//! Records never contain secrets. Each record includes the hash of the previous one, so
+//! editing, reordering or removing records breaks the chain; only truncating the newest
+//! records cannot be detected from the log alone. The hashes are HMAC-SHA256 keyed from
+//! the master salt under their own label, so the chain cannot be recomputed without it.
+//!
+//! When the store is encrypted, every line is encrypted on its own.
+
+use crate::crypto::{StorageCipher, StorageKey};
+use crate::models::Feature;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+/// `prev` of the first record
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// PBKDF2 label separating the chain key from the other keys derived from the salt
+const LABEL: &[u8] = b"SaltPass-Audit-Chain";
+
+/// Derive the key of the hash chain from the master salt
+pub fn derive_key(salt: &str) -> StorageKey {
+    use pbkdf2::pbkdf2_hmac;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(salt.as_bytes(), LABEL, 100_000, key.as_mut());
+    key
+}
+
+/// Kind of mutation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Add,
+    /// Changed in place; the record holds the new name and identifier
+    Edit,
+    /// Changed in a way that gives it a new password
+    Rotate,
+    /// Moved to the trash, or removed by undoing its addition
+    Delete,
+    /// Restored from the trash
+    Restore,
+    /// Removed from the trash for good
+    Purge,
+}
+
+impl AuditAction {
+    pub fn label(&self) -> &str {
+        match self {
+            AuditAction::Add => "➕ add",
+            AuditAction::Edit => "✏️  edit",
+            AuditAction::Rotate => "🔄 rotate",
+            AuditAction::Delete => "🗑️  delete",
+            AuditAction::Restore => "♻️  restore",
+            AuditAction::Purge => "🔥 purge",
+        }
+    }
+}
+
+/// Content of a record, covered by its hash
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    pub name: String,
+    pub feature: String,
+    pub host: String,
+    /// Hash of the previous record
+    pub prev: String,
+}
+
+impl AuditRecord {
+    fn hash(&self, key: &StorageKey) -> io::Result<String> {
+        let json =
+            serde_json::to_vec(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_ref())
+            .expect("HMAC can take key of any size");
+        mac.update(&json);
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+/// A line of the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub hash: String,
+}
+
+/// Name of this machine as recorded in the log
+fn host_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    key: Option<StorageKey>,
+    chain_key: StorageKey,
+}
+
+impl AuditLog {
+    /// Log kept in `path`; `key` encrypts the records and `chain_key` keys their hashes,
+    /// see [`derive_key`]
+    pub fn new(path: PathBuf, key: Option<StorageKey>, chain_key: StorageKey) -> Self {
+        Self {
+            path,
+            key,
+            chain_key,
+        }
+    }
+
+    /// Read all entries, oldest first
+    pub fn read(&self) -> io::Result<Vec<AuditEntry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                let json = match &self.key {
+                    Some(key) => StorageCipher::decrypt_with_key(key, line.trim())
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+                    None => line.as_bytes().to_vec(),
+                };
+                serde_json::from_slice(&json).map_err(|e| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Invalid audit record {}: {}", index + 1, e),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Append a record for a mutation of `feature`
+    ///
+    /// The caller must hold the store lock so that concurrent appends don't fork the
+    /// chain, see [`crate::storage::Storage::audit`].
+    pub fn append(&self, action: AuditAction, feature: &Feature) -> io::Result<()> {
+        let prev = self
+            .read()?
+            .last()
+            .map_or(GENESIS.to_string(), |e| e.hash.clone());
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            name: feature.name.clone(),
+            feature: feature.feature.clone(),
+            host: host_name(),
+            prev,
+        };
+        let entry = AuditEntry {
+            hash: record.hash(&self.chain_key)?,
+            record,
+        };
+
+        let line = self.render(&entry)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        file.sync_all()
+    }
+
+    /// Replace the whole log, e.g. to encrypt it under a new key
+    pub fn rewrite(&self, entries: &[AuditEntry]) -> io::Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&self.render(entry)?);
+            content.push('\n');
+        }
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Check the hash chain, returning the index of the first invalid entry
+    pub fn verify(&self, entries: &[AuditEntry]) -> io::Result<Result<(), usize>> {
+        let mut prev = GENESIS.to_string();
+        for (index, entry) in entries.iter().enumerate() {
+            if entry.record.prev != prev || entry.record.hash(&self.chain_key)? != entry.hash {
+                return Ok(Err(index));
+            }
+            prev = entry.hash.clone();
+        }
+        Ok(Ok(()))
+    }
+
+    /// The same entries chained under this log's key, e.g. after a master salt change
+    pub fn rechain(&self, entries: &[AuditEntry]) -> io::Result<Vec<AuditEntry>> {
+        let mut prev = GENESIS.to_string();
+        let mut chained = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let record = AuditRecord {
+                prev,
+                ..entry.record.clone()
+            };
+            let hash = record.hash(&self.chain_key)?;
+            prev = hash.clone();
+            chained.push(AuditEntry { record, hash });
+        }
+        Ok(chained)
+    }
+
+    fn render(&self, entry: &AuditEntry) -> io::Result<String> {
+        let json =
+            serde_json::to_string(entry).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        match &self.key {
+            Some(key) => StorageCipher::encrypt_with_key(key, json.as_bytes())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+            None => Ok(json),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+
+    #[test]
+    fn test_chain_detects_tampering() {
+        let path = std::env::temp_dir().join("saltpass_test_audit.log");
+        let _ = fs::remove_file(&path);
+
+        let log = AuditLog::new(path.clone(), None, derive_key("salt"));
+        log.append(AuditAction::Add, &test_feature("GitHub"))
+            .unwrap();
+        log.append(AuditAction::Add, &test_feature("Mail")).unwrap();
+        log.append(AuditAction::Delete, &test_feature("GitHub"))
+            .unwrap();
+        let entries = log.read().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].record.action, AuditAction::Delete);
+        assert_eq!(log.verify(&entries).unwrap(), Ok(()));
+
+        let mut edited = entries.clone();
+        edited[1].record.name = "Bank".to_string();
+        assert_eq!(log.verify(&edited).unwrap(), Err(1));
+
+        let mut removed = entries.clone();
+        removed.remove(0);
+        assert_eq!(log.verify(&removed).unwrap(), Err(0));
+
+        // Recomputing the chain takes the salt
+        let forger = AuditLog::new(path.clone(), None, derive_key("guess"));
+        let forged = forger.rechain(&edited).unwrap();
+        assert_eq!(forger.verify(&forged).unwrap(), Ok(()));
+        assert_eq!(log.verify(&forged).unwrap(), Err(0));
+        assert_eq!(log.verify(&log.rechain(&forged).unwrap()).unwrap(), Ok(()));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_records() {
+        let path = std::env::temp_dir().join("saltpass_test_audit_enc.log");
+        let _ = fs::remove_file(&path);
+
+        let chain_key = derive_key("salt");
+        let log = AuditLog::new(
+            path.clone(),
+            Some(StorageCipher::derive_key("salt")),
+            chain_key.clone(),
+        );
+        log.append(AuditAction::Add, &test_feature("GitHub"))
+            .unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("GitHub"));
+        assert_eq!(log.read().unwrap()[0].record.name, "GitHub");
+        assert!(AuditLog::new(path.clone(), None, chain_key).read().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
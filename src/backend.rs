@@ -0,0 +1,90 @@
+//! Storage backend abstraction
+//!
+//! A backend persists a [`FeatureStore`]: [`crate::file::FileBackend`] in a single file,
+//! [`crate::sqlite::SqliteBackend`] in a database. Besides loading and saving the whole
+//! store, backends give access to single features by ID, or looked up by name and
+//! identifier, which backends with row storage serve without touching the other features.
+//! [`crate::storage::Storage`] picks the backend from the store format and adds what is
+//! kept next to the store, such as the audit log.
+
+use crate::crypto::StorageKey;
+use crate::models::{Feature, FeatureStore};
+use std::io::{self, ErrorKind};
+
+pub trait StorageBackend {
+    /// Load the whole store
+    fn load(&self) -> io::Result<FeatureStore>;
+
+    /// Load the whole store without checking its signature, to re-sign it after manual
+    /// edits
+    ///
+    /// If the store does not match its signature, writes fail with
+    /// [`crate::storage::SignatureMismatch`] until it is re-signed with
+    /// [`StorageBackend::resign`].
+    fn load_unverified(&self) -> io::Result<FeatureStore> {
+        self.load()
+    }
+
+    /// Replace the whole store
+    ///
+    /// Fails with [`crate::storage::StoreChanged`] if the store was modified by someone
+    /// else since it was last loaded or saved through this backend.
+    fn save(&self, store: &FeatureStore) -> io::Result<()>;
+
+    /// Apply a change to the whole store on top of its current content
+    ///
+    /// Concurrent changes are kept as with [`StorageBackend::put`].
+    fn update(&self, change: &mut dyn FnMut(&mut FeatureStore)) -> io::Result<()>;
+
+    /// Check whether the store changed since it was last loaded or saved
+    fn has_changed(&self) -> io::Result<bool>;
+
+    /// Current content of the store, without updating what this backend has seen
+    fn current(&self) -> io::Result<FeatureStore>;
+
+    /// Look up a single feature by name and identifier
+    fn get(&self, name: &str, feature: &str) -> io::Result<Option<Feature>>;
+
+    /// Insert a feature, or replace the feature with the same ID, or else the one with
+    /// the same name and identifier
+    ///
+    /// Unlike [`StorageBackend::save`] this applies on top of the current content of the
+    /// store, keeping concurrent changes to other features.
+    fn put(&self, feature: &Feature) -> io::Result<()>;
+
+    /// Delete the feature with the given ID, returning `false` if it did not exist
+    fn delete(&self, id: &str) -> io::Result<bool>;
+
+    /// Encrypt the store under a new password; `key` is derived from it with
+    /// [`crate::crypto::StorageCipher::derive_key`]
+    ///
+    /// Fails with [`crate::storage::StoreChanged`] like [`StorageBackend::save`].
+    fn reencrypt(&mut self, password: &str, key: &StorageKey) -> io::Result<()>;
+
+    /// Compact the store, returning the number of records before and after
+    fn compact(&self) -> io::Result<(usize, usize)> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Only operation log stores can be compacted",
+        ))
+    }
+
+    /// Whether the store was loaded despite a bad signature and not re-signed since
+    fn is_tampered(&self) -> bool {
+        false
+    }
+
+    /// Sign the store and save it, accepting its current content
+    fn resign(&self, _store: &FeatureStore) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "Only plain TOML and JSON stores are signed",
+        ))
+    }
+
+    /// The store as TOML, for viewing
+    fn export_toml(&self) -> io::Result<String> {
+        toml::to_string_pretty(&self.current()?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
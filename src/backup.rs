@@ -0,0 +1,407 @@
+//! Portable backup archives
+//!
+//! An archive bundles the store file exactly as it is on disk (so an encrypted store
+//! stays encrypted under the master salt), its format and encryption mode, its audit log
+//! and salt change progress, and local backup copies of the file (`<file>.bak*`). The
+//! bundle is encrypted with AES-256-GCM under a key derived from a passphrase; the
+//! unencrypted header (format name, version and key derivation settings) is
+//! authenticated as associated data, so any modification is detected on import.
+//!
+//! ```json
+//! {"format":"saltpass-backup","version":1,
+//!  "kdf":{"algorithm":"pbkdf2-sha256","iterations":600000,"salt":"..."},
+//!  "nonce":"...","ciphertext":"..."}
+//! ```
+
+use crate::models::{Feature, FeatureStore};
+use crate::storage::{Storage, StorageFormat};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Identifies SaltPass backup archives
+const MAGIC: &str = "saltpass-backup";
+
+/// Archive version written by this build
+pub const VERSION: u32 = 1;
+
+/// PBKDF2 iterations for new archives; passphrases are often weaker than master salts
+const KDF_ITERATIONS: u32 = 600_000;
+
+/// Sidecar files carried along with the store
+const SIDECARS: [&str; 2] = ["audit", "salt-change"];
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn encode(bytes: &[u8]) -> String {
+    general_purpose::STANDARD.encode(bytes)
+}
+
+fn decode(data: &str) -> io::Result<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| invalid(format!("Invalid backup archive: {}", e)))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Kdf {
+    algorithm: String,
+    iterations: u32,
+    salt: String,
+}
+
+/// The authenticated, unencrypted part of an archive
+#[derive(Serialize)]
+struct Header<'a> {
+    format: &'a str,
+    version: u32,
+    kdf: &'a Kdf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    version: u32,
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Envelope {
+    fn header(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(&Header {
+            format: &self.format,
+            version: self.version,
+            kdf: &self.kdf,
+        })
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> io::Result<Zeroizing<[u8; 32]>> {
+    use pbkdf2::pbkdf2_hmac;
+
+    if kdf.algorithm != "pbkdf2-sha256" || !(1..=10_000_000).contains(&kdf.iterations) {
+        return Err(invalid(format!(
+            "Unsupported key derivation in backup: {} ({} iterations)",
+            kdf.algorithm, kdf.iterations
+        )));
+    }
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(
+        passphrase.as_bytes(),
+        &decode(&kdf.salt)?,
+        kdf.iterations,
+        key.as_mut(),
+    );
+    Ok(key)
+}
+
+/// Decrypted content of an archive, files are base64-encoded
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub created: DateTime<Utc>,
+    pub saltpass_version: String,
+    pub file_name: String,
+    pub format: StorageFormat,
+    pub encrypted: bool,
+    store: String,
+    /// Sidecar extension → content
+    sidecars: BTreeMap<String, String>,
+    /// File name → content
+    pub backups: BTreeMap<String, String>,
+}
+
+impl Backup {
+    /// Collect a store and the files that belong to it
+    pub fn collect(storage: &Storage) -> io::Result<Self> {
+        let path = storage.file_path();
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| invalid("Store path has no file name"))?;
+
+        let mut sidecars = BTreeMap::new();
+        for extension in SIDECARS {
+            match fs::read(storage.sidecar(extension)) {
+                Ok(content) => {
+                    sidecars.insert(extension.to_string(), encode(&content));
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut backups = BTreeMap::new();
+        let prefix = format!("{}.bak", file_name);
+        if let Some(dir) = path.parent().filter(|d| d.is_dir()) {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with(&prefix) && entry.file_type()?.is_file() {
+                    backups.insert(name, encode(&fs::read(entry.path())?));
+                }
+            }
+        }
+
+        Ok(Self {
+            created: Utc::now(),
+            saltpass_version: env!("CARGO_PKG_VERSION").to_string(),
+            file_name,
+            format: storage.format(),
+            encrypted: storage.is_encrypted(),
+            store: encode(&fs::read(path)?),
+            sidecars,
+            backups,
+        })
+    }
+
+    /// Encrypt the backup with a passphrase into an archive
+    pub fn seal(&self, passphrase: &str) -> io::Result<String> {
+        self.seal_with(passphrase, KDF_ITERATIONS)
+    }
+
+    fn seal_with(&self, passphrase: &str, iterations: u32) -> io::Result<String> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let kdf = Kdf {
+            algorithm: "pbkdf2-sha256".to_string(),
+            iterations,
+            salt: encode(&salt),
+        };
+        let key = derive_key(passphrase, &kdf)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+        let mut envelope = Envelope {
+            format: MAGIC.to_string(),
+            version: VERSION,
+            kdf,
+            nonce: encode(&nonce),
+            ciphertext: String::new(),
+        };
+        let plaintext = Zeroizing::new(
+            serde_json::to_vec(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+        );
+        let ciphertext = Aes256Gcm::new(key.as_ref().into())
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &envelope.header()?,
+                },
+            )
+            .map_err(|e| invalid(format!("Encryption failed: {}", e)))?;
+        envelope.ciphertext = encode(&ciphertext);
+
+        serde_json::to_string_pretty(&envelope)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Check and decrypt an archive
+    pub fn open(archive: &str, passphrase: &str) -> io::Result<Self> {
+        let envelope: Envelope =
+            serde_json::from_str(archive).map_err(|_| invalid("Not a SaltPass backup archive"))?;
+        if envelope.format != MAGIC {
+            return Err(invalid("Not a SaltPass backup archive"));
+        }
+        if envelope.version > VERSION {
+            return Err(invalid(format!(
+                "Backup archive version {} was written by a newer SaltPass (this one reads up \
+                 to version {})",
+                envelope.version, VERSION
+            )));
+        }
+
+        let key = derive_key(passphrase, &envelope.kdf)?;
+        let nonce = decode(&envelope.nonce)?;
+        if nonce.len() != 12 {
+            return Err(invalid("Invalid backup archive: bad nonce"));
+        }
+        let plaintext = Aes256Gcm::new(key.as_ref().into())
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &decode(&envelope.ciphertext)?,
+                    aad: &envelope.header()?,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| invalid("Wrong passphrase, or the backup archive was modified"))?;
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| invalid(format!("Invalid backup archive content: {}", e)))
+    }
+
+    /// Number of sidecar files and local backups in the archive
+    pub fn extra_files(&self) -> usize {
+        self.sidecars.len() + self.backups.len()
+    }
+
+    /// Write the store file to `path`, its sidecars next to it, and the local backups
+    /// that do not exist yet into the same directory
+    pub fn restore(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, decode(&self.store)?)?;
+
+        let storage = Storage::new(path.to_path_buf(), self.format, self.encrypted);
+        for (extension, content) in &self.sidecars {
+            if SIDECARS.contains(&extension.as_str()) {
+                fs::write(storage.sidecar(extension), decode(content)?)?;
+            }
+        }
+        self.restore_backups(path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Write the local backups that do not exist yet into `dir`
+    pub fn restore_backups(&self, dir: &Path) -> io::Result<()> {
+        for (name, content) in &self.backups {
+            // Only plain file names, never paths
+            if Path::new(name).file_name().map(|n| n.to_string_lossy()) != Some(name.into()) {
+                continue;
+            }
+            let target = dir.join(name);
+            if !target.exists() {
+                fs::write(target, decode(content)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the store file to `path` without its sidecars, to read it with [`Storage`]
+    pub fn extract_store(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, decode(&self.store)?)
+    }
+}
+
+/// Differences between the current store and the one in a backup
+#[derive(Debug, Default)]
+pub struct Preview {
+    /// Only in the backup
+    pub added: Vec<Feature>,
+    /// Only in the current store
+    pub removed: Vec<Feature>,
+    /// In both with different content: (current, backup)
+    pub changed: Vec<(Feature, Feature)>,
+}
+
+impl Preview {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare features by ID, so a renamed feature is reported as changed
+pub fn preview(current: &FeatureStore, backup: &FeatureStore) -> io::Result<Preview> {
+    let same = |a: &Feature, b: &Feature| -> io::Result<bool> {
+        let value = |f: &Feature| {
+            serde_json::to_value(f).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+        };
+        Ok(value(a)? == value(b)?)
+    };
+    let find = |store: &FeatureStore, f: &Feature| -> Option<Feature> {
+        store.features.iter().find(|o| o.id == f.id).cloned()
+    };
+
+    let mut preview = Preview::default();
+    for feature in &backup.features {
+        match find(current, feature) {
+            None => preview.added.push(feature.clone()),
+            Some(existing) if !same(&existing, feature)? => {
+                preview.changed.push((existing, feature.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for feature in &current.features {
+        if find(backup, feature).is_none() {
+            preview.removed.push(feature.clone());
+        }
+    }
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+
+    fn feature(name: &str, hint: Option<&str>) -> Feature {
+        Feature {
+            hint: hint.map(str::to_string),
+            ..test_feature(name)
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip_and_tampering() {
+        let dir = std::env::temp_dir().join("saltpass_test_backup");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let storage = Storage::new(dir.join("features.toml"), StorageFormat::Toml, false);
+        let mut store = FeatureStore::new();
+        store.add_feature(feature("GitHub", None)).unwrap();
+        storage.save(&store).unwrap();
+        fs::write(dir.join("features.toml.bak"), "old copy").unwrap();
+        fs::write(storage.sidecar("audit"), "{}\n").unwrap();
+
+        let backup = Backup::collect(&storage).unwrap();
+        assert_eq!(backup.extra_files(), 2);
+        let archive = backup.seal_with("passphrase", 1000).unwrap();
+        assert!(!archive.contains("github.com"));
+
+        let opened = Backup::open(&archive, "passphrase").unwrap();
+        assert_eq!(opened.file_name, "features.toml");
+        assert!(Backup::open(&archive, "wrong").is_err());
+
+        // The header is authenticated
+        let downgraded = archive.replace("\"iterations\": 1000", "\"iterations\": 1001");
+        assert_ne!(downgraded, archive);
+        assert!(Backup::open(&downgraded, "passphrase").is_err());
+
+        let target = dir.join("restored").join("features.toml");
+        opened.restore(&target).unwrap();
+        let restored = Storage::open(target.clone()).unwrap();
+        assert_eq!(restored.load().unwrap().features[0].name, "GitHub");
+        assert!(restored.sidecar("audit").exists());
+        assert!(target.with_file_name("features.toml.bak").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut current = FeatureStore::new();
+        current.add_feature(feature("GitHub", None)).unwrap();
+        current.add_feature(feature("Mail", None)).unwrap();
+        current.add_feature(feature("Forum", None)).unwrap();
+        let mut backup = current.clone();
+        backup.features[0].hint = Some("work".to_string());
+        backup.features[2].name = "Old forum".to_string();
+        let mail = backup.features[1].id.clone();
+        backup.delete_feature(&mail);
+        backup.add_feature(feature("Bank", None)).unwrap();
+
+        let preview = preview(&current, &backup).unwrap();
+        assert_eq!(preview.added.len(), 1);
+        assert_eq!(preview.added[0].name, "Bank");
+        assert_eq!(preview.removed.len(), 1);
+        assert_eq!(preview.removed[0].name, "Mail");
+        assert_eq!(preview.changed.len(), 2);
+        assert_eq!(preview.changed[0].1.hint.as_deref(), Some("work"));
+        assert_eq!(preview.changed[1].0.name, "Forum");
+        assert_eq!(preview.changed[1].1.name, "Old forum");
+    }
+}
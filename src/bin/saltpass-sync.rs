@@ -0,0 +1,66 @@
+//! saltpass-sync - sync server for SaltPass stores
+//!
+//! Stores end-to-end encrypted blobs with revision numbers for SaltPass clients on
+//! localhost or the LAN. The server never receives plaintext or keys.
+
+#[path = "../sync_server.rs"]
+mod sync_server;
+
+use std::path::PathBuf;
+use std::process;
+use sync_server::SyncServer;
+
+const USAGE: &str = "\
+Usage: saltpass-sync [--listen <addr>] [--data <dir>]
+
+Options:
+  -l, --listen <addr>   Address to listen on (default: 127.0.0.1:7878)
+  -d, --data <dir>      Directory holding the vaults (default: ~/.saltpass-sync)
+  -h, --help            Show this help";
+
+fn main() {
+    let mut listen = "127.0.0.1:7878".to_string();
+    let mut data = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().unwrap_or_else(|| {
+                eprintln!("❌ Error: Missing value after '{}'\n\n{}", arg, USAGE);
+                process::exit(2);
+            })
+        };
+        match arg.as_str() {
+            "-l" | "--listen" => listen = value(),
+            "-d" | "--data" => data = Some(PathBuf::from(value())),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => {
+                eprintln!("❌ Error: Unknown argument '{}'\n\n{}", arg, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    let data = data.unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".saltpass-sync")
+    });
+
+    let server = match SyncServer::bind(&listen, &data) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("❌ Error: Cannot listen on {}: {}", listen, e);
+            process::exit(1);
+        }
+    };
+    let addr = server
+        .local_addr()
+        .map_or(listen.clone(), |addr| addr.to_string());
+    println!("🔄 saltpass-sync listening on http://{}", addr);
+    println!("📁 Vaults: {}", data.display());
+    server.run();
+}
@@ -2,55 +2,746 @@
 //!
 //! This module provides an interactive CLI for managing features and generating passwords.
 
-use crate::crypto::{Algorithm, PasswordGenerator};
-use crate::models::{Feature, FeatureStore, Salt};
-use crate::storage::{Storage, StorageFormat};
+use crate::args::ProfileCommand;
+use crate::audit::{self, AuditAction};
+use crate::backup::{self, Backup};
+use crate::config::{Config, TamperPolicy};
+use crate::crypto::{Algorithm, SaltVerifier};
+use crate::domain::{self, EquivalentDomains, Normalization};
+use crate::filter::{self, Filter, TreeLine};
+use crate::fuzzy;
+use crate::kit::{self, KitOptions};
+use crate::merge::{self, Conflict, Resolution};
+use crate::models::{
+    CustomField, Feature, FeatureStore, FieldKind, LengthChoice, Salt, TrashedFeature,
+};
+use crate::profiles::{DEFAULT_PROFILE, Profiles};
+use crate::rotation::{self, Status};
+use crate::salt_change::{MigrationStatus, SaltChange};
+use crate::signature;
+use crate::storage::{self, Storage, StorageFormat};
+use crate::sync::{self, SyncClient};
 use arboard::Clipboard;
-use dialoguer::{Input, Select, theme::ColorfulTheme};
+use chrono::Utc;
+use console::{Key, Term, style};
+use dialoguer::{Confirm, Input, Password, Select, theme::ColorfulTheme};
 use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// How many results the feature picker shows at once
+const PICKER_ROWS: usize = 8;
+
+/// A mutation made in this session, kept for "Undo Last Change"
+///
+/// Purging is permanent and cannot be undone.
+#[derive(Clone)]
+enum Change {
+    /// A feature was added, replacing `previous` if it existed
+    Put {
+        previous: Option<Feature>,
+        feature: Feature,
+    },
+    /// A feature was edited in place
+    Edit { previous: Feature, feature: Feature },
+    /// A feature was moved to the trash
+    Trash(TrashedFeature),
+    /// A feature was restored from the trash, replacing `replaced` if it existed
+    Restore {
+        entry: TrashedFeature,
+        replaced: Option<Feature>,
+    },
+}
+
+impl Change {
+    /// Revert this change on `store`
+    fn revert(self, store: &mut FeatureStore) {
+        match self {
+            Change::Put {
+                previous: Some(previous),
+                ..
+            } => store.put_feature(previous),
+            Change::Put {
+                previous: None,
+                feature,
+            } => {
+                store.delete_feature(&feature.id);
+            }
+            Change::Edit { previous, feature } => {
+                let _ = store.update(&feature.id, previous);
+            }
+            Change::Trash(entry) => {
+                store.restore_feature(&entry);
+            }
+            Change::Restore { entry, replaced } => {
+                store.delete_feature(&entry.feature.id);
+                store.trash.push(entry);
+                store.trash.sort_by_key(|t| t.deleted);
+                if let Some(replaced) = replaced {
+                    store.put_feature(replaced);
+                }
+            }
+        }
+    }
+
+    /// How reverting this change is recorded in the audit log
+    fn revert_action(&self) -> (AuditAction, &Feature) {
+        match self {
+            Change::Put {
+                previous: Some(previous),
+                ..
+            } => (AuditAction::Add, previous),
+            Change::Put { feature, .. } => (AuditAction::Delete, feature),
+            Change::Edit { previous, .. } => (AuditAction::Edit, previous),
+            Change::Trash(entry) => (AuditAction::Restore, &entry.feature),
+            Change::Restore { entry, .. } => (AuditAction::Delete, &entry.feature),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Change::Put { feature, .. } => format!("adding '{}'", feature.name),
+            Change::Edit { feature, .. } => format!("editing '{}'", feature.name),
+            Change::Trash(entry) => format!("deleting '{}'", entry.feature.name),
+            Change::Restore { entry, .. } => format!("restoring '{}'", entry.feature.name),
+        }
+    }
+}
+
+/// A master salt change continued in this session
+struct Migration {
+    progress: SaltChange,
+    new_salt: Salt,
+}
 
 /// Command-line interface handler
 pub struct Cli {
+    config: Config,
+    profile: Option<String>,
     storage: Storage,
     store: FeatureStore,
     salt: Option<Salt>,
+    last_change: Option<Change>,
+    migration: Option<Migration>,
 }
 
 impl Cli {
-    pub fn new() -> io::Result<Self> {
-        // Ask for preferences first
-        let should_encrypt = Self::ask_encryption_preference()?;
-        let format = Self::ask_format_preference()?;
+    /// Start a session on the given profile, or the default store if `None`
+    ///
+    /// When named profiles exist and none was given, the user picks one.
+    pub fn new(profile: Option<String>) -> io::Result<Self> {
+        let config = Config::load()?;
+        let profiles = Profiles::load()?;
+
+        let profile = match profile {
+            Some(name) => Some(name),
+            None if !profiles.is_empty() => Self::ask_profile(&profiles)?,
+            None => None,
+        };
+        let selected = match &profile {
+            Some(name) => Some(profiles.get(name)?.clone()),
+            None => None,
+        };
 
-        // Get file path
-        let file_path = Storage::default_path(format, should_encrypt)?;
-        let mut storage = Storage::new(file_path, format, should_encrypt);
+        let mut storage = match &selected {
+            Some(p) => p.storage(),
+            None => Self::open_storage(&config)?,
+        };
 
         // Ask for salt
         let salt = Self::ask_salt_before_init()?;
 
+        if let Some(verifier) = selected.as_ref().and_then(|p| p.salt_verifier.as_ref())
+            && !SaltVerifier::matches(&salt, verifier)
+        {
+            println!("⚠️  This salt does not match the salt verifier of this profile.");
+            let proceed = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Continue anyway? (passwords will differ from before)")
+                .default(false)
+                .interact()
+                .map_err(io::Error::other)?;
+            if !proceed {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Salt does not match the profile's verifier",
+                ));
+            }
+        }
+
         // Set password if encrypted and load store
-        if should_encrypt {
+        if storage.is_encrypted() {
             storage.set_password(salt.clone());
         }
+        if storage.supports_signature() {
+            storage.set_signature_key(signature::derive_key(&salt), config.storage.sign);
+        }
+        storage.set_audit_key(audit::derive_key(&salt));
 
-        let store = storage.load()?;
+        let store = match storage.load() {
+            Ok(store) => store,
+            Err(e) if storage::is_signature_mismatch(&e) => {
+                Self::handle_tampering(&storage, config.storage.on_tamper, e)?
+            }
+            Err(e) => return Err(e),
+        };
 
         Ok(Self {
+            config,
+            profile,
             storage,
             store,
             salt: Some(Salt::new(salt)),
+            last_change: None,
+            migration: None,
+        })
+    }
+
+    /// Deal with a store that does not match its signature according to the policy
+    fn handle_tampering(
+        storage: &Storage,
+        policy: TamperPolicy,
+        err: io::Error,
+    ) -> io::Result<FeatureStore> {
+        if let TamperPolicy::Warn = policy {
+            println!("⚠️  {}", err);
+            println!(
+                "⚠️  Check your features before using them. Changes cannot be saved until the \
+                 store is re-signed (\"Re-sign Store\")."
+            );
+            return storage.load_unverified();
+        }
+
+        println!("❌ {}", err);
+        let resign = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Re-sign it? Only do this if you edited the file yourself.")
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !resign {
+            return Err(err);
+        }
+        let store = storage.load_unverified()?;
+        storage.resign(&store)?;
+        println!("✅ Store re-signed.");
+        Ok(store)
+    }
+
+    /// Open the existing store, only asking for preferences when there is none yet
+    ///
+    /// Preferences set in the config file are used instead of prompting.
+    fn open_storage(config: &Config) -> io::Result<Storage> {
+        let new_store = |path: Option<PathBuf>| -> io::Result<Storage> {
+            let should_encrypt = match config.storage.encrypted {
+                Some(encrypted) => encrypted,
+                None => Self::ask_encryption_preference()?,
+            };
+            let format = match config.storage.format {
+                Some(format) => format,
+                None => Self::ask_format_preference()?,
+            };
+            let file_path = match path {
+                Some(path) => path,
+                None => Storage::default_path(format, should_encrypt)?,
+            };
+            Ok(Storage::new(file_path, format, should_encrypt))
+        };
+
+        if let Some(path) = &config.storage.path {
+            return if path.exists() {
+                Storage::open(path.clone())
+            } else {
+                new_store(Some(path.clone()))
+            };
+        }
+
+        let candidates = Storage::discover(&Storage::config_dir()?)?;
+
+        match candidates.as_slice() {
+            [] => new_store(None),
+            [path] => Storage::open(path.clone()),
+            _ => {
+                println!("⚠️  WARNING: Found more than one features file:");
+                for path in &candidates {
+                    println!("   - {}", path.display());
+                }
+                println!("   Only one of them is used per session; features stored in the");
+                println!("   others will NOT be shown. Consider keeping a single file.");
+                println!();
+
+                let items: Vec<String> =
+                    candidates.iter().map(|p| p.display().to_string()).collect();
+                let selection = Select::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Which features file should be used?")
+                    .items(&items)
+                    .default(0)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                Storage::open(candidates[selection].clone())
+            }
+        }
+    }
+
+    /// Let the user pick a profile, `None` being the default store
+    fn ask_profile(profiles: &Profiles) -> io::Result<Option<String>> {
+        let mut items = vec![format!("{} (~/.saltpass)", DEFAULT_PROFILE)];
+        items.extend(profiles.profiles.keys().cloned());
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Choose a profile")
+            .items(&items)
+            .default(0)
+            .interact()
+            .map_err(io::Error::other)?;
+
+        Ok(selection
+            .checked_sub(1)
+            .and_then(|index| profiles.profiles.keys().nth(index))
+            .cloned())
+    }
+
+    /// Handle the `profile` subcommands
+    pub fn manage_profiles(command: ProfileCommand) -> io::Result<()> {
+        let mut profiles = Profiles::load()?;
+
+        match command {
+            ProfileCommand::List => {
+                println!("📂 Profiles:");
+                println!("   {} (~/.saltpass)", DEFAULT_PROFILE);
+                for (name, profile) in &profiles.profiles {
+                    println!(
+                        "   {} - {} ({}, {}{})",
+                        name,
+                        profile.path.display(),
+                        profile.format.extension().to_uppercase(),
+                        if profile.encrypted {
+                            "encrypted"
+                        } else {
+                            "plain text"
+                        },
+                        if profile.salt_verifier.is_some() {
+                            ", salt verifier"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            ProfileCommand::Create(name) => {
+                let encrypted = Self::ask_encryption_preference()?;
+                let format = Self::ask_format_preference()?;
+
+                let use_verifier = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Store a salt verifier to catch mistyped salts?")
+                    .default(true)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                let verifier = if use_verifier {
+                    let salt = Zeroizing::new(Self::ask_salt_before_init()?);
+                    let again = Zeroizing::new(Self::read_secret("🔑 Confirm master salt: ")?);
+                    if salt != again {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "Salts do not match",
+                        ));
+                    }
+                    Some(SaltVerifier::compute(&salt))
+                } else {
+                    None
+                };
+
+                let path = profiles
+                    .create(&name, format, encrypted, verifier)?
+                    .path
+                    .clone();
+                profiles.save()?;
+                println!("✅ Profile '{}' created ({})", name, path.display());
+            }
+            ProfileCommand::Rename(old, new) => {
+                profiles.rename(&old, &new)?;
+                profiles.save()?;
+                println!("✅ Profile '{}' renamed to '{}'", old, new);
+            }
+            ProfileCommand::Delete(name) => {
+                let storage = profiles.get(&name)?.storage();
+                let path = storage.file_path().to_path_buf();
+                let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!("Delete profile '{}'?", name))
+                    .default(false)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                if !confirmed {
+                    return Ok(());
+                }
+
+                let delete_file = path.exists()
+                    && Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt(format!("Also delete {}?", path.display()))
+                        .default(false)
+                        .interact()
+                        .map_err(io::Error::other)?;
+
+                profiles.remove(&name)?;
+                profiles.save()?;
+                if delete_file {
+                    std::fs::remove_file(&path)?;
+                    for sidecar in ["lock", "sync", "audit", "salt-change"] {
+                        let _ = std::fs::remove_file(storage.sidecar(sidecar));
+                    }
+                    if let Some(dir) = path.parent() {
+                        // Only succeeds if nothing else is left in the profile directory
+                        let _ = std::fs::remove_dir(dir);
+                    }
+                }
+                println!("🗑️  Profile '{}' deleted", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store selected by `--profile`, or the default store, if its file exists
+    fn existing_storage(profile: Option<&str>, config: &Config) -> io::Result<Option<Storage>> {
+        if let Some(name) = profile {
+            let storage = Profiles::load()?.get(name)?.storage();
+            return Ok(storage.file_path().exists().then_some(storage));
+        }
+        if let Some(path) = &config.storage.path {
+            return if path.exists() {
+                Storage::open(path.clone()).map(Some)
+            } else {
+                Ok(None)
+            };
+        }
+        if Storage::discover(&Storage::config_dir()?)?.is_empty() {
+            return Ok(None);
+        }
+        Self::open_storage(config).map(Some)
+    }
+
+    /// Write the selected store, with its audit log and local backups, to an archive
+    /// encrypted with a passphrase
+    pub fn export_backup(profile: Option<&str>, path: &Path) -> io::Result<()> {
+        let config = Config::load()?;
+        let storage = Self::existing_storage(profile, &config)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "There is no features file to back up",
+            )
+        })?;
+        let backup = Backup::collect(&storage)?;
+
+        println!("🔐 The archive is encrypted with a passphrase of its own.");
+        let passphrase = Zeroizing::new(Self::read_secret("🔑 Backup passphrase: ")?);
+        let repeated = Zeroizing::new(Self::read_secret("🔑 Repeat the passphrase: ")?);
+        if passphrase != repeated {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The passphrases do not match",
+            ));
+        }
+
+        std::fs::write(path, backup.seal(&passphrase)?)?;
+        println!(
+            "✅ Backed up {} ({}, {}) with {} extra file(s) to {}",
+            storage.file_path().display(),
+            backup.format.extension().to_uppercase(),
+            if backup.encrypted {
+                "encrypted"
+            } else {
+                "plain text"
+            },
+            backup.extra_files(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Restore a backup archive, or merge it into / replace the existing store
+    pub fn import_backup(profile: Option<&str>, path: &Path) -> io::Result<()> {
+        let archive = std::fs::read_to_string(path)?;
+        let passphrase = Zeroizing::new(Self::read_secret("🔑 Backup passphrase: ")?);
+        let backup = Backup::open(&archive, &passphrase)?;
+        println!(
+            "📦 Backup of {} from {} (SaltPass {}, {}, {})",
+            backup.file_name,
+            backup.created.format("%Y-%m-%d %H:%M:%S"),
+            backup.saltpass_version,
+            backup.format.extension().to_uppercase(),
+            if backup.encrypted {
+                "encrypted"
+            } else {
+                "plain text"
+            }
+        );
+
+        let config = Config::load()?;
+        let Some(mut storage) = Self::existing_storage(profile, &config)? else {
+            let target = match profile {
+                Some(name) => {
+                    let profiles = Profiles::load()?;
+                    let selected = profiles.get(name)?;
+                    if selected.format.extension() != backup.format.extension()
+                        || selected.encrypted != backup.encrypted
+                    {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Profile '{}' uses a different format or encryption mode than \
+                                 the backup",
+                                name
+                            ),
+                        ));
+                    }
+                    selected.path.clone()
+                }
+                None => match &config.storage.path {
+                    Some(path) => path.clone(),
+                    None => Storage::config_dir()?.join(&backup.file_name),
+                },
+            };
+            backup.restore(&target)?;
+            println!("✅ Restored {}", target.display());
+            return Ok(());
+        };
+
+        // Read the backed up store through a temporary copy next to the current one
+        let salt = Zeroizing::new(Self::ask_salt_before_init()?);
+        let tmp_path = storage.sidecar("import");
+        backup.extract_store(&tmp_path)?;
+        let incoming = {
+            let mut incoming = Storage::new(tmp_path.clone(), backup.format, backup.encrypted);
+            if backup.encrypted {
+                incoming.set_password(salt.to_string());
+            }
+            incoming.load()
+        };
+        let _ = std::fs::remove_file(&tmp_path);
+        let incoming = incoming.map_err(|e| {
+            io::Error::new(e.kind(), format!("Cannot read the backed up store: {}", e))
+        })?;
+
+        if storage.is_encrypted() {
+            storage.set_password(salt.to_string());
+        }
+        if storage.supports_signature() {
+            storage.set_signature_key(signature::derive_key(&salt), config.storage.sign);
+        }
+        storage.set_audit_key(audit::derive_key(&salt));
+        let current = storage.load()?;
+
+        let preview = backup::preview(&current, &incoming)?;
+        if preview.is_empty() {
+            println!("✅ The store already matches the backup.");
+        } else {
+            println!("\n📋 Differences from the current store:");
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+            for f in &preview.added {
+                println!("➕ {} ({})  only in the backup", f.name, f.feature);
+            }
+            for (f, backed_up) in &preview.changed {
+                if (&f.name, &f.feature) == (&backed_up.name, &backed_up.feature) {
+                    println!("✏️  {} ({})  different in the backup", f.name, f.feature);
+                } else {
+                    println!(
+                        "✏️  {} ({})  renamed to {} ({}) in the backup",
+                        f.name, f.feature, backed_up.name, backed_up.feature
+                    );
+                }
+            }
+            for f in &preview.removed {
+                println!("➖ {} ({})  only in the current store", f.name, f.feature);
+            }
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+
+            let choice = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("How should the backup be imported?")
+                .items(&[
+                    "Merge (keep both, resolve differences)",
+                    "Replace the current features with the backup",
+                    "Cancel",
+                ])
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+            let result = match choice {
+                0 => merge::three_way_merge(&FeatureStore::new(), &current, &incoming)?
+                    .resolve(Self::ask_resolution)?,
+                1 => incoming,
+                _ => return Ok(()),
+            };
+            storage.save(&result)?;
+
+            let applied = backup::preview(&current, &result)?;
+            let records = applied
+                .added
+                .iter()
+                .map(|f| (AuditAction::Add, f))
+                .chain(applied.changed.iter().map(|(_, f)| (AuditAction::Edit, f)))
+                .chain(applied.removed.iter().map(|f| (AuditAction::Delete, f)));
+            for (action, feature) in records {
+                if let Err(e) = storage.audit(action, feature) {
+                    println!("⚠️  Could not write the audit record: {}", e);
+                }
+            }
+            println!(
+                "✅ Imported: {} added, {} changed, {} removed",
+                applied.added.len(),
+                applied.changed.len(),
+                applied.removed.len()
+            );
+        }
+
+        if let Some(dir) = storage.file_path().parent() {
+            backup.restore_backups(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Three-way merge of `base`, `ours` and `theirs`, resolving conflicts interactively
+    ///
+    /// The files may use any supported format or encryption. The result is written to
+    /// `output`, or to `ours` like a git merge driver.
+    pub fn merge_stores(
+        base: &Path,
+        ours: &Path,
+        theirs: &Path,
+        output: Option<PathBuf>,
+    ) -> io::Result<()> {
+        let mut salt: Option<Zeroizing<String>> = None;
+        let mut unlock = |storage: &mut Storage| -> io::Result<()> {
+            if storage.is_encrypted() {
+                if salt.is_none() {
+                    salt = Some(Zeroizing::new(Self::ask_salt_before_init()?));
+                }
+                storage.set_password(salt.as_deref().cloned().unwrap_or_default());
+            }
+            Ok(())
+        };
+        let mut open = |path: &Path| -> io::Result<(Storage, FeatureStore)> {
+            let mut storage = Storage::open(path.to_path_buf())?;
+            unlock(&mut storage)?;
+            let store = storage
+                .load()
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            Ok((storage, store))
+        };
+
+        let (_, base_store) = open(base)?;
+        let (ours_storage, ours_store) = open(ours)?;
+        let (_, theirs_store) = open(theirs)?;
+
+        let mut output_storage = match output {
+            None => ours_storage,
+            Some(path) if path.exists() => open(&path)?.0,
+            Some(path) => {
+                let mut storage =
+                    Storage::new(path, ours_storage.format(), ours_storage.is_encrypted());
+                unlock(&mut storage)?;
+                storage
+            }
+        };
+
+        let outcome = merge::three_way_merge(&base_store, &ours_store, &theirs_store)?;
+        let conflicts = outcome.conflicts().count();
+        if conflicts > 0 {
+            println!("⚔️  {} conflict(s) need your decision", conflicts);
+        }
+        let merged = outcome.resolve(Self::ask_resolution)?;
+        // Keep signed stores signed, the merge result needs a signature of its own
+        let signed = [&base_store, &ours_store, &theirs_store]
+            .iter()
+            .any(|store| store.signature.is_some());
+        if signed && output_storage.supports_signature() {
+            let salt = match salt {
+                Some(salt) => salt,
+                None => Zeroizing::new(Self::ask_salt_before_init()?),
+            };
+            output_storage.set_signature_key(signature::derive_key(&salt), true);
+        }
+        output_storage.save(&merged)?;
+
+        println!(
+            "✅ Merged {} features into {} ({} conflict(s) resolved)",
+            merged.features.len(),
+            output_storage.file_path().display(),
+            conflicts
+        );
+        Ok(())
+    }
+
+    fn ask_resolution(conflict: &Conflict) -> io::Result<Resolution> {
+        let feature = conflict.feature();
+        println!();
+        println!("⚔️  Conflict: {} ({})", feature.name, feature.feature);
+
+        let items = match conflict {
+            Conflict::Modified {
+                ours,
+                theirs,
+                fields,
+            } => {
+                let ours = serde_json::to_value(ours).map_err(io::Error::other)?;
+                let theirs = serde_json::to_value(theirs).map_err(io::Error::other)?;
+                let show = |value: Option<&serde_json::Value>| {
+                    value.map_or("(unset)".to_string(), |v| v.to_string())
+                };
+                for field in fields {
+                    println!("   {}:", field);
+                    println!("      ours:   {}", show(ours.get(field)));
+                    println!("      theirs: {}", show(theirs.get(field)));
+                }
+                ["Keep ours", "Take theirs"]
+            }
+            Conflict::DeleteModify {
+                modified_by: Resolution::Ours,
+                ..
+            } => {
+                println!("   Modified in ours, deleted in theirs");
+                ["Keep the modified feature (ours)", "Delete it (theirs)"]
+            }
+            Conflict::DeleteModify {
+                modified_by: Resolution::Theirs,
+                ..
+            } => {
+                println!("   Deleted in ours, modified in theirs");
+                [
+                    "Keep it deleted (ours)",
+                    "Keep the modified feature (theirs)",
+                ]
+            }
+        };
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Resolve conflict")
+            .items(&items)
+            .default(0)
+            .interact()
+            .map_err(io::Error::other)?;
+
+        Ok(if selection == 0 {
+            Resolution::Ours
+        } else {
+            Resolution::Theirs
         })
     }
 
     /// Ask for salt before/during initialization
     fn ask_salt_before_init() -> io::Result<String> {
+        Self::read_secret("🔑 Enter your master salt (Tab: Show/Hide): ")
+    }
+
+    /// Read a non-empty secret from the terminal without echoing it
+    fn read_secret(prompt: &str) -> io::Result<String> {
         use std::io::Write;
-        print!("🔑 Enter your master salt (Tab: Show/Hide): ");
+        print!("{}", prompt);
         io::stdout().flush()?;
 
         // Create a temporary Cli instance just to use the password reading method
         let temp_cli = Cli {
+            config: Config::default(),
+            profile: None,
             storage: Storage::new(
                 Storage::default_path(StorageFormat::Toml, false)?,
                 StorageFormat::Toml,
@@ -58,6 +749,8 @@ impl Cli {
             ),
             store: FeatureStore::new(),
             salt: None,
+            last_change: None,
+            migration: None,
         };
 
         let salt = temp_cli.read_password_with_asterisks()?;
@@ -74,7 +767,12 @@ impl Cli {
 
     fn ask_format_preference() -> io::Result<StorageFormat> {
         println!("📁 Choose file format:");
-        let choices = vec!["TOML (Recommended)", "JSON"];
+        let choices = vec![
+            "TOML (Recommended)",
+            "JSON",
+            "Operation log (merges edits from several devices)",
+            "SQLite database (large stores)",
+        ];
         let selection = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Format")
             .items(&choices)
@@ -82,10 +780,11 @@ impl Cli {
             .interact()
             .map_err(io::Error::other)?;
 
-        Ok(if selection == 0 {
-            StorageFormat::Toml
-        } else {
-            StorageFormat::Json
+        Ok(match selection {
+            0 => StorageFormat::Toml,
+            1 => StorageFormat::Json,
+            2 => StorageFormat::Log,
+            _ => StorageFormat::Sqlite,
         })
     }
 
@@ -110,19 +809,67 @@ impl Cli {
 
     pub fn run(&mut self) -> io::Result<()> {
         println!("🔐 Welcome to SaltPass - Deterministic Password Generator");
-        println!("📁 Storage: {}", self.storage.file_path().display());
+        if let Some(profile) = &self.profile {
+            println!("👤 Profile: {}", profile);
+        }
+        println!(
+            "📁 Storage: {} ({}, {})",
+            self.storage.file_path().display(),
+            self.storage.format().extension().to_uppercase(),
+            if self.storage.is_encrypted() {
+                "encrypted"
+            } else {
+                "plain text"
+            }
+        );
         println!("✅ Salt accepted (stored in memory only)");
+        if !self.storage.is_tampered() {
+            self.purge_expired()?;
+        }
+        if let Some(progress) = self.storage.salt_change()? {
+            println!(
+                "🔁 A master salt change is in progress ({} migrated, {} pending, {} skipped). \
+                 Continue it from \"Change Master Salt\".",
+                progress.count(MigrationStatus::Migrated),
+                progress.count(MigrationStatus::Pending),
+                progress.count(MigrationStatus::Skipped)
+            );
+        }
+        self.print_stale_features();
         println!();
 
         loop {
-            let choices = vec![
+            let mut choices = vec![
                 "Generate Password",
+                "Copy Account Details",
                 "Add New Feature",
                 "List All Features",
+                "Edit Feature",
                 "Delete Feature",
+                "Trash",
                 "View Decrypted Content",
-                "Exit",
+                "Print Emergency Kit",
+                "View Audit Log",
+                "Rotation Report",
+                "Change Master Salt",
             ];
+            if self.last_change.is_some() {
+                choices.insert(6, "Undo Last Change");
+            }
+            if let StorageFormat::Log = self.storage.format() {
+                choices.push("Compact Operation Log");
+            }
+            if self.config.sync.url.is_some() {
+                choices.push("Sync with Server");
+            }
+            if self.storage.supports_signature()
+                && (self.config.storage.sign
+                    || self.store.signature.is_some()
+                    || self.storage.is_tampered())
+            {
+                choices.push("Re-sign Store");
+            }
+            choices.push("Exit");
 
             let selection = Select::with_theme(&ColorfulTheme::default())
                 .with_prompt("What would you like to do?")
@@ -131,17 +878,36 @@ impl Cli {
                 .interact()
                 .map_err(io::Error::other)?;
 
-            match selection {
-                0 => self.generate_password()?,
-                1 => self.add_feature()?,
-                2 => self.list_features()?,
-                3 => self.delete_feature()?,
-                4 => self.view_decrypted()?,
-                5 => {
+            let result = match choices[selection] {
+                "Generate Password" => self.generate_password(),
+                "Copy Account Details" => self.copy_details(),
+                "Add New Feature" => self.add_feature(),
+                "List All Features" => self.list_features(),
+                "Edit Feature" => self.edit_feature(),
+                "Delete Feature" => self.delete_feature(),
+                "Undo Last Change" => self.undo(),
+                "Trash" => self.manage_trash(),
+                "View Decrypted Content" => self.view_decrypted(),
+                "Print Emergency Kit" => self.emergency_kit(),
+                "View Audit Log" => self.show_audit(),
+                "Rotation Report" => {
+                    self.rotation_report();
+                    Ok(())
+                }
+                "Change Master Salt" => self.change_salt(),
+                "Compact Operation Log" => self.compact_log(),
+                "Sync with Server" => self.sync(),
+                "Re-sign Store" => self.resign_store(),
+                "Exit" => {
                     println!("👋 Goodbye! Salt cleared from memory.");
                     break;
                 }
                 _ => unreachable!(),
+            };
+            match result {
+                // A store opened despite a bad signature refuses writes until re-signed
+                Err(e) if storage::is_signature_mismatch(&e) => println!("❌ {}", e),
+                result => result?,
             }
 
             println!();
@@ -234,6 +1000,7 @@ impl Cli {
     }
 
     #[cfg(any(unix, windows))]
+    #[allow(clippy::collapsible_match)]
     fn read_password_chars(&self) -> io::Result<String> {
         use std::io::{self, Write};
 
@@ -435,68 +1202,117 @@ impl Cli {
         Ok(password)
     }
 
-    fn generate_password(&self) -> io::Result<()> {
+    fn generate_password(&mut self) -> io::Result<()> {
         if self.store.list_features().is_empty() {
             println!("⚠️  No features found. Please add a feature first.");
             return Ok(());
         }
 
-        let features: Vec<String> = self
-            .store
-            .list_features()
-            .iter()
-            .map(|f| {
-                let algo = format!("[{}]", f.algorithm.name());
-                if let Some(hint) = &f.hint {
-                    format!("{} {} ({}) - {}", algo, f.name, f.feature, hint)
-                } else {
-                    format!("{} {} ({})", algo, f.name, f.feature)
+        let Some(filtered) = self.filter_features()? else {
+            return Ok(());
+        };
+        let Some((feature, once)) = self.pick_feature_with(
+            "Search a feature to generate password",
+            &filtered,
+            Some("override the length once"),
+        )?
+        else {
+            return Ok(());
+        };
+        let feature = feature.clone();
+
+        let length = match feature.length_choice(once, self.config.defaults.length) {
+            LengthChoice::Recorded(length) => length,
+            LengthChoice::Ask { default, remember } => {
+                let length = self.ask_length(default)?;
+                if remember {
+                    self.remember_length(&feature, length)?;
                 }
-            })
-            .collect();
-
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select a feature to generate password")
-            .items(&features)
-            .default(0)
-            .interact()
-            .map_err(io::Error::other)?;
-
-        let feature = &self.store.list_features()[selection];
-        let salt = self.salt.as_ref().unwrap();
-
-        let length_input: String = Input::new()
-            .with_prompt("Password length (12-64)")
-            .default("16".to_string())
-            .interact_text()
-            .map_err(io::Error::other)?;
-
-        let length = length_input.parse::<usize>().unwrap_or(16).clamp(12, 64);
+                length
+            }
+        };
 
-        let password = PasswordGenerator::generate_with_algo(
-            salt.value(),
-            &feature.feature,
-            length,
-            feature.algorithm,
-        );
+        let salt = self.salt_for(&feature);
+        let Some(password) = derive_password(&feature, salt, length) else {
+            return Ok(());
+        };
 
         println!("\n🎯 Generated Password:");
         println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
         println!("Feature: {} ({})", feature.name, feature.feature);
+        if let Some(login) = &feature.login {
+            println!("Login: {}", login);
+        }
+        if let Some(username) = feature
+            .username
+            .as_ref()
+            .filter(|u| feature.login.as_ref() != Some(u))
+        {
+            println!("Username: {}", username);
+        }
         println!("Algorithm: {}", feature.algorithm.name());
-        println!("Password: {}", password);
+        if let Some(migration) = &self.migration {
+            let status = migration.progress.status(&feature);
+            println!(
+                "Salt: {}",
+                if status == Some(MigrationStatus::Migrated) {
+                    "new (migrated)"
+                } else {
+                    "old"
+                }
+            );
+        }
+        if self.config.display.show_password {
+            println!("Password: {}", password.as_str());
+        }
         println!("Length: {}", password.len());
         println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
 
-        if let Ok(mut clipboard) = Clipboard::new()
-            && clipboard.set_text(&password).is_ok()
-        {
-            println!("📋 Password copied to clipboard!");
-        }
+        self.copy_password(password);
 
         Ok(())
     }
 
+    /// Copy a password to the clipboard according to the clipboard settings
+    fn copy_password(&self, password: Zeroizing<String>) {
+        self.copy_to_clipboard("Password", password);
+    }
+
+    /// Copy `text` to the clipboard according to the clipboard settings, naming it `what`
+    /// in the confirmation
+    fn copy_to_clipboard(&self, what: &str, text: Zeroizing<String>) {
+        if !self.config.clipboard.copy {
+            return;
+        }
+
+        let Ok(mut clipboard) = Clipboard::new() else {
+            return;
+        };
+        if clipboard.set_text(text.as_str()).is_err() {
+            return;
+        }
+
+        match self.config.clipboard.clear_after_secs {
+            Some(secs) => {
+                println!(
+                    "📋 {} copied to clipboard! (cleared in {} seconds)",
+                    what, secs
+                );
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_secs(secs));
+                    // Leave the clipboard alone if something else was copied meanwhile
+                    if clipboard
+                        .get_text()
+                        .is_ok_and(|copied| Zeroizing::new(copied).as_str() == text.as_str())
+                    {
+                        let _ = clipboard.clear();
+                    }
+                });
+            }
+            None => println!("📋 {} copied to clipboard!", what),
+        }
+    }
+
     fn add_feature(&mut self) -> io::Result<()> {
         let name: String = Input::new()
             .with_prompt("Feature name (e.g., GitHub)")
@@ -507,6 +1323,25 @@ impl Cli {
             .with_prompt("Feature identifier (e.g., github.com)")
             .interact_text()
             .map_err(io::Error::other)?;
+        let (feature, normalization) = self.normalize_identifier(&feature)?;
+        let others: Vec<&str> = self
+            .store
+            .find_by_identifier(&feature)
+            .map(|f| f.name.as_str())
+            .collect();
+        if !others.is_empty() {
+            println!(
+                "💡 Already saved for {}: {}. Add a login to keep several accounts apart.",
+                feature,
+                others.join(", ")
+            );
+        }
+        let login: String = Input::new()
+            .with_prompt("Login (optional, tells several accounts on this site apart)")
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        let login = login.trim().to_string();
 
         // Select algorithm
         let algo_items: Vec<String> = Algorithm::all()
@@ -524,14 +1359,20 @@ impl Cli {
             })
             .collect();
 
+        let default_algo = Algorithm::all()
+            .iter()
+            .position(|a| *a == self.config.defaults.algorithm)
+            .unwrap_or(0);
         let algo_selection = Select::with_theme(&ColorfulTheme::default())
             .with_prompt("Select password generation algorithm")
             .items(&algo_items)
-            .default(0)
+            .default(default_algo)
             .interact()
             .map_err(io::Error::other)?;
 
         let algorithm = Algorithm::all()[algo_selection];
+        let params = self.config.defaults.params_for(algorithm);
+        let length = self.ask_length(self.config.defaults.length)?;
 
         let hint: String = Input::new()
             .with_prompt("Hint (optional, press Enter to skip)")
@@ -541,68 +1382,1714 @@ impl Cli {
 
         let hint_option = if hint.is_empty() { None } else { Some(hint) };
 
-        let new_feature = Feature::new(name.clone(), feature, algorithm, hint_option);
-        self.store.add_feature(new_feature);
-        self.storage.save(&self.store)?;
-
-        println!("✅ Feature '{}' added successfully!", name);
-
-        Ok(())
-    }
+        let tags: String = Input::new()
+            .with_prompt("Tags (comma-separated, optional)")
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        let folder: String = Input::new()
+            .with_prompt("Folder (e.g., Work/Dev, optional)")
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
 
-    fn list_features(&self) -> io::Result<()> {
-        let features = self.store.list_features();
+        let mut new_feature =
+            Feature::new(name.clone(), feature, algorithm, hint_option).with_params(params);
+        new_feature.tags = filter::parse_tags(&tags);
+        new_feature.folder = filter::normalize_folder(&folder);
+        new_feature.normalization = normalization;
+        new_feature.length = Some(length);
+        self.ask_aliases(&mut new_feature)?;
+        new_feature.max_age = self.ask_max_age(None)?;
+        new_feature.set_login(&login);
+        self.ask_account_details(&mut new_feature)?;
+
+        let previous = self.storage.get(&name, &new_feature.feature)?;
+        if let Some(previous) = &previous {
+            if previous.login != new_feature.login {
+                println!(
+                    "💡 To keep both accounts, add this one under another name, e.g. '{} ({})'.",
+                    name,
+                    new_feature.login.as_deref().unwrap_or("main")
+                );
+            }
+            let replace = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "A feature named '{}' for '{}' already exists. Replace it?",
+                    name, new_feature.feature
+                ))
+                .default(false)
+                .interact()
+                .map_err(io::Error::other)?;
+            if !replace {
+                return Ok(());
+            }
+        }
+
+        if !self.reload_if_changed()? {
+            return Ok(());
+        }
+        match &previous {
+            Some(previous) => {
+                // The replacement keeps the identity of the feature it replaces
+                new_feature.id = previous.id.clone();
+                self.storage.put(&new_feature)?;
+            }
+            None => self
+                .storage
+                .update(|store| store.add_feature(new_feature.clone()))?
+                .map_err(io::Error::other)?,
+        }
+        self.audit(AuditAction::Add, &new_feature);
+        self.last_change = Some(Change::Put {
+            previous,
+            feature: new_feature.clone(),
+        });
+        self.sync_store(|store| store.put_feature(new_feature))?;
+        println!("✅ Feature '{}' added successfully!", name);
+
+        Ok(())
+    }
+
+    fn list_features(&self) -> io::Result<()> {
+        if self.store.list_features().is_empty() {
+            println!("📭 No features stored yet.");
+            return Ok(());
+        }
+        let Some(features) = self.filter_features()? else {
+            return Ok(());
+        };
+
+        println!("\n📋 Stored Features:");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+
+        let now = Utc::now();
+        let mut number = 0;
+        for line in filter::tree(&features) {
+            let (depth, feature) = match line {
+                TreeLine::Folder { depth, name } => {
+                    println!("{}📁 {}", "   ".repeat(depth), name);
+                    continue;
+                }
+                TreeLine::Feature { depth, feature } => (depth, feature),
+            };
+            let indent = "   ".repeat(depth);
+            number += 1;
+            println!(
+                "{}{}. {} ({})",
+                indent, number, feature.name, feature.feature
+            );
+            println!("{}   ID: {}", indent, feature.id);
+            if !feature.aliases.is_empty() {
+                println!("{}   Aliases: {}", indent, feature.aliases.join(", "));
+            }
+            if let Some(login) = &feature.login {
+                println!("{}   Login: {}", indent, login);
+            }
+            println!("{}   Algorithm: {}", indent, feature.algorithm.name());
+            if let Some(length) = feature.length {
+                println!("{}   Length: {}", indent, length);
+            }
+            if !feature.tags.is_empty() {
+                println!("{}   Tags: {}", indent, feature.tags.join(", "));
+            }
+            for detail in feature.details() {
+                println!("{}   {}: {}", indent, detail.label, detail.display());
+            }
+            if let Some(status) = rotation::status(feature, now) {
+                let due = match status {
+                    Status::Overdue { days } => {
+                        style(format!("{} days overdue", days)).red().to_string()
+                    }
+                    Status::DueSoon { days_left } => style(format!("due in {} days", days_left))
+                        .yellow()
+                        .to_string(),
+                    Status::Ok { days_left } => format!("due in {} days", days_left),
+                };
+                println!(
+                    "{}   Rotation: every {} days, {}",
+                    indent,
+                    feature.max_age.unwrap_or_default(),
+                    due
+                );
+            }
+            if let Some(hint) = feature
+                .hint
+                .as_ref()
+                .filter(|_| self.config.display.show_hints)
+            {
+                println!("{}   Hint: {}", indent, hint);
+            }
+            println!(
+                "{}   Created: {}",
+                indent,
+                feature.created.format("%Y-%m-%d %H:%M:%S")
+            );
+            println!();
+        }
+        if !self.store.trash.is_empty() {
+            println!(
+                "🗑️  {} deleted feature(s) in the trash",
+                self.store.trash.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Ask for a tag or folder to narrow the features down to, if any are in use
+    ///
+    /// Returns `None` if no feature matches the chosen filter.
+    fn filter_features(&self) -> io::Result<Option<Vec<&Feature>>> {
+        let features = self.store.list_features();
+        let tags = filter::tags(features);
+        let folders = filter::folders(features);
+
+        let filter = if tags.is_empty() && folders.is_empty() {
+            Filter::All
+        } else {
+            let mut choices = vec!["All features"];
+            if !tags.is_empty() {
+                choices.push("By tag");
+            }
+            if !folders.is_empty() {
+                choices.push("By folder");
+            }
+            let choice = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Show")
+                .items(&choices)
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+            let (prompt, values) = match choices[choice] {
+                "By tag" => ("Tag", &tags),
+                "By folder" => ("Folder", &folders),
+                _ => ("", &Vec::new()),
+            };
+            if values.is_empty() {
+                Filter::All
+            } else {
+                let selection = Select::with_theme(&ColorfulTheme::default())
+                    .with_prompt(prompt)
+                    .items(values)
+                    .default(0)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                let value = values[selection].clone();
+                if prompt == "Tag" {
+                    Filter::Tag(value)
+                } else {
+                    Filter::Folder(value)
+                }
+            }
+        };
+
+        let filtered: Vec<&Feature> = features.iter().filter(|f| filter.matches(f)).collect();
+        if filtered.is_empty() {
+            println!("📭 No features match.");
+            return Ok(None);
+        }
+        Ok(Some(filtered))
+    }
+
+    /// Offer the normalized forms of an identifier that is a URL or domain, see
+    /// [`domain::normalize`]
+    ///
+    /// Returns the identifier to save and how it was normalized. Other identifiers, and
+    /// domains that are already in normal form, are kept without asking.
+    fn normalize_identifier(&self, input: &str) -> io::Result<(String, Normalization)> {
+        let input = input.trim();
+        let default = self.config.defaults.normalization;
+        if domain::host(input).is_none() {
+            return Ok((input.to_string(), Normalization::Exact));
+        }
+        let forms: Vec<(Normalization, String)> = Normalization::all()
+            .iter()
+            .map(|&mode| (mode, domain::normalize(input, mode)))
+            .collect();
+        if forms.iter().all(|(_, form)| form == input) {
+            return Ok((input.to_string(), default));
+        }
+
+        let items: Vec<String> = forms
+            .iter()
+            .map(|(mode, form)| format!("{} ({})", form, mode.name()))
+            .collect();
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Save the identifier as")
+            .items(&items)
+            .default(
+                forms
+                    .iter()
+                    .position(|(mode, _)| *mode == default)
+                    .unwrap_or(0),
+            )
+            .interact()
+            .map_err(io::Error::other)?;
+        let (mode, form) = forms[selection].clone();
+        Ok((form, mode))
+    }
+
+    /// Ask for a password length, clamped to 12-64
+    fn ask_length(&self, default: usize) -> io::Result<usize> {
+        let length: String = Input::new()
+            .with_prompt("Password length (12-64)")
+            .default(default.to_string())
+            .interact_text()
+            .map_err(io::Error::other)?;
+        Ok(length.parse::<usize>().unwrap_or(default).clamp(12, 64))
+    }
+
+    /// Ask for the length of a password generated before lengths were stored
+    fn ask_unrecorded_length(&self) -> io::Result<Option<usize>> {
+        let length: String = Input::new()
+            .with_prompt("Password length (12-64, leave empty if unknown)")
+            .allow_empty(true)
+            .validate_with(|input: &String| -> Result<(), &str> {
+                match input.trim() {
+                    "" => Ok(()),
+                    length => match length.parse::<usize>() {
+                        Ok(12..=64) => Ok(()),
+                        _ => Err("Enter a length from 12 to 64"),
+                    },
+                }
+            })
+            .interact_text()
+            .map_err(io::Error::other)?;
+        Ok(length.trim().parse().ok())
+    }
+
+    /// Ask how often the password must be rotated, in days
+    fn ask_max_age(&self, current: Option<u32>) -> io::Result<Option<u32>> {
+        let days: String = Input::new()
+            .with_prompt("Rotate the password every (days, leave empty for never)")
+            .with_initial_text(current.map(|days| days.to_string()).unwrap_or_default())
+            .allow_empty(true)
+            .validate_with(|input: &String| -> Result<(), &str> {
+                match input.trim() {
+                    "" => Ok(()),
+                    days => match days.parse::<u32>() {
+                        Ok(days) if days > 0 => Ok(()),
+                        _ => Err("Enter a number of days"),
+                    },
+                }
+            })
+            .interact_text()
+            .map_err(io::Error::other)?;
+        Ok(days.trim().parse().ok())
+    }
+
+    /// Ask for the aliases of a feature whose identifier is a URL or domain, suggesting the
+    /// domains known to share its login when it has none yet
+    fn ask_aliases(&self, feature: &mut Feature) -> io::Result<()> {
+        if domain::host(&feature.feature).is_none() && feature.aliases.is_empty() {
+            return Ok(());
+        }
+        let initial = if feature.aliases.is_empty() {
+            let known = EquivalentDomains::load()?.equivalents(&feature.feature);
+            if !known.is_empty() {
+                println!(
+                    "💡 These sites are known to share the login of {}.",
+                    feature.feature
+                );
+            }
+            known
+        } else {
+            feature.aliases.clone()
+        };
+
+        let aliases: String = Input::new()
+            .with_prompt("Aliases (other domains of this site, comma-separated, optional)")
+            .with_initial_text(initial.join(", "))
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        feature.aliases.clear();
+        for alias in aliases.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            let alias = domain::normalize(alias, feature.normalization);
+            if alias != feature.feature && !feature.aliases.contains(&alias) {
+                feature.aliases.push(alias);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask for the username, URLs, notes and custom fields of `feature`, offering the
+    /// current values as defaults
+    fn ask_account_details(&self, feature: &mut Feature) -> io::Result<()> {
+        let text = |prompt: &str, current: String| -> io::Result<String> {
+            let mut input = Input::<String>::new().with_prompt(prompt).allow_empty(true);
+            if !current.is_empty() {
+                input = input.default(current);
+            }
+            let value = input.interact_text().map_err(io::Error::other)?;
+            Ok(value.trim().to_string())
+        };
+
+        let username = text(
+            "Username (optional)",
+            feature.username.clone().unwrap_or_default(),
+        )?;
+        feature.username = (!username.is_empty()).then_some(username);
+        let urls = text("URLs (comma-separated, optional)", feature.urls.join(", "))?;
+        feature.urls = urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+        let notes = text(
+            "Notes (optional)",
+            feature.notes.clone().unwrap_or_default(),
+        )?;
+        feature.notes = (!notes.is_empty()).then_some(notes);
+
+        self.edit_custom_fields(&mut feature.fields)
+    }
+
+    /// Add, replace or remove custom fields until the user is done
+    fn edit_custom_fields(&self, fields: &mut Vec<CustomField>) -> io::Result<()> {
+        loop {
+            let mut items = vec!["Done".to_string(), "Add a field".to_string()];
+            items.extend(fields.iter().map(|f| format!("Remove '{}'", f.name)));
+            let choice = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("Custom fields ({})", fields.len()))
+                .items(&items)
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+
+            match choice {
+                0 => return Ok(()),
+                1 => {
+                    let name: String = Input::new()
+                        .with_prompt("Field name")
+                        .interact_text()
+                        .map_err(io::Error::other)?;
+                    let concealed = Select::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Field type")
+                        .items(&["Plain", "Concealed (masked on screen)"])
+                        .default(0)
+                        .interact()
+                        .map_err(io::Error::other)?
+                        == 1;
+                    let (kind, value) = if concealed {
+                        let value = Password::with_theme(&ColorfulTheme::default())
+                            .with_prompt("Value")
+                            .interact()
+                            .map_err(io::Error::other)?;
+                        (FieldKind::Concealed, value)
+                    } else {
+                        let value: String = Input::new()
+                            .with_prompt("Value")
+                            .interact_text()
+                            .map_err(io::Error::other)?;
+                        (FieldKind::Plain, value)
+                    };
+
+                    let field = CustomField {
+                        name: name.trim().to_string(),
+                        value,
+                        kind,
+                    };
+                    match fields.iter_mut().find(|f| f.name == field.name) {
+                        Some(existing) => *existing = field,
+                        None => fields.push(field),
+                    }
+                }
+                i => {
+                    fields.remove(i - 2);
+                }
+            }
+        }
+    }
+
+    /// Copy a username, URL, note or custom field of a feature to the clipboard
+    fn copy_details(&self) -> io::Result<()> {
+        let features: Vec<&Feature> = self
+            .store
+            .list_features()
+            .iter()
+            .filter(|f| !f.details().is_empty())
+            .collect();
+        if features.is_empty() {
+            println!("⚠️  No features have account details yet. Add them with \"Edit Feature\".");
+            return Ok(());
+        }
+
+        let Some(feature) = self.pick_feature("Search a feature", &features)? else {
+            return Ok(());
+        };
+        let details = feature.details();
+        let items: Vec<String> = details
+            .iter()
+            .map(|d| format!("{}: {}", d.label, d.display()))
+            .collect();
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Copy which detail?")
+            .items(&items)
+            .default(0)
+            .interact()
+            .map_err(io::Error::other)?;
+
+        let detail = &details[selection];
+        if !self.config.clipboard.copy {
+            // Concealed values are shown like passwords
+            let value = match detail.kind {
+                FieldKind::Concealed if !self.config.display.show_password => detail.display(),
+                _ => detail.value,
+            };
+            println!("{}: {}", detail.label, value);
+            return Ok(());
+        }
+        self.copy_to_clipboard(&detail.label, Zeroizing::new(detail.value.to_string()));
+        Ok(())
+    }
+
+    /// Let the user pick one of `features` by typing part of its name, identifier or hint
+    ///
+    /// Results are ranked by [`fuzzy::rank`] and the highlighted one is previewed below the
+    /// list. Returns `None` if the user presses Esc. Falls back to a plain list when not
+    /// attached to a terminal.
+    fn pick_feature<'a>(
+        &self,
+        prompt: &str,
+        features: &[&'a Feature],
+    ) -> io::Result<Option<&'a Feature>> {
+        Ok(self
+            .pick_feature_with(prompt, features, None)?
+            .map(|(feature, _)| feature))
+    }
+
+    /// [`Cli::pick_feature`], also offering to pick with Tab for the `alternate` action
+    ///
+    /// Returns the feature and whether it was picked with Tab. The plain list shown when
+    /// not attached to a terminal has no alternate action.
+    fn pick_feature_with<'a>(
+        &self,
+        prompt: &str,
+        features: &[&'a Feature],
+        alternate: Option<&str>,
+    ) -> io::Result<Option<(&'a Feature, bool)>> {
+        let term = Term::stderr();
+        if !term.is_term() {
+            let items: Vec<String> = features
+                .iter()
+                .map(|f| format!("{} ({})", f.name, f.feature))
+                .collect();
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(prompt)
+                .items(&items)
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+            return Ok(Some((features[selection], false)));
+        }
+
+        let mut query = String::new();
+        let mut cursor = 0;
+        let mut drawn = 0;
+        term.hide_cursor()?;
+        let picked = loop {
+            let matches = fuzzy::rank(&query, features);
+            cursor = cursor.min(matches.len().saturating_sub(1));
+
+            let mut lines = vec![format!(
+                "{} {} {} {}",
+                style("?").yellow(),
+                style(prompt).bold(),
+                style("›").dim(),
+                query
+            )];
+            let first = (cursor + 1).saturating_sub(PICKER_ROWS);
+            for (i, m) in matches.iter().enumerate().skip(first).take(PICKER_ROWS) {
+                let (name, identifier) = match m.field {
+                    fuzzy::Field::Name => (
+                        highlight(&m.feature.name, &m.positions),
+                        m.feature.feature.clone(),
+                    ),
+                    fuzzy::Field::Identifier => (
+                        m.feature.name.clone(),
+                        highlight(&m.feature.feature, &m.positions),
+                    ),
+                    fuzzy::Field::Alias(alias) => (
+                        m.feature.name.clone(),
+                        format!(
+                            "{} via {}",
+                            m.feature.feature,
+                            highlight(&m.feature.aliases[alias], &m.positions)
+                        ),
+                    ),
+                    fuzzy::Field::Hint => (m.feature.name.clone(), m.feature.feature.clone()),
+                };
+                let marker = if i == cursor {
+                    style("❯").cyan().to_string()
+                } else {
+                    " ".to_string()
+                };
+                lines.push(format!("{} {} ({})", marker, name, identifier));
+            }
+            match matches.len() {
+                0 => lines.push("  📭 No features match.".to_string()),
+                n if n > first + PICKER_ROWS => lines.push(
+                    style(format!("  … {} more", n - first - PICKER_ROWS))
+                        .dim()
+                        .to_string(),
+                ),
+                _ => {}
+            }
+            if let Some(alternate) = alternate {
+                lines.push(style(format!("  Tab: {}", alternate)).dim().to_string());
+            }
+            if let Some(m) = matches.get(cursor) {
+                lines.push(String::new());
+                lines.extend(self.preview(m.feature));
+            }
+
+            term.clear_last_lines(drawn)?;
+            let width = term.size().1 as usize;
+            for line in &lines {
+                term.write_line(&console::truncate_str(line, width.saturating_sub(1), "…"))?;
+            }
+            drawn = lines.len();
+
+            match term.read_key()? {
+                Key::Char(c) if !c.is_control() => {
+                    query.push(c);
+                    cursor = 0;
+                }
+                Key::Backspace => {
+                    query.pop();
+                    cursor = 0;
+                }
+                Key::ArrowUp => cursor = cursor.saturating_sub(1),
+                Key::ArrowDown if cursor + 1 < matches.len() => cursor += 1,
+                Key::Enter if !matches.is_empty() => break Some((matches[cursor].feature, false)),
+                Key::Tab if alternate.is_some() && !matches.is_empty() => {
+                    break Some((matches[cursor].feature, true));
+                }
+                Key::Escape | Key::CtrlC => break None,
+                _ => {}
+            }
+        };
+        term.clear_last_lines(drawn)?;
+        term.show_cursor()?;
+
+        if let Some((feature, alternated)) = picked {
+            term.write_line(&format!(
+                "{} {} {} {} ({}){}",
+                style("✔").green(),
+                style(prompt).bold(),
+                style("·").dim(),
+                style(&feature.name).green(),
+                feature.feature,
+                match alternate.filter(|_| alternated) {
+                    Some(alternate) => style(format!(" · {}", alternate)).dim().to_string(),
+                    None => String::new(),
+                }
+            ))?;
+        }
+        Ok(picked)
+    }
+
+    /// The algorithm and metadata of a feature, as shown under the picker
+    fn preview(&self, feature: &Feature) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !feature.aliases.is_empty() {
+            lines.push(format!("   Aliases: {}", feature.aliases.join(", ")));
+        }
+        if let Some(login) = &feature.login {
+            lines.push(format!("   Login: {}", login));
+        }
+        if let Some(username) = feature
+            .username
+            .as_ref()
+            .filter(|u| feature.login.as_ref() != Some(u))
+        {
+            lines.push(format!("   Username: {}", username));
+        }
+        lines.push(format!(
+            "   Algorithm: {} ({})",
+            feature.algorithm.name(),
+            feature.params().describe(feature.algorithm)
+        ));
+        if let Some(length) = feature.length {
+            lines.push(format!("   Length: {}", length));
+        }
+        if let Some(folder) = &feature.folder {
+            lines.push(format!("   Folder: {}", folder));
+        }
+        if !feature.tags.is_empty() {
+            lines.push(format!("   Tags: {}", feature.tags.join(", ")));
+        }
+        if let Some(hint) = feature
+            .hint
+            .as_ref()
+            .filter(|_| self.config.display.show_hints)
+        {
+            lines.push(format!("   Hint: {}", hint));
+        }
+        lines.push(format!(
+            "   Created: {}",
+            feature.created.format("%Y-%m-%d %H:%M:%S")
+        ));
+        lines
+            .into_iter()
+            .map(|line| style(line).dim().to_string())
+            .collect()
+    }
+
+    /// Change a feature's name, identifier, algorithm, hint, tags or folder, keeping its ID
+    /// and creation time
+    fn edit_feature(&mut self) -> io::Result<()> {
+        if self.store.list_features().is_empty() {
+            println!("⚠️  No features to edit.");
+            return Ok(());
+        }
+
+        let Some(filtered) = self.filter_features()? else {
+            return Ok(());
+        };
+        let Some(selected) = self.pick_feature("Search a feature to edit", &filtered)? else {
+            return Ok(());
+        };
+        let original = selected.clone();
+        let mut edited = original.clone();
+
+        edited.name = Input::new()
+            .with_prompt("Feature name")
+            .default(original.name.clone())
+            .interact_text()
+            .map_err(io::Error::other)?;
+        edited.feature = Input::new()
+            .with_prompt("Feature identifier")
+            .default(original.feature.clone())
+            .interact_text()
+            .map_err(io::Error::other)?;
+        if edited.feature != original.feature {
+            (edited.feature, edited.normalization) = self.normalize_identifier(&edited.feature)?;
+        }
+        self.ask_aliases(&mut edited)?;
+        let login: String = Input::new()
+            .with_prompt("Login (changes the password, leave empty for none)")
+            .with_initial_text(original.login.clone().unwrap_or_default())
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        edited.set_login(&login);
+
+        let algo_items: Vec<&str> = Algorithm::all().iter().map(|a| a.name()).collect();
+        let current = Algorithm::all()
+            .iter()
+            .position(|a| *a == original.algorithm)
+            .unwrap_or(0);
+        let algo_selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Password generation algorithm")
+            .items(&algo_items)
+            .default(current)
+            .interact()
+            .map_err(io::Error::other)?;
+        let algorithm = Algorithm::all()[algo_selection];
+        if algorithm != original.algorithm {
+            edited.algorithm = algorithm;
+            edited.params = None;
+            edited = edited.with_params(self.config.defaults.params_for(algorithm));
+        }
+
+        let hint: String = Input::new()
+            .with_prompt("Hint (leave empty for none)")
+            .default(original.hint.clone().unwrap_or_default())
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        edited.hint = (!hint.is_empty()).then_some(hint);
+
+        let tags: String = Input::new()
+            .with_prompt("Tags (comma-separated, leave empty for none)")
+            .default(original.tags.join(", "))
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        edited.tags = filter::parse_tags(&tags);
+        let folder: String = Input::new()
+            .with_prompt("Folder (leave empty for none)")
+            .default(original.folder.clone().unwrap_or_default())
+            .allow_empty(true)
+            .interact_text()
+            .map_err(io::Error::other)?;
+        edited.folder = filter::normalize_folder(&folder);
+        edited.length = match original.length {
+            Some(length) => Some(self.ask_length(length)?),
+            None => self.ask_unrecorded_length()?,
+        };
+        edited.max_age = self.ask_max_age(original.max_age)?;
+        self.ask_account_details(&mut edited)?;
+
+        if edited == original {
+            println!("ℹ️  Nothing changed.");
+            return Ok(());
+        }
+
+        if !edited.same_password_as(&original) {
+            let old_length = match original.length.or(edited.length) {
+                Some(length) => length,
+                None => self.ask_length(self.config.defaults.length)?,
+            };
+            let new_length = edited.length.unwrap_or(old_length);
+
+            let salt = self.salt_for(&original);
+            let (Some(old), Some(new)) = (
+                derive_password(&original, salt, old_length),
+                derive_password(&edited, salt, new_length),
+            ) else {
+                return Ok(());
+            };
+            println!(
+                "\n⚠️  This change gives '{}' a different password:",
+                original.name
+            );
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+            if self.config.display.show_password {
+                println!("Old password: {}", old.as_str());
+                println!("New password: {}", new.as_str());
+            } else {
+                println!("(passwords hidden by the display settings)");
+            }
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+            let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Change the password? The account must be updated to the new one")
+                .default(false)
+                .interact()
+                .map_err(io::Error::other)?;
+            if !confirmed {
+                return Ok(());
+            }
+            edited.last_rotated = Some(Utc::now());
+        }
+
+        let updated = self
+            .storage
+            .update(|store| store.update(&original.id, edited.clone()))?;
+        let previous = match updated {
+            Ok(Some(previous)) => previous,
+            Ok(None) => {
+                println!(
+                    "⚠️  Feature '{}' was deleted by another process.",
+                    original.name
+                );
+                return self.sync_store(|_| {});
+            }
+            Err(e) => {
+                println!("❌ {}", e);
+                return Ok(());
+            }
+        };
+
+        let action = if edited.same_password_as(&previous) {
+            AuditAction::Edit
+        } else {
+            AuditAction::Rotate
+        };
+        self.audit(action, &edited);
+        self.last_change = Some(Change::Edit {
+            previous,
+            feature: edited.clone(),
+        });
+        println!("✅ Feature '{}' updated.", edited.name);
+        self.sync_store(|store| {
+            let _ = store.update(&edited.id, edited.clone());
+        })
+    }
+
+    fn delete_feature(&mut self) -> io::Result<()> {
+        if self.store.list_features().is_empty() {
+            println!("⚠️  No features to delete.");
+            return Ok(());
+        }
+
+        let Some(filtered) = self.filter_features()? else {
+            return Ok(());
+        };
+        let Some(selected) = self.pick_feature("Search a feature to delete", &filtered)? else {
+            return Ok(());
+        };
+        let selected = selected.clone();
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Move '{}' ({}) to the trash?",
+                selected.name, selected.feature
+            ))
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            return Ok(());
+        }
+
+        if !self.reload_if_changed()? {
+            return Ok(());
+        }
+        let deleted = Utc::now();
+        let trashed = self
+            .storage
+            .update(|store| store.trash_feature(&selected.id, deleted))?;
+        match &trashed {
+            Some(entry) => {
+                self.audit(AuditAction::Delete, &entry.feature);
+                self.last_change = Some(Change::Trash(entry.clone()));
+                println!(
+                    "🗑️  Feature '{}' moved to the trash. Restore it from \"Trash\".",
+                    selected.name
+                );
+            }
+            None => println!(
+                "⚠️  Feature '{}' was already deleted by another process.",
+                selected.name
+            ),
+        }
+        self.sync_store(|store| {
+            store.trash_feature(&selected.id, deleted);
+        })?;
+
+        Ok(())
+    }
+
+    /// Revert the last change made in this session
+    fn undo(&mut self) -> io::Result<()> {
+        let Some(change) = self.last_change.take() else {
+            return Ok(());
+        };
+
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Undo {}?", change.describe()))
+            .default(true)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            self.last_change = Some(change);
+            return Ok(());
+        }
+
+        match &change {
+            Change::Put {
+                previous: Some(previous),
+                ..
+            } => self.storage.put(previous)?,
+            Change::Put {
+                previous: None,
+                feature,
+            } => {
+                self.storage.delete(&feature.id)?;
+            }
+            _ => self.storage.update(|store| change.clone().revert(store))?,
+        }
+        let (action, feature) = change.revert_action();
+        self.audit(action, feature);
+        println!("↩️  Undid {}.", change.describe());
+        self.sync_store(|store| change.revert(store))
+    }
+
+    /// Browse the trash to restore or purge deleted features
+    fn manage_trash(&mut self) -> io::Result<()> {
+        if self.store.trash.is_empty() {
+            println!("🗑️  The trash is empty.");
+            return Ok(());
+        }
+
+        let mut items: Vec<String> = self
+            .store
+            .trash
+            .iter()
+            .map(|t| {
+                format!(
+                    "{} ({}) - deleted {}",
+                    t.feature.name,
+                    t.feature.feature,
+                    t.deleted.format("%Y-%m-%d %H:%M")
+                )
+            })
+            .collect();
+        items.push("Purge entries older than...".to_string());
+        items.push("Back".to_string());
 
-        if features.is_empty() {
-            println!("📭 No features stored yet.");
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Trash")
+            .items(&items)
+            .default(0)
+            .interact()
+            .map_err(io::Error::other)?;
+        let count = self.store.trash.len();
+        if selection == count {
+            return self.purge_by_age();
+        } else if selection > count {
             return Ok(());
         }
 
-        println!("\n📋 Stored Features:");
-        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        let entry = self.store.trash[selection].clone();
+        let action = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("'{}'", entry.feature.name))
+            .items(&["Restore", "Purge permanently", "Back"])
+            .default(0)
+            .interact()
+            .map_err(io::Error::other)?;
+        match action {
+            0 => self.restore_feature(entry),
+            1 => {
+                let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt(format!(
+                        "Permanently remove '{}'? This cannot be undone.",
+                        entry.feature.name
+                    ))
+                    .default(false)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                if confirmed {
+                    let purged = self.storage.update(|store| store.purge_feature(&entry))?;
+                    self.finish_purge(purged.into_iter().collect())?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-        for (idx, feature) in features.iter().enumerate() {
-            println!("{}. {} ({})", idx + 1, feature.name, feature.feature);
-            println!("   Algorithm: {}", feature.algorithm.name());
-            if let Some(hint) = &feature.hint {
-                println!("   Hint: {}", hint);
+    fn restore_feature(&mut self, entry: TrashedFeature) -> io::Result<()> {
+        let replaced = self
+            .storage
+            .get(&entry.feature.name, &entry.feature.feature)?;
+        if replaced.is_some() {
+            let replace = Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "A feature named '{}' for '{}' exists again. Replace it?",
+                    entry.feature.name, entry.feature.feature
+                ))
+                .default(false)
+                .interact()
+                .map_err(io::Error::other)?;
+            if !replace {
+                return Ok(());
             }
+        }
+
+        if self.storage.update(|store| store.restore_feature(&entry))? {
+            self.audit(AuditAction::Restore, &entry.feature);
+            println!("♻️  Feature '{}' restored!", entry.feature.name);
+            self.last_change = Some(Change::Restore {
+                entry: entry.clone(),
+                replaced,
+            });
+        } else {
             println!(
-                "   Created: {}",
-                feature.created.format("%Y-%m-%d %H:%M:%S")
+                "⚠️  Feature '{}' is no longer in the trash.",
+                entry.feature.name
             );
-            println!();
         }
+        self.sync_store(|store| {
+            store.restore_feature(&entry);
+        })
+    }
 
-        Ok(())
+    /// Purge trash entries older than a number of days
+    fn purge_by_age(&mut self) -> io::Result<()> {
+        let default_days = self.config.trash.purge_after_days.unwrap_or(30);
+        let days: u32 = Input::new()
+            .with_prompt("Purge entries deleted more than this many days ago")
+            .default(default_days)
+            .interact_text()
+            .map_err(io::Error::other)?;
+
+        let cutoff = Utc::now() - chrono::Duration::days(days.into());
+        let expired = self
+            .store
+            .trash
+            .iter()
+            .filter(|t| t.deleted < cutoff)
+            .count();
+        if expired == 0 {
+            println!("📭 No entries older than {} days.", days);
+            return Ok(());
+        }
+
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Permanently remove {} entries? This cannot be undone.",
+                expired
+            ))
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            return Ok(());
+        }
+        let purged = self
+            .storage
+            .update(|store| store.purge_older_than(cutoff))?;
+        self.finish_purge(purged)
     }
 
-    fn delete_feature(&mut self) -> io::Result<()> {
-        if self.store.list_features().is_empty() {
-            println!("⚠️  No features to delete.");
+    /// Purge the trash entries older than the configured age, if any
+    fn purge_expired(&mut self) -> io::Result<()> {
+        let Some(days) = self.config.trash.purge_after_days else {
+            return Ok(());
+        };
+        let cutoff = Utc::now() - chrono::Duration::days(days.into());
+        if !self.store.trash.iter().any(|t| t.deleted < cutoff) {
             return Ok(());
         }
+        let purged = self
+            .storage
+            .update(|store| store.purge_older_than(cutoff))?;
+        println!("🔥 Purging trash entries older than {} days:", days);
+        self.finish_purge(purged)
+    }
 
-        let features: Vec<String> = self
-            .store
-            .list_features()
+    /// Record purged entries and drop them from the in-memory store
+    fn finish_purge(&mut self, purged: Vec<TrashedFeature>) -> io::Result<()> {
+        for entry in &purged {
+            self.audit(AuditAction::Purge, &entry.feature);
+            println!("🔥 '{}' removed permanently", entry.feature.name);
+        }
+        self.sync_store(|store| {
+            for entry in &purged {
+                store.purge_feature(entry);
+            }
+        })
+    }
+
+    /// Record a mutation in the audit log
+    ///
+    /// The change itself is already saved, so a failure is reported but not fatal.
+    fn audit(&self, action: AuditAction, feature: &Feature) {
+        if let Err(e) = self.storage.audit(action, feature) {
+            println!("⚠️  Could not write the audit record: {}", e);
+        }
+    }
+
+    /// Point out the features due for rotation, or due soon
+    fn print_stale_features(&self) {
+        let report = rotation::report(self.store.list_features(), Utc::now());
+        let overdue: Vec<String> = report
             .iter()
-            .map(|f| format!("{} ({})", f.name, f.feature))
+            .filter_map(|(f, status)| match status {
+                Status::Overdue { days } => Some(format!("{} ({} days overdue)", f.name, days)),
+                _ => None,
+            })
             .collect();
+        let soon = report
+            .iter()
+            .filter(|(_, status)| matches!(status, Status::DueSoon { .. }))
+            .count();
 
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select a feature to delete")
-            .items(&features)
-            .default(0)
+        if !overdue.is_empty() {
+            println!(
+                "{} {}",
+                style(format!("⏰ {} feature(s) due for rotation:", overdue.len()))
+                    .red()
+                    .bold(),
+                style(overdue.join(", ")).red()
+            );
+        }
+        if soon > 0 {
+            println!(
+                "{}",
+                style(format!(
+                    "⏳ {} feature(s) due within {} days. See \"Rotation Report\".",
+                    soon,
+                    rotation::DUE_SOON_DAYS
+                ))
+                .yellow()
+            );
+        }
+    }
+
+    /// List the features with a maximum password age, most urgent first
+    fn rotation_report(&self) {
+        let report = rotation::report(self.store.list_features(), Utc::now());
+        if report.is_empty() {
+            println!("ℹ️  No feature has a maximum password age. Set one with \"Edit Feature\".");
+            return;
+        }
+
+        println!("\n🔄 Rotation Report:");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        for (feature, status) in &report {
+            let line = format!(
+                "{} ({}) - every {} days, last rotated {}",
+                feature.name,
+                feature.feature,
+                feature.max_age.unwrap_or_default(),
+                rotation::last_rotated(feature).format("%Y-%m-%d")
+            );
+            match status {
+                Status::Overdue { days } => println!(
+                    "🔴 {}",
+                    style(format!("{}: {} days overdue", line, days)).red()
+                ),
+                Status::DueSoon { days_left } => println!(
+                    "🟡 {}",
+                    style(format!("{}: due in {} days", line, days_left)).yellow()
+                ),
+                Status::Ok { days_left } => println!("🟢 {}: due in {} days", line, days_left),
+            }
+        }
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        println!(
+            "💡 Rotate a password by changing its login, identifier or algorithm in \
+             \"Edit Feature\", or all of them with \"Change Master Salt\"."
+        );
+    }
+
+    /// Record that a feature's password was just changed
+    fn mark_rotated(&mut self, feature: &Feature) -> io::Result<()> {
+        let now = Utc::now();
+        let rotated = self.storage.update(|store| {
+            let mut rotated = store.get(&feature.id)?.clone();
+            rotated.last_rotated = Some(now);
+            store.update(&feature.id, rotated.clone()).ok()?;
+            Some(rotated)
+        })?;
+        let Some(rotated) = rotated else {
+            return Ok(());
+        };
+        self.audit(AuditAction::Rotate, &rotated);
+        self.sync_store(|store| {
+            let _ = store.update(&rotated.id, rotated.clone());
+        })
+    }
+
+    /// Offer to store the length just used for a feature added before lengths were stored
+    fn remember_length(&mut self, feature: &Feature, length: usize) -> io::Result<()> {
+        let remember = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                "Remember {} characters for '{}'? Generating won't ask again",
+                length, feature.name
+            ))
+            .default(true)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !remember {
+            return Ok(());
+        }
+        let updated = self
+            .storage
+            .update(|store| store.record_length(&feature.id, length))?;
+        let Some(updated) = updated else {
+            return Ok(());
+        };
+        self.audit(AuditAction::Edit, &updated);
+        self.sync_store(|store| {
+            store.record_length(&updated.id, length);
+        })
+    }
+
+    /// Show the audit log and verify its hash chain
+    pub fn show_audit(&self) -> io::Result<()> {
+        let log = self.storage.audit_log()?;
+        let entries = log.read()?;
+        if entries.is_empty() {
+            println!("📭 No audit records yet.");
+            return Ok(());
+        }
+
+        println!("\n📜 Audit Log:");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        for entry in &entries {
+            let record = &entry.record;
+            println!(
+                "{}  {} {} ({}) on {}",
+                record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                record.action.label(),
+                record.name,
+                record.feature,
+                record.host
+            );
+        }
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+
+        match log.verify(&entries)? {
+            Ok(()) => println!("✅ Hash chain intact ({} records)", entries.len()),
+            Err(index) => println!(
+                "❌ Hash chain broken at record {}: the log was modified after it was written",
+                index + 1
+            ),
+        }
+        Ok(())
+    }
+
+    /// Offer to reload the store before applying a pending change if another process
+    /// modified it since it was loaded
+    ///
+    /// Returns `false` if the user discards the change instead.
+    fn reload_if_changed(&mut self) -> io::Result<bool> {
+        match self.storage.ensure_unchanged() {
+            Err(e) if storage::is_store_changed(&e) => {
+                println!("⚠️  {}", e);
+                let reload = Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Reload the file and reapply your change?")
+                    .default(true)
+                    .interact()
+                    .map_err(io::Error::other)?;
+                if !reload {
+                    println!("↩️  Change discarded, the file on disk was left untouched.");
+                    return Ok(false);
+                }
+                self.store = self.storage.load()?;
+                Ok(true)
+            }
+            result => result.map(|()| true),
+        }
+    }
+
+    /// Bring the in-memory store up to date after a single-feature write
+    ///
+    /// Single-feature writes apply on top of the current store, so changes made by another
+    /// process are kept; if there were any, the store is reloaded instead of patched.
+    fn sync_store(&mut self, change: impl FnOnce(&mut FeatureStore)) -> io::Result<()> {
+        if self.storage.has_changed()? {
+            println!("🔄 The features file was changed by another process, reloading it.");
+            self.store = self.storage.load()?;
+        } else {
+            change(&mut self.store);
+        }
+        Ok(())
+    }
+
+    /// Accept the current content of the features file and sign it
+    fn resign_store(&mut self) -> io::Result<()> {
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(
+                "Sign the current content of the features file? Only do this after checking \
+                 any manual edits.",
+            )
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            return Ok(());
+        }
+
+        let store = self.storage.load_unverified()?;
+        self.storage.resign(&store)?;
+        self.store = store;
+        println!("✅ Store re-signed.");
+        Ok(())
+    }
+
+    fn compact_log(&mut self) -> io::Result<()> {
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(
+                "Compact the operation log? Only the latest entry of each feature is kept.",
+            )
+            .default(true)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            return Ok(());
+        }
+
+        match self.storage.compact() {
+            Ok((before, after)) => {
+                println!(
+                    "🗜️  Operation log compacted: {} → {} entries",
+                    before, after
+                );
+            }
+            Err(e) if storage::is_store_changed(&e) => {
+                println!("⚠️  {}", e);
+                println!("🔄 Reloading; compact again once you have checked the changes.");
+                self.store = self.storage.load()?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Merge the store with the copy on the sync server and upload the result
+    fn sync(&mut self) -> io::Result<()> {
+        let (Some(url), Some(salt)) = (&self.config.sync.url, &self.salt) else {
+            return Ok(());
+        };
+        println!("🔄 Syncing with {}...", url);
+        let client = SyncClient::new(url, salt.value());
+
+        let same = |a: &FeatureStore, b: &FeatureStore| -> io::Result<bool> {
+            Ok(serde_json::to_value(a).map_err(io::Error::other)?
+                == serde_json::to_value(b).map_err(io::Error::other)?)
+        };
+        loop {
+            let pulled = self.storage.pull(&client)?;
+            let merged = loop {
+                let merged = match &pulled.remote {
+                    // The server did not change since our last sync
+                    Some(_) if pulled.revision == pulled.synced_revision => self.store.clone(),
+                    Some(remote) => {
+                        let base = pulled.base.clone().unwrap_or_default();
+                        let outcome = merge::three_way_merge(&base, &self.store, remote)?;
+                        let conflicts = outcome.conflicts().count();
+                        if conflicts > 0 {
+                            println!("⚔️  {} conflict(s) need your decision", conflicts);
+                        }
+                        outcome.resolve(Self::ask_resolution)?
+                    }
+                    None => self.store.clone(),
+                };
+                if same(&merged, &self.store)? {
+                    break merged;
+                }
+                match self.storage.save(&merged) {
+                    Ok(()) => {
+                        self.store = merged.clone();
+                        break merged;
+                    }
+                    Err(e) if storage::is_store_changed(&e) => {
+                        println!(
+                            "🔄 The features file was changed by another process, merging again..."
+                        );
+                        self.store = self.storage.load()?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+
+            if let Some(remote) = &pulled.remote
+                && same(&merged, remote)?
+            {
+                self.storage.mark_synced(&client, &pulled)?;
+                println!("✅ Already up to date (revision {})", pulled.revision);
+                return Ok(());
+            }
+
+            match self.storage.push(&client, &merged, pulled.revision) {
+                Ok(revision) => {
+                    println!(
+                        "✅ Synced {} features (revision {})",
+                        merged.features.len(),
+                        revision
+                    );
+                    return Ok(());
+                }
+                Err(e) if sync::conflict_revision(&e).is_some() => {
+                    println!("🔄 The server changed meanwhile, merging again...");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Salt deriving a feature's password: the new salt once it has been migrated
+    fn salt_for(&self, feature: &Feature) -> &Salt {
+        match &self.migration {
+            Some(m) if m.progress.status(feature) == Some(MigrationStatus::Migrated) => &m.new_salt,
+            _ => self.salt.as_ref().unwrap(),
+        }
+    }
+
+    /// Walk through every feature to move to a new master salt
+    fn change_salt(&mut self) -> io::Result<()> {
+        if self.migration.is_none() && !self.begin_salt_change()? {
+            return Ok(());
+        }
+
+        loop {
+            let migration = self.migration.as_mut().expect("salt change started");
+            migration.progress.track(&self.store);
+            let progress = &migration.progress;
+            println!(
+                "\n🔁 Salt change: {} migrated, {} pending, {} skipped",
+                progress.count(MigrationStatus::Migrated),
+                progress.count(MigrationStatus::Pending),
+                progress.count(MigrationStatus::Skipped)
+            );
+
+            let mut items: Vec<String> = self
+                .store
+                .features
+                .iter()
+                .map(|f| {
+                    let status = progress.status(f).unwrap_or(MigrationStatus::Pending);
+                    format!("{}  {} ({})", status.label(), f.name, f.feature)
+                })
+                .collect();
+            items.push("Finish salt change".to_string());
+            items.push("Abandon salt change".to_string());
+            items.push("Back (progress is saved)".to_string());
+
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Select a feature to migrate")
+                .items(&items)
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+            let count = self.store.features.len();
+            if selection < count {
+                self.migrate_feature(selection)?;
+            } else if selection == count {
+                if self.finish_salt_change()? {
+                    return Ok(());
+                }
+            } else if selection == count + 1 {
+                if self.abandon_salt_change()? {
+                    return Ok(());
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Start a salt change, or resume the one in progress, asking for the new salt
+    ///
+    /// Returns `false` if there is nothing to continue with.
+    fn begin_salt_change(&mut self) -> io::Result<bool> {
+        let salt = self.salt.as_ref().unwrap().value().to_string();
+        let (progress, new_salt) = match self.storage.salt_change()? {
+            Some(progress) => {
+                if !SaltVerifier::matches(&salt, &progress.old_verifier) {
+                    println!(
+                        "⚠️  A salt change is in progress, but this session did not start with \
+                         the old salt. Restart SaltPass with the old salt to continue it."
+                    );
+                    return Ok(false);
+                }
+                println!(
+                    "🔁 Resuming the salt change started on {}",
+                    progress.started.format("%Y-%m-%d %H:%M")
+                );
+                let new_salt = Self::read_secret("🔑 Enter the new master salt: ")?;
+                if !SaltVerifier::matches(&new_salt, &progress.new_verifier) {
+                    println!("❌ This is not the new salt the change was started with.");
+                    return Ok(false);
+                }
+                (progress, new_salt)
+            }
+            None => {
+                println!(
+                    "⚠️  Changing the master salt changes every password. Update each account \
+                     to its new password and mark it as migrated; until the change is \
+                     finished, keep starting SaltPass with the current salt."
+                );
+                let new_salt = Self::read_secret("🔑 Enter the new master salt: ")?;
+                let repeated = Self::read_secret("🔑 Repeat the new master salt: ")?;
+                if new_salt != repeated {
+                    println!("❌ The salts do not match.");
+                    return Ok(false);
+                }
+                if new_salt == salt {
+                    println!("⚠️  The new salt is the same as the current one.");
+                    return Ok(false);
+                }
+                let progress = SaltChange::new(&salt, &new_salt, &self.store);
+                self.storage.set_salt_change(&progress)?;
+                (progress, new_salt)
+            }
+        };
+
+        self.migration = Some(Migration {
+            progress,
+            new_salt: Salt::new(new_salt),
+        });
+        Ok(true)
+    }
+
+    /// Show a feature's old and new passwords and update its migration status
+    fn migrate_feature(&mut self, index: usize) -> io::Result<()> {
+        let feature = self.store.features[index].clone();
+        let length = match feature.length {
+            Some(length) => length,
+            None => self.ask_length(self.config.defaults.length)?,
+        };
+
+        let migration = self.migration.as_ref().expect("salt change started");
+        let (Some(old), Some(new)) = (
+            derive_password(&feature, self.salt.as_ref().unwrap(), length),
+            derive_password(&feature, &migration.new_salt, length),
+        ) else {
+            return Ok(());
+        };
+
+        println!("\n🔁 {} ({})", feature.name, feature.feature);
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        if self.config.display.show_password {
+            println!("Old password: {}", old.as_str());
+            println!("New password: {}", new.as_str());
+        }
+        println!(
+            "Status: {}",
+            migration
+                .progress
+                .status(&feature)
+                .unwrap_or(MigrationStatus::Pending)
+                .label()
+        );
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+
+        let status = loop {
+            let action = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Change the password on the account, then")
+                .items(&[
+                    "Copy old password",
+                    "Copy new password",
+                    "Mark as migrated",
+                    "Skip (do not migrate)",
+                    "Mark as pending",
+                    "Back",
+                ])
+                .default(0)
+                .interact()
+                .map_err(io::Error::other)?;
+            match action {
+                0 => self.copy_password(old.clone()),
+                1 => self.copy_password(new.clone()),
+                2 => break MigrationStatus::Migrated,
+                3 => break MigrationStatus::Skipped,
+                4 => break MigrationStatus::Pending,
+                _ => return Ok(()),
+            }
+        };
+
+        let migration = self.migration.as_mut().expect("salt change started");
+        migration.progress.set_status(&feature, status);
+        self.storage.set_salt_change(&migration.progress)?;
+        if status == MigrationStatus::Migrated {
+            self.mark_rotated(&feature)?;
+        }
+        println!("{}  {}", status.label(), feature.name);
+        Ok(())
+    }
+
+    /// Print every feature with its migration status
+    fn print_salt_report(progress: &SaltChange) {
+        println!("\n📋 Salt Change Report:");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        for p in &progress.features {
+            println!("{}  {} ({})", p.status.label(), p.name, p.feature);
+        }
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        println!(
+            "{} migrated, {} skipped, {} pending",
+            progress.count(MigrationStatus::Migrated),
+            progress.count(MigrationStatus::Skipped),
+            progress.count(MigrationStatus::Pending)
+        );
+    }
+
+    /// Switch to the new salt once no feature is pending
+    ///
+    /// Returns `true` if the change was finished.
+    fn finish_salt_change(&mut self) -> io::Result<bool> {
+        let migration = self.migration.as_ref().expect("salt change started");
+        Self::print_salt_report(&migration.progress);
+        if !migration.progress.is_complete() {
+            println!("⚠️  Migrate or skip the pending features first.");
+            return Ok(false);
+        }
+
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Switch to the new master salt? From now on, start SaltPass with it.")
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
+        if !confirmed {
+            return Ok(false);
+        }
+
+        let new_salt = migration.new_salt.value().to_string();
+        if let Err(index) = self.storage.rekey_audit(audit::derive_key(&new_salt))? {
+            println!(
+                "❌ The audit log's hash chain is broken at record {}, so it cannot be moved to \
+                 the new salt. Check it with \"View Audit Log\" and move {} aside to start a \
+                 new log.",
+                index + 1,
+                self.storage.sidecar("audit").display()
+            );
+            return Ok(false);
+        }
+        if self.storage.is_encrypted() {
+            self.storage.reencrypt(new_salt.clone())?;
+            println!("🔐 Store re-encrypted under the new salt");
+        } else if self.storage.supports_signature() {
+            self.storage
+                .set_signature_key(signature::derive_key(&new_salt), self.config.storage.sign);
+            if self.config.storage.sign || self.store.signature.is_some() {
+                let store = self.storage.load_unverified()?;
+                self.storage.resign(&store)?;
+                self.store = store;
+                println!("🛡️  Store re-signed with the new salt");
+            }
+        }
+
+        if let Some(name) = &self.profile {
+            let mut profiles = Profiles::load()?;
+            if let Some(profile) = profiles.profiles.get_mut(name)
+                && profile.salt_verifier.is_some()
+            {
+                profile.salt_verifier = Some(SaltVerifier::compute(&new_salt));
+                profiles.save()?;
+            }
+        }
+
+        // Syncing uses a vault derived from the salt, the next sync starts a new one
+        match std::fs::remove_file(self.storage.sidecar("sync")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.storage.clear_salt_change()?;
+        let migration = self.migration.take().expect("salt change started");
+        self.salt = Some(migration.new_salt);
+        println!("✅ Master salt changed. Start SaltPass with the new salt from now on.");
+        Ok(true)
+    }
+
+    /// Drop the salt change progress and keep the old salt
+    fn abandon_salt_change(&mut self) -> io::Result<bool> {
+        let confirmed = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(
+                "Abandon the salt change? The progress is lost and the current salt stays in use.",
+            )
+            .default(false)
             .interact()
             .map_err(io::Error::other)?;
+        if confirmed {
+            self.storage.clear_salt_change()?;
+            self.migration = None;
+            println!("↩️  Salt change abandoned.");
+        }
+        Ok(confirmed)
+    }
 
-        let feature_name = self.store.list_features()[selection].name.clone();
-        self.store.remove_feature(selection);
-        self.storage.save(&self.store)?;
+    /// Write a PDF with everything needed to regenerate the passwords by hand
+    fn emergency_kit(&self) -> io::Result<()> {
+        let path: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Save the PDF to")
+            .default("saltpass-emergency-kit.pdf".to_string())
+            .interact_text()
+            .map_err(io::Error::other)?;
+        let salt_lines = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Leave blank lines to write the master salt by hand?")
+            .default(false)
+            .interact()
+            .map_err(io::Error::other)?;
 
-        println!("🗑️  Feature '{}' deleted successfully!", feature_name);
+        let store_name = match &self.profile {
+            Some(profile) => format!("Profile '{}'", profile),
+            None => self.storage.file_path().display().to_string(),
+        };
+        let pdf = kit::render(
+            &self.store,
+            &KitOptions {
+                store_name,
+                length: self.config.defaults.length,
+                salt_lines,
+            },
+        );
+        std::fs::write(&path, pdf)?;
 
+        println!(
+            "✅ Emergency kit with {} feature(s) written to {}",
+            self.store.features.len(),
+            path
+        );
+        println!("💡 Print it and keep it somewhere safe, then delete the file.");
         Ok(())
     }
 
@@ -630,3 +3117,29 @@ impl Cli {
         Ok(())
     }
 }
+
+/// Derive a feature's password, printing why it can't be if its stored parameters are
+/// invalid
+fn derive_password(feature: &Feature, salt: &Salt, length: usize) -> Option<Zeroizing<String>> {
+    match feature.password(salt, length) {
+        Ok(password) => Some(Zeroizing::new(password)),
+        Err(e) => {
+            println!("❌ {}", e);
+            None
+        }
+    }
+}
+
+/// Emphasize the characters of `text` at `positions`, as matched by the fuzzy search
+fn highlight(text: &str, positions: &[usize]) -> String {
+    text.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if positions.contains(&i) {
+                style(c).cyan().bold().to_string()
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
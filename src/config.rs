@@ -0,0 +1,313 @@
+//! User configuration for SaltPass
+//!
+//! Settings are read from `~/.saltpass/config.toml` at startup. Every setting is
+//! optional; missing ones fall back to the built-in defaults, and storage settings that
+//! are present replace the corresponding interactive prompts.
+//!
+//! ```toml
+//! [defaults]
+//! algorithm = "Argon2id"
+//! length = 20
+//! normalization = "registrable"
+//!
+//! [defaults.params]
+//! memory_kib = 65536
+//! iterations = 3
+//! parallelism = 2
+//!
+//! [storage]
+//! path = "~/Sync/saltpass/features.toml"
+//! format = "toml"
+//! encrypted = false
+//! sign = true
+//! on_tamper = "refuse"
+//!
+//! [clipboard]
+//! copy = true
+//! clear_after_secs = 30
+//!
+//! [display]
+//! show_password = true
+//! show_hints = true
+//!
+//! [sync]
+//! url = "http://192.168.1.10:7878"
+//!
+//! [trash]
+//! purge_after_days = 30
+//! ```
+
+use crate::crypto::{Algorithm, KdfParams};
+use crate::domain::Normalization;
+use crate::storage::{Storage, StorageFormat};
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Top-level configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub defaults: Defaults,
+    pub storage: StorageConfig,
+    pub clipboard: ClipboardConfig,
+    pub display: DisplayConfig,
+    pub sync: SyncConfig,
+    pub trash: TrashConfig,
+}
+
+/// Defaults applied to newly added features and generated passwords
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Defaults {
+    pub algorithm: Algorithm,
+    /// Parameters for `algorithm`; unset fields keep the algorithm's defaults
+    pub params: Option<PartialParams>,
+    pub length: usize,
+    /// Suggested normalization for identifiers that are URLs or domains
+    pub normalization: Normalization,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            params: None,
+            length: 16,
+            normalization: Normalization::Host,
+        }
+    }
+}
+
+impl Defaults {
+    /// Parameters to use for a new feature with the given algorithm
+    ///
+    /// The configured parameters only apply to the configured default algorithm.
+    pub fn params_for(&self, algo: Algorithm) -> KdfParams {
+        let base = algo.default_params();
+        match &self.params {
+            Some(params) if algo == self.algorithm => params.apply(base),
+            _ => base,
+        }
+    }
+}
+
+/// Algorithm parameters where every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PartialParams {
+    pub memory_kib: Option<u32>,
+    pub iterations: Option<u32>,
+    pub parallelism: Option<u32>,
+    pub log_n: Option<u8>,
+}
+
+impl PartialParams {
+    fn apply(&self, base: KdfParams) -> KdfParams {
+        KdfParams {
+            memory_kib: self.memory_kib.unwrap_or(base.memory_kib),
+            iterations: self.iterations.unwrap_or(base.iterations),
+            parallelism: self.parallelism.unwrap_or(base.parallelism),
+            log_n: self.log_n.unwrap_or(base.log_n),
+        }
+    }
+}
+
+/// Where and how the features file is stored
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Explicit features file, `~/` is expanded to the home directory
+    pub path: Option<PathBuf>,
+    pub format: Option<StorageFormat>,
+    pub encrypted: Option<bool>,
+    /// Sign plain stores so edits made outside SaltPass are detected, see
+    /// [`crate::signature`]
+    pub sign: bool,
+    /// What to do when a store does not match its signature, or lacks one it should have
+    pub on_tamper: TamperPolicy,
+}
+
+/// Reaction to a plain store that does not match its signature, see
+/// [`crate::storage::SignatureMismatch`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TamperPolicy {
+    /// Refuse to open the store unless the user re-signs it
+    #[default]
+    Refuse,
+    /// Open the store with a warning; writes still fail until it is re-signed
+    Warn,
+}
+
+/// Clipboard behavior after generating a password
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// Copy generated passwords to the clipboard
+    pub copy: bool,
+    /// Clear the clipboard after this many seconds if it still holds the password
+    pub clear_after_secs: Option<u64>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            copy: true,
+            clear_after_secs: None,
+        }
+    }
+}
+
+/// What is printed to the terminal
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Print generated passwords (otherwise they are only copied)
+    pub show_password: bool,
+    /// Show feature hints in lists and selections
+    pub show_hints: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            show_password: true,
+            show_hints: true,
+        }
+    }
+}
+
+/// Server used by "Sync with Server", see [`crate::sync`]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub url: Option<String>,
+}
+
+/// Deleted features kept in the store's trash
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Purge trash entries older than this many days at startup (never if unset)
+    pub purge_after_days: Option<u32>,
+}
+
+impl Config {
+    /// Location of the configuration file
+    pub fn path() -> io::Result<PathBuf> {
+        Ok(Storage::config_dir()?.join("config.toml"))
+    }
+
+    /// Load `~/.saltpass/config.toml`, or the defaults if it does not exist
+    pub fn load() -> io::Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut config: Config = toml::from_str(&content).map_err(|e| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid config file {}: {}", path.display(), e),
+            )
+        })?;
+
+        let algo = config.defaults.algorithm;
+        config
+            .defaults
+            .params_for(algo)
+            .validate(algo)
+            .map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid config file {}: {}", path.display(), e),
+                )
+            })?;
+        config.defaults.length = config.defaults.length.clamp(12, 64);
+        config.storage.path = config.storage.path.map(expand_home);
+
+        Ok(config)
+    }
+}
+
+/// Expand a leading `~` to the home directory
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let path = std::env::temp_dir().join("saltpass_test_config.toml");
+        fs::write(
+            &path,
+            r#"
+[defaults]
+algorithm = "Pbkdf2"
+length = 100
+
+[defaults.params]
+iterations = 5000
+
+[storage]
+format = "json"
+encrypted = true
+on_tamper = "warn"
+
+[display]
+show_password = false
+
+[trash]
+purge_after_days = 7
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.defaults.algorithm, Algorithm::Pbkdf2);
+        assert_eq!(config.defaults.length, 64);
+        assert_eq!(
+            config.defaults.params_for(Algorithm::Pbkdf2).iterations,
+            5000
+        );
+        assert_eq!(
+            config.defaults.params_for(Algorithm::Scrypt),
+            Algorithm::Scrypt.default_params()
+        );
+        assert!(matches!(config.storage.format, Some(StorageFormat::Json)));
+        assert_eq!(config.storage.encrypted, Some(true));
+        assert!(!config.storage.sign);
+        assert_eq!(config.storage.on_tamper, TamperPolicy::Warn);
+        assert!(config.clipboard.copy);
+        assert!(!config.display.show_password);
+        assert_eq!(config.trash.purge_after_days, Some(7));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        let path = std::env::temp_dir().join("saltpass_test_config_invalid.toml");
+        fs::write(
+            &path,
+            "[defaults]\nalgorithm = \"Argon2id\"\n[defaults.params]\nmemory_kib = 1\n",
+        )
+        .unwrap();
+
+        assert!(Config::load_from(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
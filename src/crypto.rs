@@ -10,9 +10,23 @@ use aes_gcm::{
 use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
+use std::borrow::Cow;
+use zeroize::Zeroizing;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// Derived 256-bit storage key, zeroed when dropped
+pub type StorageKey = Zeroizing<[u8; 32]>;
+
+/// Generate `bytes` random bytes from the OS RNG, hex-encoded
+pub fn random_hex(bytes: usize) -> String {
+    use aes_gcm::aead::rand_core::RngCore;
+
+    let mut buf = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buf);
+    hex::encode(buf)
+}
+
 /// Password generation algorithm
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum Algorithm {
@@ -49,6 +63,93 @@ impl Algorithm {
             Algorithm::Scrypt,
         ]
     }
+
+    /// Cost parameters used when a feature does not store its own
+    pub fn default_params(&self) -> KdfParams {
+        match self {
+            Algorithm::HmacSha256 => KdfParams::default(),
+            Algorithm::Argon2i | Algorithm::Argon2id => KdfParams {
+                memory_kib: 65536,
+                iterations: 2,
+                parallelism: 2,
+                ..KdfParams::default()
+            },
+            Algorithm::Pbkdf2 => KdfParams {
+                iterations: 10000,
+                ..KdfParams::default()
+            },
+            Algorithm::Scrypt => KdfParams {
+                log_n: 15,
+                parallelism: 1,
+                ..KdfParams::default()
+            },
+        }
+    }
+}
+
+/// Cost parameters for the key derivation algorithms
+///
+/// Each algorithm only reads the fields it uses; HMAC-SHA256 has no parameters.
+/// Changing any parameter used by an algorithm changes the generated password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KdfParams {
+    /// Argon2 memory cost in KiB
+    pub memory_kib: u32,
+    /// Argon2 passes or PBKDF2 iterations
+    pub iterations: u32,
+    /// Argon2 lanes or Scrypt parallelization
+    pub parallelism: u32,
+    /// Scrypt CPU/memory cost as log2(N)
+    pub log_n: u8,
+}
+
+impl KdfParams {
+    /// Check that the parameters are accepted by the given algorithm
+    pub fn validate(&self, algo: Algorithm) -> Result<(), String> {
+        match algo {
+            Algorithm::HmacSha256 => Ok(()),
+            Algorithm::Argon2i | Algorithm::Argon2id => {
+                argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+                    .map(|_| ())
+                    .map_err(|e| format!("Invalid Argon2 parameters: {}", e))
+            }
+            Algorithm::Pbkdf2 if self.iterations == 0 => {
+                Err("Invalid PBKDF2 parameters: iterations must be at least 1".to_string())
+            }
+            Algorithm::Pbkdf2 => Ok(()),
+            Algorithm::Scrypt => scrypt::Params::new(self.log_n, 8, self.parallelism, 32)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid Scrypt parameters: {}", e)),
+        }
+    }
+
+    /// The parameters used by the given algorithm, e.g. "N = 2^15, r = 8, p = 1"
+    pub fn describe(&self, algo: Algorithm) -> String {
+        match algo {
+            Algorithm::HmacSha256 => "none".to_string(),
+            Algorithm::Argon2i | Algorithm::Argon2id => format!(
+                "memory {} KiB, {} passes, {} lanes, version 0x13",
+                self.memory_kib, self.iterations, self.parallelism
+            ),
+            Algorithm::Pbkdf2 => format!("{} iterations", self.iterations),
+            Algorithm::Scrypt => format!("N = 2^{}, r = 8, p = {}", self.log_n, self.parallelism),
+        }
+    }
+}
+
+/// The canonical derivation input for an identifier and an optional login
+///
+/// Without a login (or with an empty one) this is the identifier itself, so existing
+/// passwords are unchanged. With a login it is the decimal byte length of the identifier,
+/// `:`, the identifier, a zero byte and the login: the zero byte keeps it apart from any
+/// identifier typed on its own, and the length prefix from every other identifier and
+/// login pair.
+pub fn derivation_input<'a>(identifier: &'a str, login: Option<&str>) -> Cow<'a, str> {
+    match login.filter(|login| !login.is_empty()) {
+        None => Cow::Borrowed(identifier),
+        Some(login) => Cow::Owned(format!("{}:{}\0{}", identifier.len(), identifier, login)),
+    }
 }
 
 /// Password generator using any hash algorithm
@@ -72,66 +173,108 @@ impl PasswordGenerator {
     /// ```
     /// use SaltPass::crypto::{PasswordGenerator, Algorithm};
     ///
-    /// let password = PasswordGenerator::generate_with_algo("my-secret-salt", "github.com", 16, Algorithm::HmacSha256);
+    /// let password = PasswordGenerator::generate_with_algo("my-secret-salt", "github.com", 16, Algorithm::HmacSha256).unwrap();
     /// assert_eq!(password.len(), 16);
     /// ```
     #[allow(dead_code)]
     pub fn generate(salt: &str, feature: &str, length: usize) -> String {
         Self::generate_with_algo(salt, feature, length, Algorithm::HmacSha256)
+            .expect("HMAC-SHA256 accepts any salt")
     }
 
     /// Generate a password using a specific algorithm
-    pub fn generate_with_algo(salt: &str, feature: &str, length: usize, algo: Algorithm) -> String {
+    pub fn generate_with_algo(
+        salt: &str,
+        feature: &str,
+        length: usize,
+        algo: Algorithm,
+    ) -> Result<String, String> {
+        Self::generate_with_params(salt, feature, length, algo, &algo.default_params())
+    }
+
+    /// Generate a password using a specific algorithm and cost parameters
+    ///
+    /// Fails if the parameters don't pass [`KdfParams::validate`], as when a store was
+    /// edited by hand, or if the algorithm rejects the salt (Argon2 needs 8 bytes).
+    pub fn generate_with_params(
+        salt: &str,
+        feature: &str,
+        length: usize,
+        algo: Algorithm,
+        params: &KdfParams,
+    ) -> Result<String, String> {
+        params.validate(algo)?;
         let bytes = match algo {
             Algorithm::HmacSha256 => Self::derive_hmac_sha256(salt, feature),
-            Algorithm::Argon2i => Self::derive_argon2(salt, feature, argon2::Algorithm::Argon2i),
-            Algorithm::Argon2id => Self::derive_argon2(salt, feature, argon2::Algorithm::Argon2id),
-            Algorithm::Pbkdf2 => Self::derive_pbkdf2(salt, feature),
-            Algorithm::Scrypt => Self::derive_scrypt(salt, feature),
-        };
+            Algorithm::Argon2i => {
+                Self::derive_argon2(salt, feature, argon2::Algorithm::Argon2i, params)
+            }
+            Algorithm::Argon2id => {
+                Self::derive_argon2(salt, feature, argon2::Algorithm::Argon2id, params)
+            }
+            Algorithm::Pbkdf2 => Self::derive_pbkdf2(salt, feature, params),
+            Algorithm::Scrypt => Self::derive_scrypt(salt, feature, params),
+        }?;
 
         let base64_encoded =
             base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
 
-        Self::format_password(&base64_encoded, length)
+        Ok(Self::format_password(&base64_encoded, length))
     }
 
-    fn derive_hmac_sha256(salt: &str, feature: &str) -> [u8; 32] {
+    fn derive_hmac_sha256(salt: &str, feature: &str) -> Result<[u8; 32], String> {
         let mut mac = <HmacSha256 as hmac::Mac>::new_from_slice(salt.as_bytes())
             .expect("HMAC can take key of any size");
         mac.update(feature.as_bytes());
         let result = mac.finalize();
-        *result.into_bytes().as_ref()
+        Ok(*result.into_bytes().as_ref())
     }
 
-    fn derive_argon2(salt: &str, feature: &str, alg: argon2::Algorithm) -> [u8; 32] {
+    fn derive_argon2(
+        salt: &str,
+        feature: &str,
+        alg: argon2::Algorithm,
+        params: &KdfParams,
+    ) -> Result<[u8; 32], String> {
         use argon2::{Argon2, Params, Version};
-        let params = Params::new(65536, 2, 2, None).unwrap();
+        let params = Params::new(
+            params.memory_kib,
+            params.iterations,
+            params.parallelism,
+            None,
+        )
+        .expect("parameters were validated");
         let argon2 = Argon2::new(alg, Version::V0x13, params);
         let mut output = [0u8; 32];
         argon2
             .hash_password_into(feature.as_bytes(), salt.as_bytes(), &mut output)
-            .unwrap();
-        output
+            .map_err(|e| format!("Argon2 failed: {}", e))?;
+        Ok(output)
     }
 
-    fn derive_pbkdf2(salt: &str, feature: &str) -> [u8; 32] {
+    fn derive_pbkdf2(salt: &str, feature: &str, params: &KdfParams) -> Result<[u8; 32], String> {
         use pbkdf2::pbkdf2_hmac;
         use sha2::Sha256;
 
         let mut output = [0u8; 32];
-        pbkdf2_hmac::<Sha256>(feature.as_bytes(), salt.as_bytes(), 10000, &mut output);
-        output
+        pbkdf2_hmac::<Sha256>(
+            feature.as_bytes(),
+            salt.as_bytes(),
+            params.iterations,
+            &mut output,
+        );
+        Ok(output)
     }
 
-    fn derive_scrypt(salt: &str, feature: &str) -> [u8; 32] {
+    fn derive_scrypt(salt: &str, feature: &str, params: &KdfParams) -> Result<[u8; 32], String> {
         use scrypt::{Params, scrypt};
 
         // Params::new(log_n, r, p, output_length)
-        let params = Params::new(15, 8, 1, 32).unwrap();
+        let params = Params::new(params.log_n, 8, params.parallelism, 32)
+            .expect("parameters were validated");
         let mut output = [0u8; 32];
         scrypt(feature.as_bytes(), salt.as_bytes(), &params, &mut output).expect("scrypt failed");
-        output
+        Ok(output)
     }
 
     fn format_password(raw: &str, length: usize) -> String {
@@ -204,6 +347,35 @@ impl PasswordGenerator {
     }
 }
 
+/// Short check value used to detect a mistyped master salt
+///
+/// Only 4 bytes of a slow PBKDF2 hash are kept: enough to catch typos, while leaving
+/// billions of false positives for anyone trying to recover the salt from it.
+pub struct SaltVerifier;
+
+impl SaltVerifier {
+    const VERIFIER_SIZE: usize = 4;
+
+    /// Compute the hex-encoded verifier of a salt
+    pub fn compute(salt: &str) -> String {
+        use pbkdf2::pbkdf2_hmac;
+
+        let mut hash = [0u8; 32];
+        pbkdf2_hmac::<Sha256>(
+            salt.as_bytes(),
+            b"SaltPass-Salt-Verifier",
+            100_000,
+            &mut hash,
+        );
+        hex::encode(&hash[..Self::VERIFIER_SIZE])
+    }
+
+    /// Check a salt against a stored verifier
+    pub fn matches(salt: &str, verifier: &str) -> bool {
+        Self::compute(salt).eq_ignore_ascii_case(verifier)
+    }
+}
+
 /// AES-256-GCM encryption for feature storage
 ///
 /// Provides authenticated encryption for secure data persistence.
@@ -214,12 +386,15 @@ impl StorageCipher {
     const KEY_SIZE: usize = 32;
 
     /// Derive a 256-bit encryption key from a password using PBKDF2
-    fn derive_key(password: &str) -> [u8; Self::KEY_SIZE] {
+    ///
+    /// Derivation is deliberately slow; derive once and use [`StorageCipher::encrypt_with_key`]
+    /// when encrypting many small records.
+    pub fn derive_key(password: &str) -> StorageKey {
         use pbkdf2::pbkdf2_hmac;
 
         let salt = b"SaltPass-Storage-Key"; // Fixed salt for key derivation
-        let mut key = [0u8; Self::KEY_SIZE];
-        pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, 100_000, &mut key);
+        let mut key = Zeroizing::new([0u8; Self::KEY_SIZE]);
+        pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, 100_000, key.as_mut());
         key
     }
 
@@ -234,8 +409,12 @@ impl StorageCipher {
     ///
     /// Base64-encoded ciphertext with nonce prepended (nonce || ciphertext || tag)
     pub fn encrypt(password: &str, plaintext: &[u8]) -> Result<String, String> {
-        let key = Self::derive_key(password);
-        let cipher = Aes256Gcm::new(&key.into());
+        Self::encrypt_with_key(&Self::derive_key(password), plaintext)
+    }
+
+    /// Encrypt data with an already derived key, see [`StorageCipher::encrypt`]
+    pub fn encrypt_with_key(key: &StorageKey, plaintext: &[u8]) -> Result<String, String> {
+        let cipher = Aes256Gcm::new(key.as_ref().into());
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
 
         cipher
@@ -249,6 +428,17 @@ impl StorageCipher {
             .map_err(|e| format!("Encryption failed: {}", e))
     }
 
+    /// Check whether file content looks like the output of [`StorageCipher::encrypt`]
+    ///
+    /// Plain TOML and JSON stores never decode as base64, so this can be used to sniff
+    /// the storage mode without knowing the password.
+    pub fn looks_encrypted(content: &str) -> bool {
+        let content = content.trim();
+        !content.is_empty()
+            && base64::Engine::decode(&base64::engine::general_purpose::STANDARD, content)
+                .is_ok_and(|data| data.len() >= Self::NONCE_SIZE + 16)
+    }
+
     /// Decrypt data using AES-256-GCM
     ///
     /// # Arguments
@@ -260,8 +450,12 @@ impl StorageCipher {
     ///
     /// Decrypted plaintext
     pub fn decrypt(password: &str, encoded: &str) -> Result<Vec<u8>, String> {
-        let key = Self::derive_key(password);
-        let cipher = Aes256Gcm::new(&key.into());
+        Self::decrypt_with_key(&Self::derive_key(password), encoded)
+    }
+
+    /// Decrypt data with an already derived key, see [`StorageCipher::decrypt`]
+    pub fn decrypt_with_key(key: &StorageKey, encoded: &str) -> Result<Vec<u8>, String> {
+        let cipher = Aes256Gcm::new(key.as_ref().into());
 
         let data = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded)
             .map_err(|e| format!("Base64 decode failed: {}", e))?;
@@ -294,6 +488,48 @@ mod tests {
         assert_eq!(pwd1, pwd2, "Same inputs should produce same password");
     }
 
+    #[test]
+    fn test_login_in_derivation_input() {
+        assert_eq!(derivation_input("github.com", None), "github.com");
+        assert_eq!(derivation_input("github.com", Some("")), "github.com");
+        assert_eq!(
+            derivation_input("github.com", Some("work")),
+            "10:github.com\0work"
+        );
+        // Moving characters between the identifier and the login changes the input
+        assert_ne!(
+            derivation_input("ab", Some("c")),
+            derivation_input("a", Some("bc"))
+        );
+
+        let params = KdfParams {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+            log_n: 4,
+        };
+        for &algo in Algorithm::all() {
+            let generate = |login: Option<&str>| {
+                let input = derivation_input("github.com", login);
+                PasswordGenerator::generate_with_params("my-master-salt", &input, 16, algo, &params)
+                    .unwrap()
+            };
+            assert_eq!(
+                generate(None),
+                PasswordGenerator::generate_with_params(
+                    "my-master-salt",
+                    "github.com",
+                    16,
+                    algo,
+                    &params
+                )
+                .unwrap()
+            );
+            assert_ne!(generate(Some("work")), generate(None));
+            assert_ne!(generate(Some("work")), generate(Some("home")));
+        }
+    }
+
     #[test]
     fn test_different_features() {
         let salt = "my-secret-salt";
@@ -307,6 +543,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_default_params_match_legacy_generation() {
+        // Pinned output: stored features must keep producing the same passwords
+        let pwd = PasswordGenerator::generate_with_params(
+            "salt",
+            "github.com",
+            16,
+            Algorithm::Pbkdf2,
+            &Algorithm::Pbkdf2.default_params(),
+        )
+        .unwrap();
+        assert_eq!(pwd, "hX!0sMAcRF0dG1QR");
+
+        let cheaper = KdfParams {
+            iterations: 1000,
+            ..Algorithm::Pbkdf2.default_params()
+        };
+        assert!(cheaper.validate(Algorithm::Pbkdf2).is_ok());
+        assert_ne!(
+            pwd,
+            PasswordGenerator::generate_with_params(
+                "salt",
+                "github.com",
+                16,
+                Algorithm::Pbkdf2,
+                &cheaper
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        let params = KdfParams {
+            memory_kib: 1,
+            ..Algorithm::Argon2id.default_params()
+        };
+        assert!(params.validate(Algorithm::Argon2id).is_err());
+        for algo in Algorithm::all() {
+            assert!(algo.default_params().validate(*algo).is_ok());
+        }
+
+        // Parameters read from a store are checked again when generating
+        let generate = |salt, algo, params: &KdfParams| {
+            PasswordGenerator::generate_with_params(salt, "github.com", 16, algo, params)
+        };
+        assert!(generate("my-master-salt", Algorithm::Argon2id, &params).is_err());
+        let scrypt = KdfParams {
+            log_n: 64,
+            ..Algorithm::Scrypt.default_params()
+        };
+        assert!(generate("my-master-salt", Algorithm::Scrypt, &scrypt).is_err());
+        // Argon2 needs a salt of 8 bytes or more
+        let argon2 = Algorithm::Argon2id.default_params();
+        assert!(generate("short", Algorithm::Argon2id, &argon2).is_err());
+    }
+
+    #[test]
+    fn test_salt_verifier() {
+        let verifier = SaltVerifier::compute("my-secret-salt");
+        assert_eq!(verifier.len(), 8);
+        assert!(SaltVerifier::matches("my-secret-salt", &verifier));
+        assert!(!SaltVerifier::matches("my-secret-sal", &verifier));
+    }
+
     #[test]
     fn test_different_salts() {
         let feature = "github.com";
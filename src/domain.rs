@@ -0,0 +1,260 @@
+//! Canonical identifiers for websites
+//!
+//! `github.com`, `GitHub.com` and `https://www.github.com/login` all name the same site,
+//! but as identifiers they derive different passwords. When a feature is added its
+//! identifier can be normalized to the bare host or further to the registrable domain
+//! (`gist.github.com` → `github.com`), found with the public suffix list embedded from
+//! `data/public_suffix_list.dat`. The chosen [`Normalization`] is recorded on the feature,
+//! so lookups by URL normalize the same way while its stored identifier, and with it the
+//! password, never changes.
+//!
+//! Some services share one login across several domains. A feature can list those as
+//! aliases, and [`EquivalentDomains`] suggests them from a bundled list of known groups.
+
+use crate::storage::Storage;
+use publicsuffix::{List, Psl};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::net::Ipv4Addr;
+use std::sync::OnceLock;
+
+/// The public suffix list from <https://publicsuffix.org>, updated with SaltPass releases
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+/// Groups of domains known to share one login, updated with SaltPass releases
+const EQUIVALENT_DOMAINS: &str = include_str!("../data/equivalent_domains.txt");
+
+/// How an identifier was normalized when its feature was added
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Kept exactly as typed
+    #[default]
+    Exact,
+    /// Lowercased host without scheme, credentials, port, path or leading `www.`
+    Host,
+    /// Registrable domain of the host, e.g. `github.com` for `gist.github.com`
+    Registrable,
+}
+
+impl Normalization {
+    pub fn all() -> &'static [Normalization] {
+        &[
+            Normalization::Host,
+            Normalization::Registrable,
+            Normalization::Exact,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Normalization::Exact => "as typed",
+            Normalization::Host => "host",
+            Normalization::Registrable => "registrable domain",
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        *self == Normalization::Exact
+    }
+}
+
+fn list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("embedded public suffix list is valid")
+    })
+}
+
+/// The host named by a URL or domain, lowercased and without `www.`
+///
+/// Returns `None` if `input` doesn't look like a URL or domain name, such as an identifier
+/// with spaces or without any dot. Without a scheme, the name must also end in a known
+/// public suffix or be an IPv4 address, so `github.com-work` stays an identifier.
+pub fn host(input: &str) -> Option<String> {
+    let input = input.trim();
+    let (scheme, rest) = match input.split_once("://") {
+        Some((_, rest)) => (true, rest),
+        None => (false, input),
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host,
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+
+    let valid = host.contains('.')
+        && host.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+    let known = || {
+        host.parse::<Ipv4Addr>().is_ok()
+            || list()
+                .suffix(host.as_bytes())
+                .is_some_and(|suffix| suffix.is_known())
+    };
+    if !valid || !(scheme || known()) {
+        return None;
+    }
+    match host.strip_prefix("www.") {
+        Some(rest) if rest.contains('.') => Some(rest.to_string()),
+        _ => Some(host),
+    }
+}
+
+/// The registrable domain of a host, or the host itself if it has none (an IP address
+/// or a bare public suffix)
+pub fn registrable(host: &str) -> String {
+    list()
+        .domain(host.as_bytes())
+        .filter(|domain| domain.suffix().is_known())
+        .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok())
+        .unwrap_or(host)
+        .to_string()
+}
+
+/// Normalize an identifier; identifiers that aren't URLs or domains are kept as they are
+pub fn normalize(input: &str, mode: Normalization) -> String {
+    let host = match mode {
+        Normalization::Exact => None,
+        Normalization::Host | Normalization::Registrable => host(input),
+    };
+    match (host, mode) {
+        (Some(host), Normalization::Registrable) => registrable(&host),
+        (Some(host), _) => host,
+        (None, _) => input.to_string(),
+    }
+}
+
+/// Groups of domains that share one login, such as `microsoft.com`, `live.com` and
+/// `outlook.com`
+///
+/// Groups are written one per line with the domains separated by spaces; `#` starts a
+/// comment.
+#[derive(Debug, Default)]
+pub struct EquivalentDomains {
+    groups: Vec<Vec<String>>,
+}
+
+impl EquivalentDomains {
+    pub fn parse(text: &str) -> Self {
+        let groups = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .map(|line| {
+                line.split_whitespace()
+                    .map(str::to_lowercase)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|group| group.len() > 1)
+            .collect();
+        Self { groups }
+    }
+
+    /// The groups bundled with SaltPass
+    pub fn bundled() -> Self {
+        Self::parse(EQUIVALENT_DOMAINS)
+    }
+
+    /// The bundled groups plus those in `~/.saltpass/equivalent_domains.txt`, if it exists
+    pub fn load() -> io::Result<Self> {
+        let mut domains = Self::bundled();
+        let path = Storage::config_dir()?.join("equivalent_domains.txt");
+        match fs::read_to_string(path) {
+            Ok(text) => domains.groups.extend(Self::parse(&text).groups),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(domains)
+    }
+
+    /// The other domains sharing a login with the site of `identifier`, in list order
+    pub fn equivalents(&self, identifier: &str) -> Vec<String> {
+        let Some(host) = host(identifier) else {
+            return Vec::new();
+        };
+        let site = registrable(&host);
+        let mut equivalents: Vec<String> = Vec::new();
+        for group in self.groups.iter().filter(|g| g.contains(&site)) {
+            for domain in group {
+                if *domain != host && !equivalents.contains(domain) {
+                    equivalents.push(domain.clone());
+                }
+            }
+        }
+        equivalents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host() {
+        for input in [
+            "github.com",
+            "GitHub.com",
+            "https://www.github.com/login",
+            "http://user:pw@github.com:8443/?q=1#top",
+            "github.com.",
+        ] {
+            assert_eq!(host(input).as_deref(), Some("github.com"), "{}", input);
+        }
+        assert_eq!(host("www.com").as_deref(), Some("www.com"));
+        assert_eq!(host("my bank"), None);
+        assert_eq!(host("localhost"), None);
+        assert_eq!(host("github.com-work"), None);
+        assert_eq!(host("https://NAS.local/").as_deref(), Some("nas.local"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let registrable = |input| normalize(input, Normalization::Registrable);
+        assert_eq!(registrable("https://gist.github.com/x"), "github.com");
+        assert_eq!(registrable("mail.example.co.uk"), "example.co.uk");
+        // Private suffixes keep separate sites apart
+        assert_eq!(registrable("octocat.github.io"), "octocat.github.io");
+        assert_eq!(registrable("192.168.1.10"), "192.168.1.10");
+
+        assert_eq!(
+            normalize("https://Gist.GitHub.com", Normalization::Host),
+            "gist.github.com"
+        );
+        assert_eq!(
+            normalize("https://GitHub.com", Normalization::Exact),
+            "https://GitHub.com"
+        );
+        assert_eq!(normalize("My Bank", Normalization::Host), "My Bank");
+    }
+
+    #[test]
+    fn test_equivalent_domains() {
+        let bundled = EquivalentDomains::bundled();
+        let microsoft = bundled.equivalents("https://login.live.com/");
+        assert!(microsoft.contains(&"outlook.com".to_string()));
+        assert!(microsoft.contains(&"live.com".to_string()));
+        assert!(
+            !bundled
+                .equivalents("live.com")
+                .contains(&"live.com".to_string())
+        );
+        assert!(bundled.equivalents("example.com").is_empty());
+        assert!(bundled.equivalents("My Bank").is_empty());
+
+        let custom = EquivalentDomains::parse(
+            "# comment\nExample.com example.net # same login\nlonely.com\nexample.org example.com\n",
+        );
+        assert_eq!(
+            custom.equivalents("www.example.com"),
+            ["example.net", "example.org"]
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! File storage backend
+//!
+//! Stores are kept in a single file: a JSON or TOML snapshot, optionally encrypted as a
+//! whole with AES-256-GCM, or an append-only operation log (see [`crate::oplog`]) whose
+//! entries are encrypted one by one.
+//!
+//! Writes are guarded by an advisory lock on a `<file>.lock` sidecar, and refuse to
+//! overwrite a file that was changed by another process since it was last loaded. Plain
+//! snapshots can be signed, see [`crate::signature`].
+
+use crate::backend::StorageBackend;
+use crate::crypto::{StorageCipher, StorageKey};
+use crate::models::{Feature, FeatureStore};
+use crate::storage::{self, SignatureMismatch, StorageFormat, StoreChanged, StoreLock};
+use crate::{migrations, oplog, signature};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+
+/// Content of the store file as last seen through a [`FileBackend`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Snapshot {
+    /// The file has not been read or written through this handle yet
+    Unknown,
+    /// The file did not exist
+    Missing,
+    /// SHA-256 of the raw file content
+    Hash([u8; 32]),
+}
+
+impl Snapshot {
+    fn of(content: &[u8]) -> Self {
+        Snapshot::Hash(Sha256::digest(content).into())
+    }
+}
+
+/// A store kept in a single file
+pub struct FileBackend {
+    path: PathBuf,
+    format: StorageFormat,
+    encrypted: bool,
+    password: Option<String>,
+    /// Key of the entries of an encrypted operation log, derived from `password`
+    record_key: Option<StorageKey>,
+    /// Device ID written to operation log entries, see [`oplog::local_device_id`]
+    device_id: Option<String>,
+    /// Key verifying and signing plain stores, see [`FileBackend::set_signature_key`]
+    signature_key: Option<StorageKey>,
+    /// Sign stores that are not signed yet
+    sign: bool,
+    snapshot: Cell<Snapshot>,
+    /// A valid signature was read or written through this handle, so an unsigned store
+    /// means its signature was removed
+    signed: Cell<bool>,
+    /// The store was loaded despite a bad signature, see [`FileBackend::load_unverified`]
+    tampered: Cell<bool>,
+}
+
+impl FileBackend {
+    pub fn new(path: PathBuf, format: StorageFormat, encrypted: bool) -> Self {
+        Self {
+            path,
+            format,
+            encrypted,
+            password: None,
+            record_key: None,
+            device_id: None,
+            signature_key: None,
+            sign: false,
+            snapshot: Cell::new(Snapshot::Unknown),
+            signed: Cell::new(false),
+            tampered: Cell::new(false),
+        }
+    }
+
+    /// Set the password of an encrypted store; `record_key` is derived from it and needed
+    /// for operation logs only
+    pub fn set_password(&mut self, password: String, record_key: Option<StorageKey>) {
+        self.password = Some(password);
+        self.record_key = record_key;
+    }
+
+    /// Verify signed stores with `key` and keep them signed on write, see
+    /// [`crate::storage::Storage::set_signature_key`]
+    pub fn set_signature_key(&mut self, key: StorageKey, sign: bool) {
+        self.signature_key = Some(key);
+        self.sign = sign;
+    }
+
+    pub fn set_device_id(&mut self, device_id: String) {
+        self.device_id = Some(device_id);
+    }
+
+    fn password(&self) -> io::Result<&str> {
+        self.password
+            .as_deref()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Encryption password not set"))
+    }
+
+    fn record_key(&self) -> io::Result<Option<&StorageKey>> {
+        if !self.encrypted {
+            return Ok(None);
+        }
+        self.password()?;
+        Ok(self.record_key.as_ref())
+    }
+
+    fn supports_signature(&self) -> bool {
+        self.format.supports_signature(self.encrypted)
+    }
+
+    fn read_snapshot(&self) -> io::Result<Snapshot> {
+        match fs::read(&self.path) {
+            Ok(content) => Ok(Snapshot::of(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Snapshot::Missing),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn changed(&self) -> io::Error {
+        io::Error::other(StoreChanged::new(self.path.clone()))
+    }
+
+    /// Current content of the file, without updating what this handle has seen
+    fn read_current(&self) -> io::Result<FeatureStore> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => self.parse_content(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(FeatureStore::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Apply a change on top of the current file content, see [`StorageBackend::put`]
+    ///
+    /// A handle that was up to date stays so; otherwise the next [`StorageBackend::save`]
+    /// still reports the concurrent modification.
+    fn update_current<T>(&self, change: impl FnOnce(&mut FeatureStore) -> T) -> io::Result<T> {
+        let _lock = StoreLock::acquire(&self.path)?;
+        let known = self.snapshot.get();
+        let in_sync = !self.has_changed()?;
+
+        let mut store = self.read_current()?;
+        let result = change(&mut store);
+        self.write(&store)?;
+        if !in_sync {
+            self.snapshot.set(known);
+        }
+        Ok(result)
+    }
+
+    /// Parse the file content, checking the signature of a signed plain store
+    fn parse_content(&self, content: &str) -> io::Result<FeatureStore> {
+        let store = self.parse_unverified(content)?;
+        self.check_signature(content, &store)?;
+        Ok(store)
+    }
+
+    /// Check the signature of plain store content
+    ///
+    /// A store without a signature is only accepted if signing is off and no signature was
+    /// seen through this handle before.
+    fn check_signature(&self, content: &str, store: &FeatureStore) -> io::Result<()> {
+        let Some(key) = self
+            .signature_key
+            .as_ref()
+            .filter(|_| self.supports_signature())
+        else {
+            return Ok(());
+        };
+        let valid = match store.signature {
+            Some(_) => {
+                let format = StorageFormat::sniff(content);
+                let version = migrations::schema_version(&format.document(content, None)?)?;
+                signature::verify(key, store, version)? != Some(false)
+            }
+            None => !(self.sign || self.signed.get()),
+        };
+        if !valid {
+            return Err(io::Error::other(SignatureMismatch::new(
+                self.path.clone(),
+                store.signature.is_none(),
+            )));
+        }
+        if store.signature.is_some() {
+            self.signed.set(true);
+        }
+        Ok(())
+    }
+
+    fn parse_unverified(&self, content: &str) -> io::Result<FeatureStore> {
+        if let StorageFormat::Log = self.format {
+            return StorageFormat::Log.parse(content, self.record_key()?);
+        }
+
+        if self.encrypted {
+            let decrypted = StorageCipher::decrypt(self.password()?, content)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            let decrypted_string = String::from_utf8(decrypted)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            StorageFormat::sniff(&decrypted_string).parse(&decrypted_string, None)
+        } else if StorageCipher::looks_encrypted(content) {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Storage file is encrypted but was opened as plain text",
+            ))
+        } else {
+            StorageFormat::sniff(content).parse(content, None)
+        }
+    }
+
+    /// Write the store to the file (lock already held)
+    fn write(&self, store: &FeatureStore) -> io::Result<()> {
+        if self.tampered.get() {
+            return Err(io::Error::other(SignatureMismatch::new(
+                self.path.clone(),
+                store.signature.is_none(),
+            )));
+        }
+        let store = match &self.signature_key {
+            Some(key)
+                if self.supports_signature()
+                    && (self.sign || self.signed.get() || store.signature.is_some()) =>
+            {
+                let mut signed = store.clone();
+                signed.signature = Some(signature::sign(key, store)?);
+                self.signed.set(true);
+                Cow::Owned(signed)
+            }
+            _ => Cow::Borrowed(store),
+        };
+        let store = store.as_ref();
+        let data = match self.format {
+            StorageFormat::Json => serde_json::to_vec_pretty(store)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            StorageFormat::Toml => toml::to_string_pretty(store)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+                .into_bytes(),
+            StorageFormat::Log => return self.append_log(store),
+            StorageFormat::Sqlite => unreachable!("databases are kept by SqliteBackend"),
+        };
+
+        let content = if self.encrypted {
+            StorageCipher::encrypt(self.password()?, &data)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        } else {
+            String::from_utf8(data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        };
+        self.replace_file(&content)
+    }
+
+    /// Append the operations turning the logged store into `store` (lock already held)
+    fn append_log(&self, store: &FeatureStore) -> io::Result<()> {
+        let key = self.record_key()?;
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = oplog::parse(&content, key)?;
+        let logged = migrations::migrate(oplog::fold(&entries)?)?;
+        let wanted =
+            serde_json::to_value(store).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        let ops = oplog::diff(&logged, &wanted);
+        if !ops.is_empty() {
+            let device = match &self.device_id {
+                Some(device) => device.clone(),
+                None => oplog::local_device_id()?,
+            };
+            let mut lines = String::new();
+            for entry in oplog::entries_for(&entries, &device, ops) {
+                lines.push_str(&oplog::render(&entry, key)?);
+                lines.push('\n');
+            }
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            file.write_all(lines.as_bytes())?;
+            file.sync_all()?;
+        }
+        self.snapshot.set(self.read_snapshot()?);
+        Ok(())
+    }
+
+    /// Atomically replace the store file (lock already held)
+    fn replace_file(&self, content: &str) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.snapshot.set(Snapshot::of(content.as_bytes()));
+        Ok(())
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn load(&self) -> io::Result<FeatureStore> {
+        if !self.path.exists() {
+            self.snapshot.set(Snapshot::Missing);
+            return Ok(FeatureStore::new());
+        }
+
+        let content = fs::read_to_string(&self.path)?;
+        self.snapshot.set(Snapshot::of(content.as_bytes()));
+        self.parse_content(&content)
+    }
+
+    fn load_unverified(&self) -> io::Result<FeatureStore> {
+        if !self.supports_signature() || !self.path.exists() {
+            return self.load();
+        }
+        let content = fs::read_to_string(&self.path)?;
+        self.snapshot.set(Snapshot::of(content.as_bytes()));
+        let store = StorageFormat::sniff(&content).parse(&content, None)?;
+        if let Err(e) = self.check_signature(&content, &store) {
+            if !storage::is_signature_mismatch(&e) {
+                return Err(e);
+            }
+            self.tampered.set(true);
+        }
+        Ok(store)
+    }
+
+    /// Write the store under the lock, replacing the file atomically
+    fn save(&self, store: &FeatureStore) -> io::Result<()> {
+        let _lock = StoreLock::acquire(&self.path)?;
+        if self.has_changed()? {
+            return Err(self.changed());
+        }
+        self.write(store)
+    }
+
+    fn update(&self, change: &mut dyn FnMut(&mut FeatureStore)) -> io::Result<()> {
+        self.update_current(change)
+    }
+
+    /// Always `false` if the file was never loaded through this handle
+    fn has_changed(&self) -> io::Result<bool> {
+        let known = self.snapshot.get();
+        Ok(known != Snapshot::Unknown && self.read_snapshot()? != known)
+    }
+
+    fn current(&self) -> io::Result<FeatureStore> {
+        self.read_current()
+    }
+
+    fn get(&self, name: &str, feature: &str) -> io::Result<Option<Feature>> {
+        Ok(self.read_current()?.find(name, feature).cloned())
+    }
+
+    fn put(&self, feature: &Feature) -> io::Result<()> {
+        self.update_current(|store| store.put_feature(feature.clone()))
+    }
+
+    fn delete(&self, id: &str) -> io::Result<bool> {
+        self.update_current(|store| store.delete_feature(id).is_some())
+    }
+
+    fn reencrypt(&mut self, password: &str, key: &StorageKey) -> io::Result<()> {
+        let _lock = StoreLock::acquire(&self.path)?;
+        if self.has_changed()? {
+            return Err(self.changed());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        if let StorageFormat::Log = self.format {
+            let entries = oplog::parse(&content, self.record_key()?)?;
+            self.set_password(password.to_string(), Some(key.clone()));
+            let mut content = String::new();
+            for entry in &entries {
+                content.push_str(&oplog::render(entry, Some(key))?);
+                content.push('\n');
+            }
+            self.replace_file(&content)
+        } else {
+            let store = self.parse_content(&content)?;
+            self.set_password(password.to_string(), None);
+            self.write(&store)
+        }
+    }
+
+    /// Keep only the winning entry of each feature and store field, see
+    /// [`oplog::compact`]
+    fn compact(&self) -> io::Result<(usize, usize)> {
+        if !matches!(self.format, StorageFormat::Log) {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Only operation log stores can be compacted",
+            ));
+        }
+
+        let _lock = StoreLock::acquire(&self.path)?;
+        if self.has_changed()? {
+            return Err(self.changed());
+        }
+
+        let key = self.record_key()?;
+        let entries = oplog::parse(&fs::read_to_string(&self.path)?, key)?;
+        let compacted = oplog::compact(&entries);
+        let mut content = String::new();
+        for entry in &compacted {
+            content.push_str(&oplog::render(entry, key)?);
+            content.push('\n');
+        }
+        self.replace_file(&content)?;
+        Ok((entries.len(), compacted.len()))
+    }
+
+    fn is_tampered(&self) -> bool {
+        self.tampered.get()
+    }
+
+    fn resign(&self, store: &FeatureStore) -> io::Result<()> {
+        let key = self
+            .signature_key
+            .as_ref()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Signature key not set"))?;
+        let mut signed = store.clone();
+        signed.signature = Some(signature::sign(key, store)?);
+        let tampered = self.tampered.replace(false);
+        let saved = self.save(&signed);
+        if saved.is_err() {
+            self.tampered.set(tampered);
+        }
+        saved
+    }
+
+    /// A TOML snapshot is shown as it is, other formats are converted
+    fn export_toml(&self) -> io::Result<String> {
+        if let StorageFormat::Log = self.format {
+            return toml::to_string_pretty(&self.read_current()?)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+        }
+
+        let mut content = fs::read_to_string(&self.path)?;
+        if self.encrypted {
+            let decrypted = StorageCipher::decrypt(self.password()?, &content)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            content = String::from_utf8(decrypted)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        }
+
+        match StorageFormat::sniff(&content) {
+            StorageFormat::Toml => Ok(content),
+            format => {
+                let store = format.parse(&content, None)?;
+                toml::to_string_pretty(&store)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+            }
+        }
+    }
+}
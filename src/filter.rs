@@ -0,0 +1,181 @@
+//! Tags, folders and filtered views of the features
+//!
+//! Features can carry any number of tags and sit in an optional folder, a path of names
+//! separated by `/` such as `Work/Dev`. A [`Filter`] narrows the features offered by
+//! the menus, and [`tree`] groups them by folder for display. A folder filter includes
+//! the features of its subfolders.
+
+use crate::models::Feature;
+use std::collections::BTreeSet;
+
+/// Which features to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    All,
+    Tag(String),
+    /// Features in this folder or one of its subfolders
+    Folder(String),
+}
+
+impl Filter {
+    pub fn matches(&self, feature: &Feature) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Tag(tag) => feature.tags.iter().any(|t| t == tag),
+            Filter::Folder(folder) => feature.folder.as_deref().is_some_and(|f| {
+                f == folder
+                    || f.strip_prefix(folder.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }),
+        }
+    }
+}
+
+/// Clean up a folder path typed by the user: `" Work / Dev/"` becomes `Work/Dev`
+pub fn normalize_folder(input: &str) -> Option<String> {
+    let parts: Vec<&str> = input
+        .split('/')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Parse a comma-separated list of tags, dropping duplicates and empty entries
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in input.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+/// All tags in use, sorted
+pub fn tags(features: &[Feature]) -> Vec<String> {
+    let tags: BTreeSet<&String> = features.iter().flat_map(|f| &f.tags).collect();
+    tags.into_iter().cloned().collect()
+}
+
+/// All folders in use, including the parents of nested folders, sorted
+pub fn folders(features: &[Feature]) -> Vec<String> {
+    let mut folders = BTreeSet::new();
+    for folder in features.iter().filter_map(|f| f.folder.as_deref()) {
+        let mut path = String::new();
+        for part in folder.split('/') {
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(part);
+            folders.insert(path.clone());
+        }
+    }
+    folders.into_iter().collect()
+}
+
+/// A line of the folder tree
+#[derive(Debug)]
+pub enum TreeLine<'a> {
+    Folder { depth: usize, name: &'a str },
+    Feature { depth: usize, feature: &'a Feature },
+}
+
+/// Group features by folder: features without a folder first, then each folder with its
+/// subfolders, folders sorted by name and features kept in their original order
+pub fn tree<'a>(features: &[&'a Feature]) -> Vec<TreeLine<'a>> {
+    let path = |f: &'a Feature| -> Vec<&'a str> {
+        f.folder
+            .as_deref()
+            .map(|folder| folder.split('/').collect())
+            .unwrap_or_default()
+    };
+    let mut sorted: Vec<&'a Feature> = features.to_vec();
+    sorted.sort_by_key(|f| path(f));
+
+    let mut lines = Vec::new();
+    let mut open: Vec<&'a str> = Vec::new();
+    for feature in sorted {
+        let path = path(feature);
+        let common = open.iter().zip(&path).take_while(|(a, b)| a == b).count();
+        open.truncate(common);
+        for (depth, name) in path.iter().enumerate().skip(common) {
+            lines.push(TreeLine::Folder { depth, name });
+            open.push(name);
+        }
+        lines.push(TreeLine::Feature {
+            depth: path.len(),
+            feature,
+        });
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+
+    fn feature(name: &str, folder: Option<&str>, tags: &[&str]) -> Feature {
+        let mut feature = test_feature(name);
+        feature.folder = folder.map(str::to_string);
+        feature.tags = tags.iter().map(|t| t.to_string()).collect();
+        feature
+    }
+
+    #[test]
+    fn test_filters() {
+        let features = vec![
+            feature("GitHub", Some("Work/Dev"), &["2fa", "work"]),
+            feature("Mail", Some("Work"), &["work"]),
+            feature("Bank", Some("Workshop"), &[]),
+            feature("Shop", None, &["2fa"]),
+        ];
+        let names = |filter: Filter| -> Vec<&str> {
+            features
+                .iter()
+                .filter(|f| filter.matches(f))
+                .map(|f| f.name.as_str())
+                .collect()
+        };
+
+        assert_eq!(names(Filter::All).len(), 4);
+        assert_eq!(names(Filter::Tag("2fa".to_string())), ["GitHub", "Shop"]);
+        assert_eq!(
+            names(Filter::Folder("Work".to_string())),
+            ["GitHub", "Mail"]
+        );
+        assert_eq!(names(Filter::Folder("Work/Dev".to_string())), ["GitHub"]);
+
+        assert_eq!(tags(&features), ["2fa", "work"]);
+        assert_eq!(folders(&features), ["Work", "Work/Dev", "Workshop"]);
+        assert_eq!(
+            normalize_folder(" Work / Dev/ ").as_deref(),
+            Some("Work/Dev")
+        );
+        assert_eq!(normalize_folder(" / "), None);
+        assert_eq!(parse_tags("work, 2fa,,work "), ["work", "2fa"]);
+    }
+
+    #[test]
+    fn test_tree() {
+        let github = feature("GitHub", Some("Work/Dev"), &[]);
+        let mail = feature("Mail", Some("Work"), &[]);
+        let shop = feature("Shop", None, &[]);
+        let gitlab = feature("GitLab", Some("Work/Dev"), &[]);
+
+        let lines: Vec<String> = tree(&[&github, &mail, &shop, &gitlab])
+            .into_iter()
+            .map(|line| match line {
+                TreeLine::Folder { depth, name } => format!("{} [{}]", depth, name),
+                TreeLine::Feature { depth, feature } => format!("{} {}", depth, feature.name),
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "0 Shop", "0 [Work]", "1 Mail", "1 [Dev]", "2 GitHub", "2 GitLab"
+            ]
+        );
+    }
+}
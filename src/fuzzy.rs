@@ -0,0 +1,252 @@
+//! Fuzzy matching of features for the search picker
+//!
+//! A query matches a feature when its characters appear in order, ignoring case, in the
+//! feature's name, identifier, aliases or hint. Matches are scored so the best ones come
+//! first: consecutive characters, characters at the start of a word and matches near the
+//! start of the text score higher, gaps between characters cost a little, and a match in
+//! the name beats the same match in the identifier, which beats one in an alias or the
+//! hint. A URL or domain naming a feature's identifier or alias, such as a pasted
+//! `https://outlook.live.com/mail`, ranks that feature above everything else.
+//!
+//! This module only ranks; the terminal picker lives in the CLI.
+
+use crate::domain;
+use crate::models::Feature;
+
+/// The part of a feature a query matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Identifier,
+    /// One of the feature's aliases
+    Alias(usize),
+    Hint,
+}
+
+impl Field {
+    fn bonus(self) -> i64 {
+        match self {
+            Field::Name => 8,
+            Field::Identifier => 4,
+            Field::Alias(_) => 2,
+            Field::Hint => 0,
+        }
+    }
+}
+
+/// A feature matching the query, with the character positions that matched in `field`
+///
+/// Features found through [`Feature::matches_identifier`] have no positions.
+#[derive(Debug)]
+pub struct Match<'a> {
+    pub feature: &'a Feature,
+    pub score: i64,
+    pub field: Field,
+    pub positions: Vec<usize>,
+}
+
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 16;
+const WORD_START: i64 = 12;
+const PREFIX: i64 = 8;
+const EXACT: i64 = 32;
+const MAX_GAP_PENALTY: i64 = 12;
+/// Score of a query naming the feature's identifier or an alias, above any fuzzy match
+const URL_MATCH: i64 = 1 << 20;
+
+/// Score `query` against `text`, returning the score and the matched character positions
+///
+/// Every possible starting point for the first query character is tried, with the rest of
+/// the query matched greedily from there, and the best scoring attempt wins.
+pub fn score(query: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    for start in (0..lower.len()).filter(|&i| lower[i] == query[0]) {
+        let mut positions = vec![start];
+        let mut next = start + 1;
+        for &c in &query[1..] {
+            match (next..lower.len()).find(|&i| lower[i] == c) {
+                Some(i) => {
+                    positions.push(i);
+                    next = i + 1;
+                }
+                None => break,
+            }
+        }
+        if positions.len() < query.len() {
+            // Later starting points leave even less text to match in
+            break;
+        }
+
+        let score = rate(&text, &positions);
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, positions));
+        }
+    }
+    best
+}
+
+fn rate(text: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    for (n, &i) in positions.iter().enumerate() {
+        score += MATCH;
+        if i == 0 || is_word_start(text, i) {
+            score += WORD_START;
+        }
+        if n > 0 {
+            let gap = (i - positions[n - 1] - 1) as i64;
+            if gap == 0 {
+                score += CONSECUTIVE;
+            } else {
+                score -= gap.min(MAX_GAP_PENALTY);
+            }
+        }
+    }
+    if positions[0] == 0 {
+        score += PREFIX;
+        if positions.len() == text.len() {
+            score += EXACT;
+        }
+    } else {
+        score -= (positions[0] as i64).min(MAX_GAP_PENALTY);
+    }
+    score
+}
+
+fn is_word_start(text: &[char], i: usize) -> bool {
+    let (prev, c) = (text[i - 1], text[i]);
+    (!prev.is_alphanumeric() && c.is_alphanumeric()) || (prev.is_lowercase() && c.is_uppercase())
+}
+
+/// Rank `features` against `query`, best match first
+///
+/// An empty query matches everything and keeps the original order. Equal scores also
+/// keep the original order.
+pub fn rank<'a>(query: &str, features: &[&'a Feature]) -> Vec<Match<'a>> {
+    let query = query.trim();
+    let is_url = domain::host(query).is_some();
+    let mut matches: Vec<Match<'a>> = features
+        .iter()
+        .filter_map(|&feature| {
+            if is_url && feature.matches_identifier(query) {
+                return Some(Match {
+                    feature,
+                    score: URL_MATCH,
+                    field: Field::Identifier,
+                    positions: Vec::new(),
+                });
+            }
+
+            let mut fields = vec![
+                (Field::Name, feature.name.as_str()),
+                (Field::Identifier, feature.feature.as_str()),
+            ];
+            fields.extend(
+                feature
+                    .aliases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, alias)| (Field::Alias(i), alias.as_str())),
+            );
+            fields.extend(feature.hint.as_deref().map(|hint| (Field::Hint, hint)));
+            // On equal scores the first field wins
+            fields
+                .into_iter()
+                .filter_map(|(field, text)| {
+                    let (score, positions) = score(query, text)?;
+                    Some(Match {
+                        feature,
+                        score: score + field.bonus(),
+                        field,
+                        positions,
+                    })
+                })
+                .reduce(|best, m| if m.score > best.score { m } else { best })
+        })
+        .collect();
+    if !query.is_empty() {
+        matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Algorithm;
+
+    fn feature(name: &str, identifier: &str, hint: Option<&str>) -> Feature {
+        Feature::new(
+            name.to_string(),
+            identifier.to_string(),
+            Algorithm::HmacSha256,
+            hint.map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("gh", "GitHub").map(|(_, p)| p), Some(vec![0, 3]));
+        assert_eq!(score("xyz", "GitHub"), None);
+        assert_eq!(score("", "GitHub"), Some((0, Vec::new())));
+
+        // Consecutive and word-start matches beat scattered ones
+        let (tight, _) = score("hub", "GitHub").unwrap();
+        let (loose, _) = score("hub", "hotel umbrella bay").unwrap();
+        assert!(tight > loose);
+
+        // The best starting point is chosen, not the first one
+        assert_eq!(
+            score("mail", "admin email").map(|(_, p)| p),
+            Some(vec![7, 8, 9, 10])
+        );
+
+        let (exact, _) = score("mail", "Mail").unwrap();
+        let (prefix, _) = score("mail", "Mailbox").unwrap();
+        assert!(exact > prefix);
+    }
+
+    #[test]
+    fn test_rank() {
+        let github = feature("GitHub", "github.com", None);
+        let gitlab = feature("GitLab", "gitlab.com", Some("work account"));
+        let bank = feature("Bank", "mybank.example", Some("pin in the safe"));
+        let mut outlook = feature("Outlook", "outlook.com", None);
+        outlook.normalization = crate::domain::Normalization::Registrable;
+        outlook.aliases = vec!["live.com".to_string()];
+        let features = [&github, &gitlab, &bank, &outlook];
+
+        let names = |query: &str| -> Vec<(&str, Field)> {
+            rank(query, &features)
+                .iter()
+                .map(|m| (m.feature.name.as_str(), m.field))
+                .collect()
+        };
+
+        assert_eq!(names("").len(), 4);
+        assert_eq!(
+            names("git"),
+            [("GitHub", Field::Name), ("GitLab", Field::Name)]
+        );
+        assert_eq!(names("gith"), [("GitHub", Field::Name)]);
+        assert_eq!(names("lab")[0], ("GitLab", Field::Name));
+        assert_eq!(names("example"), [("Bank", Field::Identifier)]);
+        assert_eq!(names("safe"), [("Bank", Field::Hint)]);
+        assert!(names("zzz").is_empty());
+        assert_eq!(names("live")[0], ("Outlook", Field::Alias(0)));
+        // A URL through an alias beats fuzzy matches of its characters elsewhere
+        assert_eq!(
+            names("https://login.live.com/")[0],
+            ("Outlook", Field::Identifier)
+        );
+    }
+}
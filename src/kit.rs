@@ -0,0 +1,510 @@
+//! Printable emergency kit
+//!
+//! A PDF with everything needed to regenerate a store's passwords from the master salt
+//! alone, even without SaltPass: every feature's identifier, login, algorithm, parameters
+//! and hint, and a description of the derivation scheme. It never contains passwords or the
+//! salt, but can leave blank lines to write the salt down by hand.
+//!
+//! The document only uses the standard Helvetica and Courier fonts that every PDF viewer
+//! provides, so nothing is embedded or downloaded. Those fonts cover Latin-1; other
+//! characters are printed as `?`, except in the identifier, login and aliases, which are
+//! derivation inputs and print them escaped as `\u{…}` instead.
+
+use crate::models::FeatureStore;
+use chrono::Utc;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+
+/// A4 in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+/// Space kept free at the bottom of each page for the page number
+const FOOTER: f32 = 20.0;
+
+/// What to print besides the features
+pub struct KitOptions {
+    /// Store the kit was made from, shown in the header
+    pub store_name: String,
+    /// Password length of features added before lengths were stored
+    pub length: usize,
+    /// Leave blank lines to write the master salt by hand
+    pub salt_lines: bool,
+}
+
+/// How to regenerate a password; `{length}` is replaced by the configured length
+const SCHEME: &[&str] = &[
+    "1. Inputs: the master salt and the feature's input, both as UTF-8 bytes. The input is \
+     the identifier exactly as printed or, for a feature with a login, the number of bytes \
+     of the identifier in decimal, ':', the identifier, a zero byte and the login.",
+    "2. Derive 32 bytes with the feature's algorithm:",
+    "    HMAC-SHA256: HMAC-SHA256 with the salt as key and the input as message.",
+    "    PBKDF2: PBKDF2-HMAC-SHA256 with the input as password and the salt as salt.",
+    "    Argon2i / Argon2id: the input as password and the salt as salt.",
+    "    Scrypt: the input as password and the salt as salt, r = 8.",
+    "3. Encode the 32 bytes as standard Base64 with padding.",
+    "4. Walk the Base64 text from the left, keeping letters and digits as they are. Replace \
+     '+' with S[i], '/' with S[i + 1] and '=' with S[i + 2], where S = \"!@#$%^&*\" (indices \
+     modulo 8) and i is the number of characters kept so far. Stop once the password is \
+     long enough: the length printed with the feature; if none is printed, {length} \
+     characters (or whatever length was chosen when it was generated).",
+    "5. If the password has no uppercase letter, uppercase its first character. If it has \
+     no digit, replace its second character with the digit (length modulo 10). If it has \
+     no special character, replace its third character with '!'.",
+];
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+    Mono,
+}
+
+impl Font {
+    fn name(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"F1"),
+            Font::Bold => Name(b"F2"),
+            Font::Mono => Name(b"F3"),
+        }
+    }
+
+    fn base_font(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"Helvetica"),
+            Font::Bold => Name(b"Helvetica-Bold"),
+            Font::Mono => Name(b"Courier"),
+        }
+    }
+
+    /// Approximate width of a character in thousandths of the font size
+    fn width(self, ch: char) -> f32 {
+        let width = match (self, ch) {
+            (Font::Mono, _) => 600.0,
+            (_, 'i' | 'j' | 'l' | '\'' | '|') => 230.0,
+            (_, ' ' | '!' | ',' | '.' | '/' | ':' | ';' | 'I' | 'f' | 't' | '[' | ']') => 278.0,
+            (_, '(' | ')' | '-' | 'r' | '`' | '{' | '}' | '"' | '*') => 340.0,
+            (_, 'c' | 'k' | 's' | 'v' | 'x' | 'y' | 'z' | 'J') => 500.0,
+            (_, 'm' | 'M' | 'w' | '%') => 850.0,
+            (_, 'W' | '@') => 1000.0,
+            (_, 'A'..='Z' | '&' | '+' | '<' | '=' | '>' | '~') => 700.0,
+            _ => 556.0,
+        };
+        match self {
+            Font::Bold => width * 1.08,
+            _ => width,
+        }
+    }
+}
+
+/// Text in the fonts' WinAnsi encoding
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|ch| match ch {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => ch as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// A derivation input with every character the fonts can't show unambiguously written as
+/// `\u{…}` with its hexadecimal code point, and backslashes doubled; `None` if it prints as is
+fn escape(text: &str) -> Option<String> {
+    let plain =
+        |ch: char| matches!(ch, ' '..='~' | '\u{a1}'..='\u{ff}') && !matches!(ch, '\\' | '\u{ad}');
+    if text.chars().all(plain) {
+        return None;
+    }
+    Some(
+        text.chars()
+            .map(|ch| match ch {
+                '\\' => "\\\\".to_string(),
+                ch if plain(ch) => ch.to_string(),
+                ch => format!("\\u{{{:x}}}", ch as u32),
+            })
+            .collect(),
+    )
+}
+
+/// Split text into lines no wider than `width` points
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let measure = |s: &str| s.chars().map(|c| font.width(c)).sum::<f32>() * size / 1000.0;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if measure(&candidate) <= width || line.is_empty() && measure(word) <= width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // Break words that do not fit on a line of their own
+        for ch in word.chars() {
+            if !line.is_empty() && measure(&format!("{}{}", line, ch)) > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(ch);
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// Pages being laid out from top to bottom
+struct Layout {
+    pages: Vec<Content>,
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        let mut layout = Self {
+            pages: Vec::new(),
+            y: 0.0,
+        };
+        layout.new_page();
+        layout
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Content::new());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn page(&mut self) -> &mut Content {
+        self.pages.last_mut().expect("layout always has a page")
+    }
+
+    /// Start a new page unless `height` points are left on this one
+    fn keep(&mut self, height: f32) {
+        if self.y - height < MARGIN + FOOTER {
+            self.new_page();
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// Write wrapped text starting `indent` points from the left margin
+    fn text(&mut self, font: Font, size: f32, indent: f32, text: &str) {
+        let leading = size * 1.35;
+        for line in wrap(text, font, size, PAGE_WIDTH - 2.0 * MARGIN - indent) {
+            self.keep(leading);
+            self.y -= leading;
+            let y = self.y;
+            self.page()
+                .begin_text()
+                .set_font(font.name(), size)
+                .next_line(MARGIN + indent, y + size * 0.3)
+                .show(Str(&encode(&line)))
+                .end_text();
+        }
+    }
+
+    /// Write a label in bold followed by its value on the same line
+    fn field(&mut self, label: &str, value: &str, font: Font) {
+        const LABEL_WIDTH: f32 = 72.0;
+        let size = 10.0;
+        let lines = wrap(
+            value,
+            font,
+            size,
+            PAGE_WIDTH - 2.0 * MARGIN - 12.0 - LABEL_WIDTH,
+        );
+        let leading = size * 1.35;
+        for (i, line) in lines.iter().enumerate() {
+            self.keep(leading);
+            self.y -= leading;
+            let y = self.y + size * 0.3;
+            let page = self.page();
+            if i == 0 {
+                page.begin_text()
+                    .set_font(Font::Bold.name(), size)
+                    .next_line(MARGIN + 12.0, y)
+                    .show(Str(&encode(label)))
+                    .end_text();
+            }
+            page.begin_text()
+                .set_font(font.name(), size)
+                .next_line(MARGIN + 12.0 + LABEL_WIDTH, y)
+                .show(Str(&encode(line)))
+                .end_text();
+        }
+    }
+
+    /// Draw a horizontal line across the text area
+    fn rule(&mut self, gray: f32) {
+        let y = self.y;
+        self.page()
+            .set_stroke_gray(gray)
+            .set_line_width(0.5)
+            .move_to(MARGIN, y)
+            .line_to(PAGE_WIDTH - MARGIN, y)
+            .stroke();
+    }
+}
+
+/// Render the emergency kit of a store as a PDF document
+pub fn render(store: &FeatureStore, options: &KitOptions) -> Vec<u8> {
+    let mut layout = Layout::new();
+
+    layout.text(Font::Bold, 20.0, 0.0, "SaltPass Emergency Kit");
+    layout.text(
+        Font::Regular,
+        10.0,
+        0.0,
+        &format!(
+            "{} - {} feature(s) - created {} by SaltPass {}",
+            options.store_name,
+            store.features.len(),
+            Utc::now().format("%Y-%m-%d %H:%M UTC"),
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    layout.text(
+        Font::Regular,
+        10.0,
+        0.0,
+        "Passwords are derived from the master salt and each feature's identifier and login; none \
+         are stored anywhere. Keep this document somewhere safe.",
+    );
+    layout.gap(6.0);
+    layout.rule(0.0);
+
+    if options.salt_lines {
+        layout.gap(10.0);
+        layout.text(Font::Bold, 13.0, 0.0, "Master salt");
+        layout.text(
+            Font::Regular,
+            10.0,
+            0.0,
+            "Once the salt is written here, this sheet gives access to every password below.",
+        );
+        for _ in 0..3 {
+            layout.keep(28.0);
+            layout.gap(28.0);
+            layout.rule(0.3);
+        }
+    }
+
+    layout.gap(10.0);
+    layout.text(Font::Bold, 13.0, 0.0, "Regenerating a password");
+    for step in SCHEME {
+        let step = step.replace("{length}", &options.length.to_string());
+        let indent = if step.starts_with("    ") { 14.0 } else { 0.0 };
+        layout.text(Font::Regular, 10.0, indent, step.trim_start());
+    }
+
+    layout.gap(10.0);
+    layout.text(Font::Bold, 13.0, 0.0, "Features");
+    if store.features.is_empty() {
+        layout.text(Font::Regular, 10.0, 0.0, "The store has no features.");
+    }
+    for feature in &store.features {
+        layout.keep(80.0);
+        layout.gap(6.0);
+        layout.text(Font::Bold, 11.0, 0.0, &feature.name);
+        let mut escaped = false;
+        let mut input = |text: &str| match escape(text) {
+            Some(text) => {
+                escaped = true;
+                text
+            }
+            None => text.to_string(),
+        };
+        layout.field("Identifier", &input(&feature.feature), Font::Mono);
+        if !feature.aliases.is_empty() {
+            let aliases: Vec<String> = feature.aliases.iter().map(|a| input(a)).collect();
+            layout.field("Aliases", &aliases.join(", "), Font::Regular);
+        }
+        if let Some(login) = feature.login.as_deref().filter(|l| !l.is_empty()) {
+            layout.field("Login", &input(login), Font::Mono);
+        }
+        if escaped {
+            layout.field(
+                "Note",
+                "\\u{...} stands for the character with that hexadecimal Unicode code point \
+                 and \\\\ for a single backslash; type the characters themselves.",
+                Font::Regular,
+            );
+        }
+        layout.field("Algorithm", feature.algorithm.name(), Font::Regular);
+        layout.field(
+            "Parameters",
+            &feature.params().describe(feature.algorithm),
+            Font::Regular,
+        );
+        if let Some(length) = feature.length {
+            layout.field("Length", &length.to_string(), Font::Regular);
+        }
+        if let Some(hint) = feature.hint.as_deref().filter(|h| !h.is_empty()) {
+            layout.field("Hint", hint, Font::Regular);
+        }
+        layout.field(
+            "Created",
+            &feature.created.format("%Y-%m-%d").to_string(),
+            Font::Regular,
+        );
+        layout.gap(4.0);
+        layout.rule(0.75);
+    }
+
+    // Page numbers, now that the page count is known
+    let count = layout.pages.len();
+    for (index, page) in layout.pages.iter_mut().enumerate() {
+        page.begin_text()
+            .set_font(Font::Regular.name(), 8.0)
+            .next_line(MARGIN, MARGIN - 10.0)
+            .show(Str(format!("Page {} of {}", index + 1, count).as_bytes()))
+            .end_text();
+    }
+
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let info_id = Ref::new(3);
+    let fonts = [Font::Regular, Font::Bold, Font::Mono];
+    let font_id = |i: usize| Ref::new(4 + i as i32);
+    let page_id = |i: usize| Ref::new(7 + 2 * i as i32);
+    let content_id = |i: usize| Ref::new(8 + 2 * i as i32);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids((0..count).map(page_id))
+        .count(count as i32);
+    pdf.document_info(info_id)
+        .title(TextStr("SaltPass Emergency Kit"))
+        .producer(TextStr(concat!("SaltPass ", env!("CARGO_PKG_VERSION"))));
+    for (i, font) in fonts.into_iter().enumerate() {
+        pdf.type1_font(font_id(i))
+            .base_font(font.base_font())
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (i, content) in layout.pages.into_iter().enumerate() {
+        let mut page = pdf.page(page_id(i));
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .parent(tree_id)
+            .contents(content_id(i));
+        let mut resources = page.resources();
+        let mut resource_fonts = resources.fonts();
+        for (j, font) in fonts.into_iter().enumerate() {
+            resource_fonts.pair(font.name(), font_id(j));
+        }
+        resource_fonts.finish();
+        resources.finish();
+        page.finish();
+        pdf.stream(content_id(i), &content.finish());
+    }
+
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{Algorithm, KdfParams};
+    use crate::models::Feature;
+
+    fn options(salt_lines: bool) -> KitOptions {
+        KitOptions {
+            store_name: "features.toml".to_string(),
+            length: 16,
+            salt_lines,
+        }
+    }
+
+    #[test]
+    fn test_kit_lists_features() {
+        let mut store = FeatureStore::new();
+        store
+            .add_feature(Feature::new(
+                "Straße".to_string(),
+                "github.com".to_string(),
+                Algorithm::HmacSha256,
+                Some("work account".to_string()),
+            ))
+            .unwrap();
+        store
+            .add_feature(
+                Feature::new(
+                    "Bank".to_string(),
+                    "bank.example".to_string(),
+                    Algorithm::Scrypt,
+                    None,
+                )
+                .with_params(KdfParams {
+                    log_n: 14,
+                    parallelism: 1,
+                    ..KdfParams::default()
+                }),
+            )
+            .unwrap();
+
+        let pdf = render(&store, &options(false));
+        assert!(pdf.starts_with(b"%PDF-"));
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("(github.com)"));
+        assert!(text.contains("(work account)"));
+        assert!(text.contains("(N = 2^14, r = 8, p = 1)"));
+        assert!(!text.contains("Master salt"));
+        // Latin-1 characters are kept, in the font's single-byte encoding
+        assert!(text.to_ascii_lowercase().contains("<53747261df65>"));
+
+        // Enough features spill over to more pages
+        for i in 0..40 {
+            store
+                .add_feature(Feature::new(
+                    format!("Site {}", i),
+                    format!("site{}.example", i),
+                    Algorithm::HmacSha256,
+                    None,
+                ))
+                .unwrap();
+        }
+        let text = String::from_utf8_lossy(&render(&store, &options(true))).into_owned();
+        assert!(text.contains("Master salt"));
+        assert!(text.contains("(Page 1 of "));
+        assert!(!text.contains("(Page 1 of 1)"));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let lines = wrap("aaa bbb ccc", Font::Mono, 10.0, 40.0);
+        assert_eq!(lines, ["aaa", "bbb", "ccc"]);
+        let lines = wrap("abcdefghij", Font::Mono, 10.0, 30.0);
+        assert_eq!(lines, ["abcde", "fghij"]);
+        assert_eq!(encode("日本 ok"), b"?? ok");
+    }
+
+    #[test]
+    fn test_kit_escapes_derivation_inputs() {
+        assert_eq!(escape("Straße.de"), None);
+        assert_eq!(escape("日本.jp").as_deref(), Some("\\u{65e5}\\u{672c}.jp"));
+        assert_eq!(escape("a\\u{41}").as_deref(), Some("a\\\\u{41}"));
+        assert_eq!(escape("日\\").as_deref(), Some("\\u{65e5}\\\\"));
+        assert_eq!(escape("a\u{a0}b").as_deref(), Some("a\\u{a0}b"));
+
+        let mut store = FeatureStore::new();
+        let mut feature = Feature::new(
+            "Japan".to_string(),
+            "日本.jp".to_string(),
+            Algorithm::HmacSha256,
+            Some("ひみつ".to_string()),
+        );
+        feature.login = Some("ユーザー".to_string());
+        store.add_feature(feature).unwrap();
+        let text = String::from_utf8_lossy(&render(&store, &options(false))).into_owned();
+        assert!(text.contains("(\\\\u{65e5}\\\\u{672c}.jp)"));
+        assert!(text.contains("(\\\\u{30e6}\\\\u{30fc}\\\\u{30b6}\\\\u{30fc})"));
+        assert!(text.contains("(Note)"));
+        // Other fields still fall back to question marks
+        assert!(text.contains("(???)"));
+    }
+}
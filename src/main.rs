@@ -19,16 +19,91 @@
 //! cargo run --release
 //! ```
 
+mod args;
+mod audit;
+mod backend;
+mod backup;
 mod cli;
+mod config;
 mod crypto;
+mod domain;
+mod file;
+mod filter;
+mod fuzzy;
+mod kit;
+mod merge;
+mod migrations;
 mod models;
+mod oplog;
+mod profiles;
+mod rotation;
+mod salt_change;
+mod signature;
+mod sqlite;
 mod storage;
+mod sync;
+#[cfg(test)]
+mod sync_server;
 
+use args::{Args, Command};
 use cli::Cli;
 use std::process;
 
 fn main() {
-    let mut app = match Cli::new() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("❌ Error: {}\n\n{}", e, args::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let profile = match args.command {
+        Command::Interactive | Command::Audit => args.profile,
+        Command::Help => {
+            println!("{}", args::USAGE);
+            return;
+        }
+        Command::Version => {
+            println!("SaltPass {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Command::Profile(command) => {
+            if let Err(e) = Cli::manage_profiles(command) {
+                eprintln!("\r\x1b[2K❌ Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::ExportBackup(path) => {
+            if let Err(e) = Cli::export_backup(args.profile.as_deref(), &path) {
+                eprintln!("\r\x1b[2K❌ Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::ImportBackup(path) => {
+            if let Err(e) = Cli::import_backup(args.profile.as_deref(), &path) {
+                eprintln!("\r\x1b[2K❌ Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Command::Merge {
+            base,
+            ours,
+            theirs,
+            output,
+        } => {
+            if let Err(e) = Cli::merge_stores(&base, &ours, &theirs, output) {
+                eprintln!("\r\x1b[2K❌ Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+    };
+
+    let mut app = match Cli::new(profile) {
         Ok(app) => app,
         Err(e) => {
             eprintln!("\r\x1b[2K❌ Error: {}", e);
@@ -36,7 +111,11 @@ fn main() {
         }
     };
 
-    if let Err(e) = app.run() {
+    let result = match args.command {
+        Command::Audit => app.show_audit(),
+        _ => app.run(),
+    };
+    if let Err(e) = result {
         eprintln!("\r\x1b[2K❌ Error: {}", e); // \x1b[2K clears the line
         process::exit(1);
     }
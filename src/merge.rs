@@ -0,0 +1,554 @@
+//! Three-way merge of diverged feature stores
+//!
+//! Features are matched by ID across the common ancestor (`base`) and the two edited
+//! copies (`ours` and `theirs`), so a renamed feature is still the same feature. Stores
+//! written before features had IDs get them derived from their name, identifier and
+//! creation time when loaded, see [`crate::migrations::feature_id`]; a feature added on
+//! both sides with the same name and identifier is merged as one. Edits to different fields
+//! of a feature, and additions or deletions on one side only, merge automatically. The
+//! fields that derive the password merge as one unit, so a merge never produces a
+//! password neither side generated. The remaining true conflicts are returned for the
+//! caller to resolve. Trash entries are merged as a set.
+
+use crate::models::{Feature, FeatureStore, TrashedFeature};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, ErrorKind};
+
+/// Identity used to match a feature across the three stores: its ID
+pub type FeatureKey = String;
+
+fn key(feature: &Feature) -> FeatureKey {
+    feature.id.clone()
+}
+
+/// Fields that together derive the password, see [`Feature::same_password_as`]
+const DERIVATION_FIELDS: &[&str] = &["feature", "login", "algorithm", "params", "length"];
+
+fn to_map(feature: &Feature) -> io::Result<Map<String, Value>> {
+    match serde_json::to_value(feature) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => unreachable!("features serialize to maps"),
+        Err(e) => Err(io::Error::new(ErrorKind::InvalidData, e)),
+    }
+}
+
+fn from_map(map: Map<String, Value>) -> io::Result<Feature> {
+    serde_json::from_value(Value::Object(map))
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Side chosen to resolve a conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+}
+
+/// A change made on both sides that cannot be merged automatically
+#[derive(Debug)]
+pub enum Conflict {
+    /// Both sides changed the same fields to different values
+    ///
+    /// Both candidates already contain the non-conflicting edits of the other side.
+    Modified {
+        ours: Box<Feature>,
+        theirs: Box<Feature>,
+        fields: Vec<String>,
+    },
+    /// One side deleted the feature while the other one modified it
+    DeleteModify {
+        /// The modified version, `Ours` if we modified it and they deleted it
+        modified: Box<Feature>,
+        modified_by: Resolution,
+    },
+}
+
+impl Conflict {
+    /// The feature this conflict is about
+    pub fn feature(&self) -> &Feature {
+        match self {
+            Conflict::Modified { ours, .. } => ours,
+            Conflict::DeleteModify { modified, .. } => modified,
+        }
+    }
+
+    fn resolve(self, resolution: Resolution) -> Option<Feature> {
+        match self {
+            Conflict::Modified { ours, theirs, .. } => Some(match resolution {
+                Resolution::Ours => *ours,
+                Resolution::Theirs => *theirs,
+            }),
+            Conflict::DeleteModify {
+                modified,
+                modified_by,
+            } => (resolution == modified_by).then_some(*modified),
+        }
+    }
+}
+
+enum Entry {
+    Merged(Box<Feature>),
+    Conflict(Conflict),
+}
+
+/// Result of a three-way merge, possibly with unresolved conflicts
+pub struct MergeOutcome {
+    template: FeatureStore,
+    entries: Vec<Entry>,
+}
+
+impl MergeOutcome {
+    pub fn conflicts(&self) -> impl Iterator<Item = &Conflict> {
+        self.entries.iter().filter_map(|e| match e {
+            Entry::Conflict(c) => Some(c),
+            Entry::Merged(_) => None,
+        })
+    }
+
+    /// Build the merged store, resolving each conflict with `choose`
+    pub fn resolve<F>(self, mut choose: F) -> io::Result<FeatureStore>
+    where
+        F: FnMut(&Conflict) -> io::Result<Resolution>,
+    {
+        let mut store = self.template;
+        for entry in self.entries {
+            let feature = match entry {
+                Entry::Merged(feature) => Some(*feature),
+                Entry::Conflict(conflict) => {
+                    let resolution = choose(&conflict)?;
+                    conflict.resolve(resolution)
+                }
+            };
+            store.features.extend(feature);
+        }
+        Ok(store)
+    }
+}
+
+/// Three-way merge of a single value
+fn merge_value<'a>(
+    base: Option<&'a Value>,
+    ours: Option<&'a Value>,
+    theirs: Option<&'a Value>,
+) -> Result<Option<&'a Value>, ()> {
+    if ours == theirs || theirs == base {
+        Ok(ours)
+    } else if ours == base {
+        Ok(theirs)
+    } else {
+        Err(())
+    }
+}
+
+/// Merge a feature changed on both sides, field by field
+fn merge_feature(base: Option<&Feature>, ours: &Feature, theirs: &Feature) -> io::Result<Entry> {
+    let base = base.map(to_map).transpose()?.unwrap_or_default();
+    let ours = to_map(ours)?;
+    let theirs = to_map(theirs)?;
+
+    let fields: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut merged_ours = Map::new();
+    let mut merged_theirs = Map::new();
+    let mut conflicting = Vec::new();
+
+    // Take the derivation inputs from the side that changed them, all or nothing
+    let derivation = |map: &Map<String, Value>| -> Vec<Option<Value>> {
+        DERIVATION_FIELDS
+            .iter()
+            .map(|field| map.get(*field).cloned())
+            .collect()
+    };
+    let (b, o, t) = (derivation(&base), derivation(&ours), derivation(&theirs));
+    let derivation_side = if o == t || t == b {
+        Ok(&ours)
+    } else if o == b {
+        Ok(&theirs)
+    } else {
+        Err(())
+    };
+
+    for field in fields {
+        let (b, o, t) = (base.get(field), ours.get(field), theirs.get(field));
+        let merged = if DERIVATION_FIELDS.contains(&field.as_str()) {
+            match derivation_side {
+                Ok(side) => Ok(side.get(field)),
+                Err(()) if o == t => Ok(o),
+                Err(()) => Err(()),
+            }
+        } else {
+            merge_value(b, o, t)
+        };
+        match merged {
+            Ok(value) => {
+                if let Some(value) = value {
+                    merged_ours.insert(field.clone(), value.clone());
+                    merged_theirs.insert(field.clone(), value.clone());
+                }
+            }
+            Err(()) => {
+                conflicting.push(field.clone());
+                if let Some(o) = o {
+                    merged_ours.insert(field.clone(), o.clone());
+                }
+                if let Some(t) = t {
+                    merged_theirs.insert(field.clone(), t.clone());
+                }
+            }
+        }
+    }
+
+    if conflicting.is_empty() {
+        Ok(Entry::Merged(Box::new(from_map(merged_ours)?)))
+    } else {
+        Ok(Entry::Conflict(Conflict::Modified {
+            ours: Box::new(from_map(merged_ours)?),
+            theirs: Box::new(from_map(merged_theirs)?),
+            fields: conflicting,
+        }))
+    }
+}
+
+/// Three-way merge of the trash entries, oldest first
+///
+/// An entry stays if it was added on either side, and goes if either side restored or
+/// purged it.
+fn merge_trash(
+    base: &[TrashedFeature],
+    ours: &[TrashedFeature],
+    theirs: &[TrashedFeature],
+) -> Vec<TrashedFeature> {
+    let contains = |trash: &[TrashedFeature], entry| trash.iter().any(|t| t.is(entry));
+    let mut trash: Vec<TrashedFeature> = ours
+        .iter()
+        .filter(|t| !contains(base, t) || contains(theirs, t))
+        .cloned()
+        .collect();
+    trash.extend(
+        theirs
+            .iter()
+            .filter(|t| !contains(base, t) && !contains(ours, t))
+            .cloned(),
+    );
+    trash.sort_by_key(|t| t.deleted);
+    trash
+}
+
+/// `theirs` with the features added on both sides under the same name and identifier
+/// given our ID, so they merge as one feature instead of ending up twice
+fn match_added(base: &FeatureStore, ours: &FeatureStore, theirs: &FeatureStore) -> FeatureStore {
+    let added: Vec<&Feature> = ours
+        .features
+        .iter()
+        .filter(|f| base.get(&f.id).is_none() && theirs.get(&f.id).is_none())
+        .collect();
+    let mut theirs = theirs.clone();
+    for feature in &mut theirs.features {
+        if base.get(&feature.id).is_some() || ours.get(&feature.id).is_some() {
+            continue;
+        }
+        if let Some(ours) = added
+            .iter()
+            .find(|o| o.name == feature.name && o.feature == feature.feature)
+        {
+            feature.id = ours.id.clone();
+        }
+    }
+    theirs
+}
+
+fn index(store: &FeatureStore) -> BTreeMap<FeatureKey, &Feature> {
+    store.features.iter().map(|f| (key(f), f)).collect()
+}
+
+/// Merge `ours` and `theirs`, which both descend from `base`
+///
+/// The merged store keeps the order of `ours`, followed by features only added in
+/// `theirs`. Store-level fields other than the features and the trash come from `ours`,
+/// except for the signature: the merged store is unsigned until it is written through a
+/// [`crate::storage::Storage`] with the signature key.
+pub fn three_way_merge(
+    base: &FeatureStore,
+    ours: &FeatureStore,
+    theirs: &FeatureStore,
+) -> io::Result<MergeOutcome> {
+    let theirs = &match_added(base, ours, theirs);
+    let base_index = index(base);
+    let ours_index = index(ours);
+    let theirs_index = index(theirs);
+
+    let mut order: Vec<FeatureKey> = ours.features.iter().map(key).collect();
+    order.extend(
+        theirs
+            .features
+            .iter()
+            .map(key)
+            .filter(|k| !ours_index.contains_key(k)),
+    );
+
+    let mut entries = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for k in order {
+        if !seen.insert(k.clone()) {
+            continue;
+        }
+        let (b, o, t) = (
+            base_index.get(&k).copied(),
+            ours_index.get(&k).copied(),
+            theirs_index.get(&k).copied(),
+        );
+        let same = |x: Option<&Feature>, y: Option<&Feature>| -> io::Result<bool> {
+            Ok(x.map(to_map).transpose()? == y.map(to_map).transpose()?)
+        };
+
+        let entry = match (o, t) {
+            (None, None) => continue,
+            (Some(o), Some(t)) => {
+                if same(Some(o), Some(t))? {
+                    Entry::Merged(Box::new(o.clone()))
+                } else {
+                    merge_feature(b, o, t)?
+                }
+            }
+            (Some(only), None) | (None, Some(only)) => {
+                let side = if o.is_some() {
+                    Resolution::Ours
+                } else {
+                    Resolution::Theirs
+                };
+                match b {
+                    // Added on one side
+                    None => Entry::Merged(Box::new(only.clone())),
+                    // Deleted on the other side without changes on this one
+                    Some(b) if same(Some(b), Some(only))? => continue,
+                    Some(_) => Entry::Conflict(Conflict::DeleteModify {
+                        modified: Box::new(only.clone()),
+                        modified_by: side,
+                    }),
+                }
+            }
+        };
+        entries.push(entry);
+    }
+
+    let mut template = ours.clone();
+    template.features.clear();
+    template.trash = merge_trash(&base.trash, &ours.trash, &theirs.trash);
+    template.signature = None;
+
+    Ok(MergeOutcome { template, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Algorithm;
+    use crate::models::test_feature;
+
+    fn feature(name: &str, hint: Option<&str>) -> Feature {
+        let mut f = test_feature(name);
+        f.hint = hint.map(str::to_string);
+        f.id = name.to_lowercase();
+        f.created = "2026-01-01T00:00:00Z".parse().unwrap();
+        f
+    }
+
+    fn store(features: Vec<Feature>) -> FeatureStore {
+        let mut store = FeatureStore::new();
+        store.features = features;
+        store
+    }
+
+    fn names(store: &FeatureStore) -> Vec<&str> {
+        store.features.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_non_overlapping_changes_merge() {
+        let base = store(vec![feature("GitHub", None), feature("Mail", None)]);
+        let ours = store(vec![
+            feature("GitHub", Some("work")),
+            feature("Mail", None),
+            feature("Bank", None),
+        ]);
+        let mut mail = feature("Mail", None);
+        mail.algorithm = Algorithm::Argon2id;
+        let theirs = store(vec![feature("GitHub", None), mail, feature("Shop", None)]);
+
+        let outcome = three_way_merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(outcome.conflicts().count(), 0);
+        let merged = outcome.resolve(|_| unreachable!()).unwrap();
+
+        assert_eq!(names(&merged), ["GitHub", "Mail", "Bank", "Shop"]);
+        assert_eq!(merged.features[0].hint.as_deref(), Some("work"));
+        assert_eq!(merged.features[1].algorithm, Algorithm::Argon2id);
+    }
+
+    #[test]
+    fn test_deletion_merges_when_unmodified() {
+        let base = store(vec![feature("GitHub", None), feature("Mail", None)]);
+        let ours = store(vec![feature("GitHub", None)]);
+        let theirs = base.clone();
+
+        let merged = three_way_merge(&base, &ours, &theirs)
+            .unwrap()
+            .resolve(|_| unreachable!())
+            .unwrap();
+        assert_eq!(names(&merged), ["GitHub"]);
+    }
+
+    #[test]
+    fn test_same_field_conflicts() {
+        let base = store(vec![feature("GitHub", None)]);
+        let mut ours_feature = feature("GitHub", Some("mine"));
+        ours_feature.algorithm = Algorithm::Scrypt;
+        let ours = store(vec![ours_feature]);
+        let mut theirs_feature = feature("GitHub", None);
+        theirs_feature.algorithm = Algorithm::Pbkdf2;
+        let theirs = store(vec![theirs_feature]);
+
+        let outcome = three_way_merge(&base, &ours, &theirs).unwrap();
+        let conflicts: Vec<_> = outcome.conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        match conflicts[0] {
+            Conflict::Modified { fields, theirs, .. } => {
+                assert_eq!(fields, &["algorithm"]);
+                // The non-conflicting hint edit is kept on both candidates
+                assert_eq!(theirs.hint.as_deref(), Some("mine"));
+            }
+            _ => panic!("expected a modify conflict"),
+        }
+
+        let merged = outcome.resolve(|_| Ok(Resolution::Theirs)).unwrap();
+        assert_eq!(merged.features[0].algorithm, Algorithm::Pbkdf2);
+        assert_eq!(merged.features[0].hint.as_deref(), Some("mine"));
+    }
+
+    #[test]
+    fn test_derivation_inputs_merge_as_one() {
+        let base = store(vec![feature("GitHub", None)]);
+        let mut ours_feature = feature("GitHub", None);
+        ours_feature.algorithm = Algorithm::Argon2id;
+        let mut theirs_feature = feature("GitHub", Some("work"));
+        theirs_feature.length = Some(24);
+
+        let outcome = three_way_merge(
+            &base,
+            &store(vec![ours_feature]),
+            &store(vec![theirs_feature]),
+        )
+        .unwrap();
+        let conflicts: Vec<_> = outcome.conflicts().collect();
+        assert_eq!(conflicts.len(), 1);
+        match conflicts[0] {
+            Conflict::Modified {
+                fields,
+                ours,
+                theirs,
+            } => {
+                assert_eq!(fields, &["algorithm", "length"]);
+                // Each candidate derives the password of its own side
+                assert_eq!((ours.algorithm, ours.length), (Algorithm::Argon2id, None));
+                assert_eq!(
+                    (theirs.algorithm, theirs.length),
+                    (Algorithm::HmacSha256, Some(24))
+                );
+                assert_eq!(ours.hint.as_deref(), Some("work"));
+            }
+            _ => panic!("expected a modify conflict"),
+        }
+
+        // Changed on one side only, the inputs merge with the other side's edits
+        let mut theirs_feature = feature("GitHub", Some("work"));
+        theirs_feature.length = Some(24);
+        let merged = three_way_merge(&base, &base, &store(vec![theirs_feature]))
+            .unwrap()
+            .resolve(|_| unreachable!())
+            .unwrap();
+        assert_eq!(merged.features[0].length, Some(24));
+    }
+
+    #[test]
+    fn test_features_match_by_id() {
+        let base = store(vec![feature("GitHub", None)]);
+        let mut renamed = feature("GitHub", None);
+        renamed.name = "GitHub (work)".to_string();
+        let ours = store(vec![renamed]);
+        let theirs = store(vec![feature("GitHub", Some("work")), feature("Mail", None)]);
+
+        // A rename on one side and an edit on the other are the same feature
+        let merged = three_way_merge(&base, &ours, &theirs)
+            .unwrap()
+            .resolve(|_| unreachable!())
+            .unwrap();
+        assert_eq!(names(&merged), ["GitHub (work)", "Mail"]);
+        assert_eq!(merged.features[0].hint.as_deref(), Some("work"));
+
+        // The same feature added on both sides merges into one, keeping our ID
+        let mut added = feature("Mail", None);
+        added.id = "other".to_string();
+        let merged = three_way_merge(
+            &FeatureStore::new(),
+            &store(vec![added]),
+            &store(vec![feature("Mail", None)]),
+        )
+        .unwrap()
+        .resolve(|_| unreachable!())
+        .unwrap();
+        assert_eq!(names(&merged), ["Mail"]);
+        assert_eq!(merged.features[0].id, "other");
+    }
+
+    #[test]
+    fn test_trash_merges() {
+        let now = chrono::Utc::now();
+        let mut base = store(vec![
+            feature("GitHub", None),
+            feature("Mail", None),
+            feature("Old", None),
+        ]);
+        let old = base.trash_feature("old", now).unwrap();
+
+        // We delete one feature, they delete another and restore the old one
+        let mut ours = base.clone();
+        ours.trash_feature("github", now);
+        let mut theirs = base.clone();
+        theirs.trash_feature("mail", now);
+        assert!(theirs.restore_feature(&old));
+
+        let merged = three_way_merge(&base, &ours, &theirs)
+            .unwrap()
+            .resolve(|_| unreachable!())
+            .unwrap();
+        assert_eq!(names(&merged), ["Old"]);
+        let trashed: Vec<&str> = merged
+            .trash
+            .iter()
+            .map(|t| t.feature.name.as_str())
+            .collect();
+        assert_eq!(trashed, ["GitHub", "Mail"]);
+    }
+
+    #[test]
+    fn test_delete_modify_conflict() {
+        let base = store(vec![feature("GitHub", None)]);
+        let ours = store(vec![]);
+        let theirs = store(vec![feature("GitHub", Some("still used"))]);
+
+        let outcome = three_way_merge(&base, &ours, &theirs).unwrap();
+        assert!(matches!(
+            outcome.conflicts().next(),
+            Some(Conflict::DeleteModify {
+                modified_by: Resolution::Theirs,
+                ..
+            })
+        ));
+        let merged = outcome.resolve(|_| Ok(Resolution::Ours)).unwrap();
+        assert!(merged.features.is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+//! Schema versioning for the feature store
+//!
+//! Every store records the `schema_version` it was written with. On load the raw
+//! document is upgraded step by step to [`CURRENT_SCHEMA_VERSION`] before it is
+//! deserialized into a `FeatureStore`. Stores written by a newer SaltPass are rejected
+//! instead of being loaded with their unknown data silently dropped.
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::io::{self, ErrorKind};
+
+/// Schema version written by this build of SaltPass
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+/// A migration upgrades a store document from version `n` to `n + 1`
+type Migration = fn(&mut Map<String, Value>);
+
+/// Migrations indexed by the version they upgrade from
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// Read the schema version of a raw store document (stores without one are version 0)
+pub fn schema_version(doc: &Value) -> io::Result<u32> {
+    match doc.get("schema_version") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Invalid schema_version")),
+    }
+}
+
+/// Upgrade a raw store document to the current schema version
+pub fn migrate(mut doc: Value) -> io::Result<Value> {
+    let version = schema_version(&doc)?;
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Features file uses schema version {}, but this SaltPass only supports up to \
+                 version {}. Please upgrade SaltPass; the file was left untouched.",
+                version, CURRENT_SCHEMA_VERSION
+            ),
+        ));
+    }
+
+    let map = doc
+        .as_object_mut()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Features file is not a table"))?;
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(map);
+    }
+    map.insert(
+        "schema_version".to_string(),
+        Value::from(CURRENT_SCHEMA_VERSION),
+    );
+
+    Ok(doc)
+}
+
+/// Iterate over the feature tables of a store document
+fn features_mut(doc: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    entries_mut(doc, "features")
+}
+
+/// Iterate over the tables of an array field of a store document
+fn entries_mut<'a>(
+    doc: &'a mut Map<String, Value>,
+    field: &str,
+) -> impl Iterator<Item = &'a mut Map<String, Value>> {
+    doc.get_mut(field)
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+/// v0 → v1: introduce `schema_version` and record the implicit default algorithm
+///
+/// Stores written before 0.1.1 have no `algorithm` field and were generated with
+/// HMAC-SHA256. Writing it out explicitly keeps those features stable even if the
+/// default algorithm ever changes.
+fn v0_to_v1(doc: &mut Map<String, Value>) {
+    doc.entry("features")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    for feature in features_mut(doc) {
+        feature
+            .entry("algorithm")
+            .or_insert_with(|| Value::from("HmacSha256"));
+    }
+}
+
+/// v1 → v2: give every feature an `id`
+///
+/// New features get random IDs, but the ID of an existing feature is derived from its
+/// name, identifier and creation time, so every device migrating the same store (or
+/// loading it again before it is saved) assigns the same IDs.
+fn v1_to_v2(doc: &mut Map<String, Value>) {
+    for field in ["features", "trash"] {
+        for feature in entries_mut(doc, field) {
+            if !feature.contains_key("id") {
+                let id = feature_id(feature);
+                feature.insert("id".to_string(), Value::from(id));
+            }
+        }
+    }
+}
+
+/// ID of a serialized feature: its `id`, or for a feature written before v2 the ID that
+/// [`v1_to_v2`] gives it
+pub fn feature_id(feature: &Map<String, Value>) -> String {
+    if let Some(id) = feature.get("id").and_then(Value::as_str) {
+        return id.to_string();
+    }
+    let mut hasher = Sha256::new();
+    for field in ["name", "feature", "created"] {
+        let value = feature
+            .get(field)
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// v2 → v3: features may have a `login`, which is part of the password derivation
+///
+/// Nothing changes in the document. The version is raised so that older SaltPass builds,
+/// which would ignore the login and generate the wrong password, refuse these stores.
+fn v2_to_v3(_doc: &mut Map<String, Value>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_v0_to_v1_sets_default_algorithm() {
+        let doc = json!({
+            "features": [
+                { "name": "GitHub", "feature": "github.com", "created": "2026-01-01T00:00:00Z" },
+                { "name": "Mail", "feature": "mail.com", "algorithm": "Argon2id", "created": "2026-01-01T00:00:00Z" }
+            ]
+        });
+
+        let migrated = migrate(doc).unwrap();
+        assert_eq!(migrated["schema_version"], CURRENT_SCHEMA_VERSION);
+        assert_eq!(migrated["features"][0]["algorithm"], "HmacSha256");
+        assert_eq!(migrated["features"][1]["algorithm"], "Argon2id");
+    }
+
+    #[test]
+    fn test_v0_to_v1_empty_store() {
+        let migrated = migrate(json!({})).unwrap();
+        assert_eq!(migrated["features"], json!([]));
+    }
+
+    #[test]
+    fn test_v1_to_v2_assigns_stable_ids() {
+        let doc = json!({
+            "schema_version": 1,
+            "features": [
+                { "name": "GitHub", "feature": "github.com", "created": "2026-01-01T00:00:00Z" },
+                { "name": "Mail", "feature": "mail.com", "created": "2026-01-01T00:00:00Z" }
+            ],
+            "trash": [
+                { "name": "Bank", "feature": "bank.com", "created": "2026-01-01T00:00:00Z",
+                  "deleted": "2026-02-01T00:00:00Z" }
+            ]
+        });
+
+        let migrated = migrate(doc.clone()).unwrap();
+        let id = |v: &Value| v.as_str().unwrap().to_string();
+        let github = id(&migrated["features"][0]["id"]);
+        assert_eq!(github.len(), 16);
+        assert_ne!(github, id(&migrated["features"][1]["id"]));
+        assert!(migrated["trash"][0]["id"].is_string());
+        // Migrating the same store again gives the same IDs
+        assert_eq!(migrate(doc.clone()).unwrap(), migrated);
+        assert_eq!(feature_id(doc["features"][0].as_object().unwrap()), github);
+    }
+
+    #[test]
+    fn test_newer_schema_is_rejected() {
+        let doc = json!({ "schema_version": CURRENT_SCHEMA_VERSION + 1, "features": [] });
+        let err = migrate(doc).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("upgrade SaltPass"));
+    }
+
+    #[test]
+    fn test_current_schema_is_untouched() {
+        let doc = json!({ "schema_version": CURRENT_SCHEMA_VERSION, "features": [], "future": 1 });
+        assert_eq!(migrate(doc.clone()).unwrap(), doc);
+    }
+}
@@ -3,11 +3,19 @@
 //! This module defines the core data structures used in SaltPass:
 //! - `Salt`: Master salt stored securely in memory
 //! - `Feature`: Feature identifiers for password generation
+//! - `LengthChoice`: How the length of a generated password is chosen
+//! - `CustomField`: Extra key/value field of a feature, plain or concealed
+//! - `TrashedFeature`: Deleted feature kept in the trash
 //! - `FeatureStore`: Collection of features
 
-use crate::crypto::Algorithm;
+use crate::crypto::{self, Algorithm, KdfParams, PasswordGenerator};
+use crate::domain::{self, Normalization};
+use crate::migrations::CURRENT_SCHEMA_VERSION;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, ErrorKind};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// Master salt that is automatically zeroed when dropped
@@ -33,45 +41,334 @@ impl Salt {
 ///
 /// Each feature represents a unique identifier (e.g., website domain) that combined
 /// with the master salt produces a unique password.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Feature {
+    /// Stable ID of the feature, kept when it is edited or replaced; not part of the
+    /// password derivation
+    pub id: String,
     pub name: String,
     pub feature: String,
+    /// How `feature` was normalized when it was entered, see [`crate::domain`]
+    #[serde(default, skip_serializing_if = "Normalization::is_exact")]
+    pub normalization: Normalization,
+    /// Other identifiers of the same site, such as domains sharing its login; lookups find
+    /// the feature through them, but they are not part of the password derivation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Login on the site, part of the password derivation so several accounts on the same
+    /// site get independent passwords; see [`crypto::derivation_input`]. Set it with
+    /// [`Feature::set_login`] so `username` follows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub login: Option<String>,
     #[serde(default)]
     pub algorithm: Algorithm,
+    /// Algorithm cost parameters, `None` means the algorithm's defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<KdfParams>,
+    /// Password length chosen when the feature was added, `None` for features added before
+    /// lengths were stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
     pub created: DateTime<Utc>,
+    /// When the password was last changed, `None` if never since `created`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_rotated: Option<DateTime<Utc>>,
+    /// Maximum password age in days, see [`crate::rotation`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u32>,
     pub hint: Option<String>,
+    /// Login name of the account as typed on the site, shown and copied but not part of
+    /// the derivation; the same as `login` unless it was set to something else, such as
+    /// an email address for a site that also accepts it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// Free-form notes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Custom key/value fields such as security questions or recovery codes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<CustomField>,
+    /// Labels to filter by, see [`crate::filter`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Folder path such as `Work/Dev`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /// Fields written by a newer SaltPass, preserved as-is when saving
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 impl Feature {
     pub fn new(name: String, feature: String, algorithm: Algorithm, hint: Option<String>) -> Self {
         Self {
+            id: Self::new_id(),
             name,
             feature,
+            normalization: Normalization::Exact,
+            aliases: Vec::new(),
+            login: None,
             algorithm,
+            params: None,
+            length: None,
             created: Utc::now(),
+            last_rotated: None,
+            max_age: None,
             hint,
+            username: None,
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            tags: Vec::new(),
+            folder: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// A new random feature ID: 16 hex digits
+    pub fn new_id() -> String {
+        crypto::random_hex(8)
+    }
+
+    /// Set the algorithm parameters, only storing them if they differ from the defaults
+    pub fn with_params(mut self, params: KdfParams) -> Self {
+        self.params = (params != self.algorithm.default_params()).then_some(params);
+        self
+    }
+
+    /// Algorithm parameters used to derive this feature's password
+    pub fn params(&self) -> KdfParams {
+        self.params
+            .unwrap_or_else(|| self.algorithm.default_params())
+    }
+
+    /// Set the login from user input, empty for none
+    ///
+    /// The username follows the login, unless it was set to something else than the
+    /// previous login.
+    pub fn set_login(&mut self, login: &str) {
+        let login = login.trim();
+        let login = (!login.is_empty()).then(|| login.to_string());
+        if self.username.is_none() || self.username == self.login {
+            self.username = login.clone();
+        }
+        self.login = login;
+    }
+
+    /// How to choose the password length when generating, `once` to override it for this
+    /// time only; `default` is the configured length
+    pub fn length_choice(&self, once: bool, default: usize) -> LengthChoice {
+        match self.length {
+            Some(length) if once => LengthChoice::Ask {
+                default: length,
+                remember: false,
+            },
+            Some(length) => LengthChoice::Recorded(length),
+            None => LengthChoice::Ask {
+                default,
+                remember: !once,
+            },
+        }
+    }
+
+    /// Whether `other` derives the same password from the same master salt; a length that
+    /// isn't recorded is taken to match any
+    pub fn same_password_as(&self, other: &Feature) -> bool {
+        crypto::derivation_input(&self.feature, self.login.as_deref())
+            == crypto::derivation_input(&other.feature, other.login.as_deref())
+            && self.algorithm == other.algorithm
+            && self.params() == other.params()
+            && (self.length == other.length || self.length.is_none() || other.length.is_none())
+    }
+
+    /// Whether `query`, normalized the way this feature's identifier was, names this
+    /// feature's identifier or one of its aliases
+    pub fn matches_identifier(&self, query: &str) -> bool {
+        let query = domain::normalize(query, self.normalization);
+        self.feature == query || self.aliases.contains(&query)
+    }
+
+    /// The account details that can be copied: username, URLs, notes and custom fields,
+    /// in that order
+    pub fn details(&self) -> Vec<Detail<'_>> {
+        let mut details = Vec::new();
+        if let Some(username) = &self.username {
+            details.push(Detail::new(
+                "Username".to_string(),
+                username,
+                FieldKind::Plain,
+            ));
+        }
+        for (i, url) in self.urls.iter().enumerate() {
+            let label = if self.urls.len() == 1 {
+                "URL".to_string()
+            } else {
+                format!("URL {}", i + 1)
+            };
+            details.push(Detail::new(label, url, FieldKind::Plain));
+        }
+        if let Some(notes) = &self.notes {
+            details.push(Detail::new("Notes".to_string(), notes, FieldKind::Plain));
+        }
+        for field in &self.fields {
+            details.push(Detail::new(field.name.clone(), &field.value, field.kind));
         }
+        details
     }
+
+    /// Derive this feature's password from the master salt
+    ///
+    /// Fails if the feature's stored parameters are invalid for its algorithm.
+    pub fn password(&self, salt: &Salt, length: usize) -> io::Result<String> {
+        PasswordGenerator::generate_with_params(
+            salt.value(),
+            &crypto::derivation_input(&self.feature, self.login.as_deref()),
+            length,
+            self.algorithm,
+            &self.params(),
+        )
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", self.name, e)))
+    }
+}
+
+/// Feature used by tests: `name` on `<name>.com`, with the default algorithm and no hint
+#[cfg(test)]
+pub fn test_feature(name: &str) -> Feature {
+    Feature::new(
+        name.to_string(),
+        format!("{}.com", name.to_lowercase()),
+        Algorithm::HmacSha256,
+        None,
+    )
 }
 
+/// How the length of a generated password is chosen, see [`Feature::length_choice`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthChoice {
+    /// Use the length recorded with the feature
+    Recorded(usize),
+    /// Ask for a length starting from `default`, and if `remember`, offer to record it
+    Ask { default: usize, remember: bool },
+}
+
+/// Whether a custom field's value may be shown on screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    #[default]
+    Plain,
+    /// Masked when displayed, like a password
+    Concealed,
+}
+
+/// A custom key/value field of a feature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+}
+
+/// A labelled account detail of a feature, see [`Feature::details`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detail<'a> {
+    pub label: String,
+    pub value: &'a str,
+    pub kind: FieldKind,
+}
+
+impl<'a> Detail<'a> {
+    fn new(label: String, value: &'a str, kind: FieldKind) -> Self {
+        Self { label, value, kind }
+    }
+
+    /// The value as it may be shown on screen: concealed values are masked
+    pub fn display(&self) -> &str {
+        match self.kind {
+            FieldKind::Plain => self.value,
+            FieldKind::Concealed => "••••••••",
+        }
+    }
+}
+
+/// A deleted feature kept in the store's trash until it is purged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedFeature {
+    pub deleted: DateTime<Utc>,
+    #[serde(flatten)]
+    pub feature: Feature,
+}
+
+impl TrashedFeature {
+    /// Whether this is the same trash entry (same feature ID, deleted at the same time)
+    pub fn is(&self, other: &TrashedFeature) -> bool {
+        self.deleted == other.deleted && self.feature.id == other.feature.id
+    }
+}
+
+/// Error returned by [`FeatureStore::add_feature`] when a feature with the same name and
+/// identifier already exists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateFeature {
+    pub name: String,
+    pub feature: String,
+}
+
+impl fmt::Display for DuplicateFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A feature named '{}' for '{}' already exists",
+            self.name, self.feature
+        )
+    }
+}
+
+impl std::error::Error for DuplicateFeature {}
+
 /// Collection of features stored on disk
 ///
 /// This structure holds all feature identifiers and can be serialized to/from JSON or TOML.
-#[derive(Debug, Serialize, Deserialize)]
+/// See [`crate::migrations`] for how older schema versions are upgraded on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FeatureStore {
+    pub schema_version: u32,
     pub features: Vec<Feature>,
+    /// Deleted features, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash: Vec<TrashedFeature>,
+    /// HMAC of a signed plain store, see [`crate::signature`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Fields written by a newer SaltPass, preserved as-is when saving
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
 impl FeatureStore {
     pub fn new() -> Self {
         Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
             features: Vec::new(),
+            trash: Vec::new(),
+            signature: None,
+            extra: BTreeMap::new(),
         }
     }
 
-    pub fn add_feature(&mut self, feature: Feature) {
+    /// Add a new feature, unless one with the same name and identifier exists
+    pub fn add_feature(&mut self, feature: Feature) -> Result<(), DuplicateFeature> {
+        if self.find(&feature.name, &feature.feature).is_some() {
+            return Err(DuplicateFeature {
+                name: feature.name,
+                feature: feature.feature,
+            });
+        }
         self.features.push(feature);
+        Ok(())
     }
 
     pub fn remove_feature(&mut self, index: usize) -> Option<Feature> {
@@ -85,6 +382,136 @@ impl FeatureStore {
     pub fn list_features(&self) -> &[Feature] {
         &self.features
     }
+
+    /// Feature with the given ID
+    pub fn get(&self, id: &str) -> Option<&Feature> {
+        self.features.iter().find(|f| f.id == id)
+    }
+
+    /// Feature with the given name and identifier
+    pub fn find(&self, name: &str, feature: &str) -> Option<&Feature> {
+        self.features
+            .iter()
+            .find(|f| f.name == name && f.feature == feature)
+    }
+
+    /// Features with exactly this name
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn find_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Feature> {
+        self.features.iter().filter(move |f| f.name == name)
+    }
+
+    /// Features for this identifier or URL, including those with it as an alias
+    ///
+    /// The query is normalized the way each feature's identifier was, so
+    /// `https://www.GitHub.com/login` finds a feature saved as `github.com` with host
+    /// normalization. Features kept as typed only match exactly.
+    pub fn find_by_identifier<'a>(&'a self, query: &'a str) -> impl Iterator<Item = &'a Feature> {
+        self.features
+            .iter()
+            .filter(move |f| f.matches_identifier(query))
+    }
+
+    /// Replace the feature with the same ID, or else the one with the same name and
+    /// identifier, or add it
+    pub fn put_feature(&mut self, feature: Feature) {
+        let index = self
+            .features
+            .iter()
+            .position(|f| f.id == feature.id)
+            .or_else(|| {
+                self.features
+                    .iter()
+                    .position(|f| f.name == feature.name && f.feature == feature.feature)
+            });
+        match index.map(|i| &mut self.features[i]) {
+            Some(existing) => *existing = feature,
+            None => self.features.push(feature),
+        }
+    }
+
+    /// Replace the feature with the given ID by `updated`, returning the previous version
+    ///
+    /// `updated` takes over the ID. Returns `Ok(None)` if no feature has this ID, and an
+    /// error if another feature already has the new name and identifier.
+    pub fn update(
+        &mut self,
+        id: &str,
+        mut updated: Feature,
+    ) -> Result<Option<Feature>, DuplicateFeature> {
+        let Some(index) = self.features.iter().position(|f| f.id == id) else {
+            return Ok(None);
+        };
+        if self
+            .features
+            .iter()
+            .any(|f| f.id != id && f.name == updated.name && f.feature == updated.feature)
+        {
+            return Err(DuplicateFeature {
+                name: updated.name,
+                feature: updated.feature,
+            });
+        }
+        updated.id = id.to_string();
+        Ok(Some(std::mem::replace(&mut self.features[index], updated)))
+    }
+
+    /// Record the length of a feature added before lengths were stored, returning the
+    /// updated feature
+    ///
+    /// Returns `None` if no feature has this ID or it already has a length, since changing
+    /// a recorded length changes the password.
+    pub fn record_length(&mut self, id: &str, length: usize) -> Option<Feature> {
+        let feature = self
+            .features
+            .iter_mut()
+            .find(|f| f.id == id && f.length.is_none())?;
+        feature.length = Some(length);
+        Some(feature.clone())
+    }
+
+    /// Remove the feature with the given ID, returning it if it existed
+    pub fn delete_feature(&mut self, id: &str) -> Option<Feature> {
+        let index = self.features.iter().position(|f| f.id == id)?;
+        self.remove_feature(index)
+    }
+
+    /// Move the feature with the given ID to the trash, returning the trash entry if the
+    /// feature existed
+    pub fn trash_feature(&mut self, id: &str, deleted: DateTime<Utc>) -> Option<TrashedFeature> {
+        let feature = self.delete_feature(id)?;
+        let entry = TrashedFeature { deleted, feature };
+        self.trash.push(entry.clone());
+        Some(entry)
+    }
+
+    /// Put a trashed feature back, replacing a feature with the same name and identifier
+    ///
+    /// Returns `false` if the entry is no longer in the trash.
+    pub fn restore_feature(&mut self, entry: &TrashedFeature) -> bool {
+        match self.purge_feature(entry) {
+            Some(restored) => {
+                self.put_feature(restored.feature);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove an entry from the trash for good
+    pub fn purge_feature(&mut self, entry: &TrashedFeature) -> Option<TrashedFeature> {
+        let index = self.trash.iter().position(|t| t.is(entry))?;
+        Some(self.trash.remove(index))
+    }
+
+    /// Remove the trash entries deleted before `cutoff`, returning them
+    pub fn purge_older_than(&mut self, cutoff: DateTime<Utc>) -> Vec<TrashedFeature> {
+        let (purged, kept) = std::mem::take(&mut self.trash)
+            .into_iter()
+            .partition(|t| t.deleted < cutoff);
+        self.trash = kept;
+        purged
+    }
 }
 
 impl Default for FeatureStore {
@@ -92,3 +519,255 @@ impl Default for FeatureStore {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_lookups_and_duplicates() {
+        let mut store = FeatureStore::new();
+        let github = test_feature("GitHub");
+        let id = github.id.clone();
+        store.add_feature(github).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        store
+            .add_feature(Feature::new(
+                "GitHub".to_string(),
+                "github.example".to_string(),
+                Algorithm::HmacSha256,
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!(id.len(), 16);
+        assert_ne!(store.features[0].id, store.features[1].id);
+        assert_eq!(store.get(&id).unwrap().feature, "github.com");
+        assert!(store.get("0000000000000000").is_none());
+        assert_eq!(store.find_by_name("GitHub").count(), 2);
+        assert_eq!(store.find_by_identifier("mail.com").count(), 1);
+        assert_eq!(store.find_by_identifier("https://mail.com").count(), 0);
+        store.features[1].normalization = Normalization::Host;
+        assert_eq!(
+            store
+                .find_by_identifier("https://www.Mail.com/inbox")
+                .count(),
+            1
+        );
+        store.features[1].aliases = vec!["mail.example".to_string()];
+        assert_eq!(
+            store
+                .find_by_identifier("https://mail.example/")
+                .next()
+                .unwrap()
+                .name,
+            "Mail"
+        );
+        assert_eq!(
+            store.find("GitHub", "github.example").unwrap().name,
+            "GitHub"
+        );
+
+        assert_eq!(
+            store.add_feature(test_feature("GitHub")),
+            Err(DuplicateFeature {
+                name: "GitHub".to_string(),
+                feature: "github.com".to_string(),
+            })
+        );
+        assert_eq!(store.features.len(), 3);
+
+        // Lookups by ID do not depend on the order of the features
+        store.remove_feature(0);
+        assert!(store.get(&id).is_none());
+        store.put_feature(Feature {
+            id: id.clone(),
+            ..test_feature("GitHub")
+        });
+        assert_eq!(store.features[2].id, id);
+        assert_eq!(store.get(&id).unwrap().name, "GitHub");
+    }
+
+    #[test]
+    fn test_username_follows_login() {
+        let mut github = test_feature("GitHub");
+        github.set_login(" octocat ");
+        assert_eq!(github.login.as_deref(), Some("octocat"));
+        assert_eq!(github.username.as_deref(), Some("octocat"));
+        github.set_login("hubot");
+        assert_eq!(github.username.as_deref(), Some("hubot"));
+
+        // A username set to something else is kept
+        github.username = Some("hubot@example.com".to_string());
+        github.set_login("octocat");
+        assert_eq!(github.username.as_deref(), Some("hubot@example.com"));
+        github.set_login("");
+        assert_eq!(github.login, None);
+        assert_eq!(github.username.as_deref(), Some("hubot@example.com"));
+    }
+
+    #[test]
+    fn test_update_keeps_identity() {
+        let mut store = FeatureStore::new();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        let original = store.features[0].clone();
+
+        let mut edited = original.clone();
+        edited.hint = Some("work".to_string());
+        assert!(edited.same_password_as(&original));
+        edited.name = "GitHub (work)".to_string();
+        let previous = store.update(&original.id, edited).unwrap().unwrap();
+        assert_eq!(previous.name, "GitHub");
+        let updated = store.get(&original.id).unwrap();
+        assert_eq!(updated.name, "GitHub (work)");
+        assert_eq!(updated.created, original.created);
+
+        // Renaming onto another feature is refused
+        let mut clash = store.features[0].clone();
+        clash.name = "Mail".to_string();
+        clash.feature = "mail.com".to_string();
+        assert!(!clash.same_password_as(&original));
+        assert!(store.update(&original.id, clash).is_err());
+        assert_eq!(store.features[0].name, "GitHub (work)");
+
+        assert!(matches!(
+            store.update("missing", test_feature("Bank")),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn test_stored_length() {
+        let mut store = FeatureStore::new();
+        let mut github = test_feature("GitHub");
+        github.length = Some(20);
+        store.add_feature(github).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        let (github, mail) = (store.features[0].clone(), store.features[1].clone());
+
+        // A recorded length is used as is, or asked for starting from it when overridden
+        // once, and an override is never remembered
+        assert_eq!(github.length_choice(false, 16), LengthChoice::Recorded(20));
+        assert_eq!(
+            github.length_choice(true, 16),
+            LengthChoice::Ask {
+                default: 20,
+                remember: false
+            }
+        );
+        assert_eq!(
+            mail.length_choice(true, 16),
+            LengthChoice::Ask {
+                default: 16,
+                remember: false
+            }
+        );
+
+        // Features added before lengths were stored ask and offer to remember the answer
+        assert_eq!(
+            mail.length_choice(false, 16),
+            LengthChoice::Ask {
+                default: 16,
+                remember: true
+            }
+        );
+        let recorded = store.record_length(&mail.id, 24).unwrap();
+        assert_eq!(store.features[1].length, Some(24));
+        assert_eq!(
+            recorded.length_choice(false, 16),
+            LengthChoice::Recorded(24)
+        );
+        // Recording the length of an older feature keeps its password
+        assert!(recorded.same_password_as(&mail));
+
+        // A recorded length is not replaced, and changing it gives a new password
+        assert!(store.record_length(&github.id, 24).is_none());
+        assert!(store.record_length("missing", 24).is_none());
+        assert_eq!(store.features[0].length, Some(20));
+        let mut longer = github.clone();
+        longer.length = Some(24);
+        assert!(!longer.same_password_as(&github));
+    }
+
+    #[test]
+    fn test_trash_restore_and_purge() {
+        let mut store = FeatureStore::new();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+
+        let (github, mail) = (store.features[0].id.clone(), store.features[1].id.clone());
+
+        let now = Utc::now();
+        let old = store
+            .trash_feature(&github, now - Duration::days(40))
+            .unwrap();
+        let recent = store.trash_feature(&mail, now).unwrap();
+        assert!(store.trash_feature(&mail, now).is_none());
+        assert!(store.features.is_empty());
+
+        // The trash survives a round trip and unknown fields stay on the feature
+        let toml = toml::to_string_pretty(&store).unwrap();
+        let mut store: FeatureStore = toml::from_str(&toml).unwrap();
+        assert_eq!(store.trash.len(), 2);
+        assert!(store.trash[1].feature.extra.is_empty());
+
+        // Entries are matched by ID, and restoring replaces a feature added again since
+        let mut renamed = recent.clone();
+        renamed.feature.name = "Mail (old)".to_string();
+        assert!(store.trash[1].is(&renamed));
+        store.add_feature(test_feature("Mail")).unwrap();
+        assert!(store.restore_feature(&recent));
+        assert!(!store.restore_feature(&recent));
+        assert_eq!(store.features.len(), 1);
+        assert_eq!(store.features[0].id, mail);
+
+        let purged = store.purge_older_than(now - Duration::days(30));
+        assert_eq!(purged.len(), 1);
+        assert!(purged[0].is(&old));
+        assert!(store.trash.is_empty());
+    }
+
+    #[test]
+    fn test_account_details() {
+        let mut github = test_feature("GitHub");
+        assert!(github.details().is_empty());
+        let plain = serde_json::to_value(&github).unwrap();
+        assert!(plain.get("username").is_none() && plain.get("fields").is_none());
+
+        github.username = Some("octocat".to_string());
+        github.urls = vec![
+            "https://github.com".to_string(),
+            "https://gist.github.com".to_string(),
+        ];
+        github.fields = vec![
+            CustomField {
+                name: "Recovery code".to_string(),
+                value: "1234-5678".to_string(),
+                kind: FieldKind::Concealed,
+            },
+            CustomField {
+                name: "Team".to_string(),
+                value: "core".to_string(),
+                kind: FieldKind::Plain,
+            },
+        ];
+
+        let details = github.details();
+        let labels: Vec<&str> = details.iter().map(|d| d.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            ["Username", "URL 1", "URL 2", "Recovery code", "Team"]
+        );
+        assert_eq!(details[3].value, "1234-5678");
+        assert_eq!(details[3].display(), "••••••••");
+        assert_eq!(details[4].display(), "core");
+
+        let json = serde_json::to_value(&github).unwrap();
+        assert_eq!(json["fields"][0]["kind"], "concealed");
+        let parsed: Feature = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.fields, github.fields);
+        assert!(parsed.extra.is_empty());
+    }
+}
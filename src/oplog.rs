@@ -0,0 +1,469 @@
+//! Append-only operation log store format
+//!
+//! Instead of a snapshot, the store is kept as a log of operations with one JSON entry
+//! per line. Every entry carries the ID of the device that wrote it and a Lamport
+//! timestamp. Loading folds the entries in timestamp order, ties broken by device ID,
+//! and the last write wins per feature. Two copies edited independently can therefore
+//! be combined by simply concatenating their files.
+//!
+//! In encrypted mode every line is encrypted on its own with [`StorageCipher`].
+
+use crate::crypto::{StorageCipher, StorageKey, random_hex};
+use crate::migrations::{self, CURRENT_SCHEMA_VERSION};
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, ErrorKind};
+
+/// One operation on the store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Op {
+    Add {
+        feature: Value,
+    },
+    Update {
+        feature: Value,
+    },
+    /// Delete a feature; `feature` holds its last known identity fields
+    Delete {
+        feature: Value,
+    },
+    /// Set a store-level field other than the features
+    Set {
+        field: String,
+        value: Value,
+    },
+}
+
+/// A line of the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub device: String,
+    pub lamport: u64,
+    /// Schema version of the payload
+    pub schema: u32,
+    #[serde(flatten)]
+    pub op: Op,
+}
+
+impl Entry {
+    /// Entries are applied in this order
+    fn order(&self) -> (u64, &str) {
+        (self.lamport, &self.device)
+    }
+
+    /// What the entry writes to: a feature identity or a store field
+    fn target(&self) -> String {
+        match &self.op {
+            Op::Add { feature } | Op::Update { feature } | Op::Delete { feature } => {
+                format!("feature:{}", feature_key(feature))
+            }
+            Op::Set { field, .. } => format!("field:{}", field),
+        }
+    }
+}
+
+/// Identity of a serialized feature: its ID, derived as on migration for entries written
+/// before features had IDs
+fn feature_key(feature: &Value) -> String {
+    feature
+        .as_object()
+        .map(migrations::feature_id)
+        .unwrap_or_default()
+}
+
+/// ID of this machine, created on first use in `~/.saltpass/device_id`
+pub fn local_device_id() -> io::Result<String> {
+    let path = Storage::config_dir()?.join("device_id");
+    match fs::read_to_string(&path) {
+        Ok(id) if !id.trim().is_empty() => Ok(id.trim().to_string()),
+        Ok(_) => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Empty device_id file",
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let id = random_hex(8);
+            fs::write(&path, &id)?;
+            Ok(id)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse a log file, decrypting each line if a key is given
+pub fn parse(content: &str, key: Option<&StorageKey>) -> io::Result<Vec<Entry>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            let json = match key {
+                Some(key) => String::from_utf8(
+                    StorageCipher::decrypt_with_key(key, line)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+                )
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+                None => line.to_string(),
+            };
+            serde_json::from_str(&json).map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid log entry {}: {}", index + 1, e),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Render an entry as a line of the log (without the newline)
+pub fn render(entry: &Entry, key: Option<&StorageKey>) -> io::Result<String> {
+    let json =
+        serde_json::to_string(entry).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    match key {
+        Some(key) => StorageCipher::encrypt_with_key(key, json.as_bytes())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+        None => Ok(json),
+    }
+}
+
+/// Sort entries into application order, dropping duplicates from concatenated logs
+fn ordered(entries: &[Entry]) -> Vec<&Entry> {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.order().cmp(&b.order()));
+    sorted.dedup_by(|a, b| a.order() == b.order());
+    sorted
+}
+
+/// Fold the log into a store document ready for [`crate::migrations::migrate`]
+pub fn fold(entries: &[Entry]) -> io::Result<Value> {
+    if let Some(newest) = entries.iter().map(|e| e.schema).max()
+        && newest > CURRENT_SCHEMA_VERSION
+    {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Operation log contains entries with schema version {}, but this SaltPass \
+                 only supports up to version {}. Please upgrade SaltPass; the file was left \
+                 untouched.",
+                newest, CURRENT_SCHEMA_VERSION
+            ),
+        ));
+    }
+
+    let mut order: Vec<String> = Vec::new();
+    let mut features: HashMap<String, Value> = HashMap::new();
+    let mut doc = Map::new();
+
+    for entry in ordered(entries) {
+        match &entry.op {
+            Op::Add { feature } | Op::Update { feature } => {
+                let key = feature_key(feature);
+                if features.insert(key.clone(), feature.clone()).is_none() {
+                    order.retain(|k| *k != key);
+                    order.push(key);
+                }
+            }
+            Op::Delete { feature } => {
+                features.remove(&feature_key(feature));
+            }
+            Op::Set { field, value } => {
+                doc.insert(field.clone(), value.clone());
+            }
+        }
+    }
+
+    let features: Vec<Value> = order
+        .iter()
+        .filter_map(|key| features.remove(key))
+        .collect();
+    let schema = entries
+        .iter()
+        .map(|e| e.schema)
+        .min()
+        .unwrap_or(CURRENT_SCHEMA_VERSION);
+    doc.insert("schema_version".to_string(), Value::from(schema));
+    doc.insert("features".to_string(), Value::Array(features));
+    Ok(Value::Object(doc))
+}
+
+/// Operations turning store document `old` into `new`
+pub fn diff(old: &Value, new: &Value) -> Vec<Op> {
+    let index = |doc: &Value| -> BTreeMap<String, Value> {
+        doc.get("features")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(|f| (feature_key(f), f.clone()))
+            .collect()
+    };
+    let (old_features, new_features) = (index(old), index(new));
+    let mut ops = Vec::new();
+
+    for feature in new
+        .get("features")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match old_features.get(&feature_key(feature)) {
+            None => ops.push(Op::Add {
+                feature: feature.clone(),
+            }),
+            Some(previous) if previous != feature => ops.push(Op::Update {
+                feature: feature.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (key, feature) in &old_features {
+        if !new_features.contains_key(key) {
+            ops.push(Op::Delete {
+                feature: feature.clone(),
+            });
+        }
+    }
+
+    let fields = |doc: &Value| -> Map<String, Value> {
+        let mut map = doc.as_object().cloned().unwrap_or_default();
+        map.remove("features");
+        map.remove("schema_version");
+        map
+    };
+    let (old_fields, new_fields) = (fields(old), fields(new));
+    let names: BTreeSet<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+    for name in names {
+        let value = new_fields.get(name).cloned().unwrap_or(Value::Null);
+        if old_fields.get(name).unwrap_or(&Value::Null) != &value {
+            ops.push(Op::Set {
+                field: name.clone(),
+                value,
+            });
+        }
+    }
+
+    ops
+}
+
+/// Wrap operations into entries following the existing log
+pub fn entries_for(existing: &[Entry], device: &str, ops: Vec<Op>) -> Vec<Entry> {
+    let next = existing.iter().map(|e| e.lamport).max().unwrap_or(0) + 1;
+    ops.into_iter()
+        .enumerate()
+        .map(|(i, op)| Entry {
+            device: device.to_string(),
+            lamport: next + i as u64,
+            schema: CURRENT_SCHEMA_VERSION,
+            op,
+        })
+        .collect()
+}
+
+/// Keep only the winning entry per feature and store field
+///
+/// Entries keep their device and timestamp, and the last delete of each feature is
+/// kept as a tombstone, so a compacted log still combines correctly with older copies.
+pub fn compact(entries: &[Entry]) -> Vec<Entry> {
+    let mut latest: HashMap<String, &Entry> = HashMap::new();
+    for entry in ordered(entries) {
+        latest.insert(entry.target(), entry);
+    }
+    let mut kept: Vec<Entry> = latest.into_values().cloned().collect();
+    kept.sort_by(|a, b| a.order().cmp(&b.order()));
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn feature(name: &str, hint: &str) -> Value {
+        json!({ "name": name, "feature": format!("{}.com", name), "hint": hint })
+    }
+
+    fn entry(device: &str, lamport: u64, op: Op) -> Entry {
+        Entry {
+            device: device.to_string(),
+            lamport,
+            schema: CURRENT_SCHEMA_VERSION,
+            op,
+        }
+    }
+
+    fn names(doc: &Value) -> Vec<&str> {
+        doc["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_concatenated_logs_fold_deterministically() {
+        let shared = vec![entry(
+            "a",
+            1,
+            Op::Add {
+                feature: feature("github", ""),
+            },
+        )];
+        let mut device_a = shared.clone();
+        device_a.push(entry(
+            "a",
+            2,
+            Op::Update {
+                feature: feature("github", "from a"),
+            },
+        ));
+        let mut device_b = shared;
+        device_b.push(entry(
+            "b",
+            2,
+            Op::Update {
+                feature: feature("github", "from b"),
+            },
+        ));
+        device_b.push(entry(
+            "b",
+            3,
+            Op::Add {
+                feature: feature("mail", ""),
+            },
+        ));
+
+        let ab: Vec<Entry> = device_a.iter().chain(&device_b).cloned().collect();
+        let ba: Vec<Entry> = device_b.iter().chain(&device_a).cloned().collect();
+        let folded = fold(&ab).unwrap();
+        assert_eq!(folded, fold(&ba).unwrap());
+        assert_eq!(names(&folded), ["github", "mail"]);
+        // Same timestamp: the higher device ID wins
+        assert_eq!(folded["features"][0]["hint"], "from b");
+    }
+
+    #[test]
+    fn test_features_are_keyed_by_id() {
+        let github = feature("github", "");
+        let mut renamed = feature("github", "");
+        renamed["id"] = Value::from(migrations::feature_id(github.as_object().unwrap()));
+        renamed["name"] = Value::from("work");
+
+        // An entry from before IDs and a later rename are the same feature
+        let log = vec![
+            entry(
+                "a",
+                1,
+                Op::Add {
+                    feature: github.clone(),
+                },
+            ),
+            entry(
+                "a",
+                2,
+                Op::Update {
+                    feature: renamed.clone(),
+                },
+            ),
+        ];
+        assert_eq!(names(&fold(&log).unwrap()), ["work"]);
+        let ops = diff(
+            &json!({ "features": [github] }),
+            &json!({ "features": [renamed] }),
+        );
+        assert!(matches!(ops[..], [Op::Update { .. }]));
+    }
+
+    #[test]
+    fn test_diff_round_trip() {
+        let old = json!({ "schema_version": 1, "features": [feature("github", ""), feature("mail", "")] });
+        let new = json!({
+            "schema_version": 1,
+            "features": [feature("github", "changed"), feature("bank", "")],
+            "note": "x"
+        });
+
+        let mut log = entries_for(&[], "a", diff(&json!({}), &old));
+        let ops = diff(&fold(&log).unwrap(), &new);
+        assert_eq!(ops.len(), 4);
+        log.extend(entries_for(&log, "a", ops));
+
+        let folded = fold(&log).unwrap();
+        assert_eq!(names(&folded), ["github", "bank"]);
+        assert_eq!(folded["features"][0]["hint"], "changed");
+        assert_eq!(folded["note"], "x");
+    }
+
+    #[test]
+    fn test_compact_keeps_result_and_tombstones() {
+        let log = vec![
+            entry(
+                "a",
+                1,
+                Op::Add {
+                    feature: feature("github", ""),
+                },
+            ),
+            entry(
+                "a",
+                2,
+                Op::Update {
+                    feature: feature("github", "v2"),
+                },
+            ),
+            entry(
+                "a",
+                3,
+                Op::Add {
+                    feature: feature("mail", ""),
+                },
+            ),
+            entry(
+                "a",
+                4,
+                Op::Delete {
+                    feature: feature("mail", ""),
+                },
+            ),
+        ];
+        let compacted = compact(&log);
+        assert_eq!(compacted.len(), 2);
+        assert_eq!(fold(&compacted).unwrap(), fold(&log).unwrap());
+
+        // An old copy that still has the deleted feature does not resurrect it
+        let mut combined = compacted;
+        combined.push(log[2].clone());
+        assert_eq!(names(&fold(&combined).unwrap()), ["github"]);
+    }
+
+    #[test]
+    fn test_encrypted_entries() {
+        let key = StorageCipher::derive_key("salt");
+        let e = entry(
+            "a",
+            1,
+            Op::Add {
+                feature: feature("github", ""),
+            },
+        );
+        let line = render(&e, Some(&key)).unwrap();
+        assert!(!line.contains("github"));
+        assert_eq!(parse(&line, Some(&key)).unwrap(), vec![e]);
+    }
+
+    #[test]
+    fn test_newer_entries_rejected() {
+        let mut e = entry(
+            "a",
+            1,
+            Op::Add {
+                feature: feature("github", ""),
+            },
+        );
+        e.schema = CURRENT_SCHEMA_VERSION + 1;
+        assert!(fold(&[e]).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Named profiles (vaults)
+//!
+//! Each profile has its own features file, format and encryption mode, and can keep a
+//! salt verifier to catch a mistyped master salt. The registry lives in
+//! `~/.saltpass/profiles.toml`; profile stores are created under
+//! `~/.saltpass/profiles/<name>/`. Running without a profile keeps using the default
+//! store in `~/.saltpass`.
+
+use crate::storage::{Storage, StorageFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Name shown for the store used when no profile is selected
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// A named store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub path: PathBuf,
+    pub format: StorageFormat,
+    pub encrypted: bool,
+    /// See [`crate::crypto::SaltVerifier`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_verifier: Option<String>,
+}
+
+impl Profile {
+    pub fn storage(&self) -> Storage {
+        Storage::new(self.path.clone(), self.format, self.encrypted)
+    }
+}
+
+/// Registry of all profiles
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+impl Profiles {
+    /// Load the registry from `~/.saltpass/profiles.toml`
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(&Storage::config_dir()?)
+    }
+
+    /// Load the registry kept in `base_dir`
+    pub fn load_from(base_dir: &Path) -> io::Result<Self> {
+        let path = base_dir.join("profiles.toml");
+        let mut profiles: Profiles = if path.exists() {
+            toml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        } else {
+            Profiles::default()
+        };
+        profiles.base_dir = base_dir.to_path_buf();
+        Ok(profiles)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let content =
+            toml::to_string_pretty(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(&self.base_dir)?;
+        fs::write(self.base_dir.join("profiles.toml"), content)
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("Profile '{}' does not exist", name),
+            )
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    /// Directory holding the store of a profile created by SaltPass
+    fn profile_dir(&self, name: &str) -> PathBuf {
+        self.base_dir.join("profiles").join(name)
+    }
+
+    /// Register a new profile with a store under `profiles/<name>/`
+    pub fn create(
+        &mut self,
+        name: &str,
+        format: StorageFormat,
+        encrypted: bool,
+        salt_verifier: Option<String>,
+    ) -> io::Result<&Profile> {
+        self.check_new_name(name)?;
+        let path = self
+            .profile_dir(name)
+            .join(Storage::file_name(format, encrypted));
+        let profile = Profile {
+            path,
+            format,
+            encrypted,
+            salt_verifier,
+        };
+        Ok(self.profiles.entry(name.to_string()).or_insert(profile))
+    }
+
+    /// Rename a profile, moving its directory if SaltPass created it
+    pub fn rename(&mut self, old: &str, new: &str) -> io::Result<()> {
+        self.check_new_name(new)?;
+        let mut profile = self.get(old)?.clone();
+
+        let old_dir = self.profile_dir(old);
+        if let Ok(relative) = profile.path.strip_prefix(&old_dir) {
+            let new_dir = self.profile_dir(new);
+            if old_dir.exists() {
+                fs::rename(&old_dir, &new_dir)?;
+            }
+            profile.path = new_dir.join(relative);
+        }
+
+        self.profiles.remove(old);
+        self.profiles.insert(new.to_string(), profile);
+        Ok(())
+    }
+
+    /// Remove a profile from the registry, returning it so its files can be cleaned up
+    pub fn remove(&mut self, name: &str) -> io::Result<Profile> {
+        self.get(name)?;
+        Ok(self.profiles.remove(name).expect("profile exists"))
+    }
+
+    fn check_new_name(&self, name: &str) -> io::Result<()> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid || name == DEFAULT_PROFILE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Invalid profile name '{}': use letters, digits, '-' or '_' (and not '{}')",
+                    name, DEFAULT_PROFILE
+                ),
+            ));
+        }
+        if self.profiles.contains_key(name) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Profile '{}' already exists", name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FeatureStore;
+
+    #[test]
+    fn test_profile_lifecycle() {
+        let dir = std::env::temp_dir().join("saltpass_test_profiles");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut profiles = Profiles::load_from(&dir).unwrap();
+        profiles
+            .create("work", StorageFormat::Json, false, None)
+            .unwrap();
+        assert!(
+            profiles
+                .create("work", StorageFormat::Toml, false, None)
+                .is_err()
+        );
+        assert!(
+            profiles
+                .create(DEFAULT_PROFILE, StorageFormat::Toml, false, None)
+                .is_err()
+        );
+        assert!(
+            profiles
+                .create("../evil", StorageFormat::Toml, false, None)
+                .is_err()
+        );
+
+        let storage = profiles.get("work").unwrap().storage();
+        storage.save(&FeatureStore::new()).unwrap();
+        profiles.save().unwrap();
+
+        let mut profiles = Profiles::load_from(&dir).unwrap();
+        profiles.rename("work", "office").unwrap();
+        let office = profiles.get("office").unwrap();
+        assert!(office.path.ends_with("profiles/office/features.json"));
+        assert!(office.path.exists());
+        assert!(profiles.get("work").is_err());
+
+        profiles.remove("office").unwrap();
+        assert!(profiles.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
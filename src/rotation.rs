@@ -0,0 +1,116 @@
+//! Password rotation reminders
+//!
+//! A feature can have a maximum password age in days. Its password counts as rotated when
+//! the feature is created and whenever it gets a new one, by an edit of its derivation
+//! inputs or by a master salt change. Once the password is older than the maximum age the
+//! feature is due for rotation.
+
+use crate::models::Feature;
+use chrono::{DateTime, Duration, Utc};
+
+/// Features this close to their maximum age are reported as due soon
+pub const DUE_SOON_DAYS: i64 = 14;
+
+/// Where a feature stands against its maximum age
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok { days_left: i64 },
+    DueSoon { days_left: i64 },
+    Overdue { days: i64 },
+}
+
+/// When the feature's password was last changed
+pub fn last_rotated(feature: &Feature) -> DateTime<Utc> {
+    feature.last_rotated.unwrap_or(feature.created)
+}
+
+/// When the feature's password must be rotated, if it has a maximum age
+pub fn deadline(feature: &Feature) -> Option<DateTime<Utc>> {
+    let max_age = feature.max_age?;
+    Some(last_rotated(feature) + Duration::days(max_age.into()))
+}
+
+/// The rotation status of a feature, `None` if it has no maximum age
+pub fn status(feature: &Feature, now: DateTime<Utc>) -> Option<Status> {
+    let deadline = deadline(feature)?;
+    Some(if now >= deadline {
+        Status::Overdue {
+            days: (now - deadline).num_days(),
+        }
+    } else {
+        let days_left = (deadline - now).num_days();
+        if days_left < DUE_SOON_DAYS {
+            Status::DueSoon { days_left }
+        } else {
+            Status::Ok { days_left }
+        }
+    })
+}
+
+/// Every feature with a maximum age and its status, most urgent first
+pub fn report(features: &[Feature], now: DateTime<Utc>) -> Vec<(&Feature, Status)> {
+    let mut report: Vec<(&Feature, Status)> = features
+        .iter()
+        .filter_map(|f| Some((f, status(f, now)?)))
+        .collect();
+    report.sort_by_key(|(f, _)| deadline(f));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+
+    fn feature(
+        name: &str,
+        max_age: Option<u32>,
+        rotated_days_ago: i64,
+        now: DateTime<Utc>,
+    ) -> Feature {
+        let mut feature = test_feature(name);
+        feature.created = now - Duration::days(365);
+        feature.last_rotated = Some(now - Duration::days(rotated_days_ago));
+        feature.max_age = max_age;
+        feature
+    }
+
+    #[test]
+    fn test_status() {
+        let now = Utc::now();
+        assert_eq!(status(&feature("Free", None, 400, now), now), None);
+        assert_eq!(
+            status(&feature("Fresh", Some(90), 10, now), now),
+            Some(Status::Ok { days_left: 80 })
+        );
+        assert_eq!(
+            status(&feature("Soon", Some(90), 85, now), now),
+            Some(Status::DueSoon { days_left: 5 })
+        );
+        assert_eq!(
+            status(&feature("Stale", Some(90), 100, now), now),
+            Some(Status::Overdue { days: 10 })
+        );
+
+        // Without a rotation the creation time counts
+        let mut never = feature("Never", Some(30), 0, now);
+        never.last_rotated = None;
+        assert!(matches!(status(&never, now), Some(Status::Overdue { .. })));
+    }
+
+    #[test]
+    fn test_report_order() {
+        let now = Utc::now();
+        let features = vec![
+            feature("Fresh", Some(90), 10, now),
+            feature("Free", None, 400, now),
+            feature("Stale", Some(30), 100, now),
+            feature("Soon", Some(90), 85, now),
+        ];
+        let names: Vec<&str> = report(&features, now)
+            .iter()
+            .map(|(f, _)| f.name.as_str())
+            .collect();
+        assert_eq!(names, ["Stale", "Soon", "Fresh"]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Progress of a master salt change
+//!
+//! Changing the master salt changes every password, so each account has to be updated
+//! by hand. The progress is kept in a `<file>.salt-change` sidecar so the change can span
+//! several sessions: every feature is pending until it is marked as migrated (its new
+//! password is in use) or skipped. Until the change is finished, SaltPass is started with
+//! the old salt and the store stays encrypted and signed under it.
+//!
+//! The file holds verifiers of both salts, never the salts themselves, and is encrypted
+//! like the store's other records.
+
+use crate::crypto::{SaltVerifier, StorageCipher, StorageKey};
+use crate::models::{Feature, FeatureStore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Where a feature stands in the salt change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationStatus {
+    /// Still uses the password derived from the old salt
+    Pending,
+    /// The account was updated to the password derived from the new salt
+    Migrated,
+    /// Left alone, its password will change once the new salt is in use
+    Skipped,
+}
+
+impl MigrationStatus {
+    pub fn label(&self) -> &str {
+        match self {
+            MigrationStatus::Pending => "⏳ pending",
+            MigrationStatus::Migrated => "✅ migrated",
+            MigrationStatus::Skipped => "⏭️  skipped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureProgress {
+    /// ID of the feature, so its progress is kept when it is renamed
+    pub id: String,
+    /// Name and identifier as last tracked, for the report
+    pub name: String,
+    pub feature: String,
+    pub status: MigrationStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaltChange {
+    pub old_verifier: String,
+    pub new_verifier: String,
+    pub started: DateTime<Utc>,
+    pub features: Vec<FeatureProgress>,
+}
+
+impl SaltChange {
+    /// Start a change with every feature of the store pending
+    pub fn new(old_salt: &str, new_salt: &str, store: &FeatureStore) -> Self {
+        let mut change = Self {
+            old_verifier: SaltVerifier::compute(old_salt),
+            new_verifier: SaltVerifier::compute(new_salt),
+            started: Utc::now(),
+            features: Vec::new(),
+        };
+        change.track(store);
+        change
+    }
+
+    /// Follow the store: new features are pending, deleted ones are forgotten and renamed
+    /// ones keep their status
+    pub fn track(&mut self, store: &FeatureStore) {
+        self.features
+            .retain(|p| store.features.iter().any(|f| f.id == p.id));
+        for feature in &store.features {
+            match self.features.iter_mut().find(|p| p.id == feature.id) {
+                Some(progress) => {
+                    progress.name = feature.name.clone();
+                    progress.feature = feature.feature.clone();
+                }
+                None => self.features.push(FeatureProgress {
+                    id: feature.id.clone(),
+                    name: feature.name.clone(),
+                    feature: feature.feature.clone(),
+                    status: MigrationStatus::Pending,
+                }),
+            }
+        }
+    }
+
+    pub fn status(&self, feature: &Feature) -> Option<MigrationStatus> {
+        self.features
+            .iter()
+            .find(|p| p.id == feature.id)
+            .map(|p| p.status)
+    }
+
+    pub fn set_status(&mut self, feature: &Feature, status: MigrationStatus) {
+        if let Some(progress) = self.features.iter_mut().find(|p| p.id == feature.id) {
+            progress.status = status;
+        }
+    }
+
+    /// Number of features with the given status
+    pub fn count(&self, status: MigrationStatus) -> usize {
+        self.features.iter().filter(|p| p.status == status).count()
+    }
+
+    /// Whether no feature is pending any more
+    pub fn is_complete(&self) -> bool {
+        self.count(MigrationStatus::Pending) == 0
+    }
+
+    /// Read the progress from `path`, `None` if no change is in progress
+    pub fn load(path: &Path, key: Option<&StorageKey>) -> io::Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let json = match key {
+            Some(key) => StorageCipher::decrypt_with_key(key, content.trim())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            None => content.into_bytes(),
+        };
+        serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Write the progress to `path`, replacing it atomically
+    pub fn save(&self, path: &Path, key: Option<&StorageKey>) -> io::Result<()> {
+        let json =
+            serde_json::to_string(self).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let content = match key {
+            Some(key) => StorageCipher::encrypt_with_key(key, json.as_bytes())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            None => json,
+        };
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge;
+    use crate::models::test_feature;
+
+    #[test]
+    fn test_progress_survives_restart() {
+        let path = std::env::temp_dir().join("saltpass_test_salt_change.json");
+        let _ = fs::remove_file(&path);
+
+        let mut store = FeatureStore::new();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        let mut change = SaltChange::new("old", "new", &store);
+        assert_eq!(change.count(MigrationStatus::Pending), 2);
+
+        change.set_status(&store.features[0], MigrationStatus::Migrated);
+        let key = StorageCipher::derive_key("old");
+        change.save(&path, Some(&key)).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("GitHub"));
+
+        let mut resumed = SaltChange::load(&path, Some(&key)).unwrap().unwrap();
+        assert!(SaltVerifier::matches("new", &resumed.new_verifier));
+        assert_eq!(
+            resumed.status(&store.features[0]),
+            Some(MigrationStatus::Migrated)
+        );
+
+        // Features added or deleted meanwhile are tracked
+        let mail = store.find("Mail", "mail.com").unwrap().id.clone();
+        store.delete_feature(&mail);
+        store.add_feature(test_feature("Bank")).unwrap();
+        resumed.track(&store);
+        resumed.set_status(&store.features[1], MigrationStatus::Skipped);
+        assert!(resumed.is_complete());
+        assert_eq!(resumed.features.len(), 2);
+
+        fs::remove_file(&path).unwrap();
+        assert!(SaltChange::load(&path, None).unwrap().is_none());
+    }
+    #[test]
+    fn test_rename_through_merge_keeps_status() {
+        let mut store = FeatureStore::new();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        let mut change = SaltChange::new("old", "new", &store);
+        change.set_status(&store.features[0], MigrationStatus::Migrated);
+
+        // Another device renamed the migrated feature
+        let mut theirs = store.clone();
+        theirs.features[0].name = "GitHub (work)".to_string();
+        theirs.features[0].feature = "github.example.com".to_string();
+        let merged = merge::three_way_merge(&store, &store, &theirs)
+            .unwrap()
+            .resolve(|_| panic!("no conflict expected"))
+            .unwrap();
+
+        change.track(&merged);
+        assert_eq!(change.features.len(), 2);
+        assert_eq!(
+            change.status(&merged.features[0]),
+            Some(MigrationStatus::Migrated)
+        );
+        assert_eq!(change.features[0].name, "GitHub (work)");
+        assert_eq!(change.count(MigrationStatus::Pending), 1);
+    }
+}
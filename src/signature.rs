@@ -0,0 +1,166 @@
+//! Tamper detection for plain-text stores
+//!
+//! Plain TOML and JSON stores can be edited by any process, and changing a feature's
+//! `algorithm` or identifier silently changes the password it generates. A signed store
+//! carries an HMAC-SHA256 over a canonical serialization of its content, keyed from the
+//! master salt under its own label, so edits made without the salt are detected on load.
+//!
+//! The canonical form is the store as JSON with object keys sorted and no whitespace,
+//! excluding the `signature` field itself, so it does not depend on the file format or
+//! on how the file was formatted. A store signed with an older schema version is checked
+//! against its content as it was before the migrations applied on load.
+
+use crate::crypto::StorageKey;
+use crate::migrations::CURRENT_SCHEMA_VERSION;
+use crate::models::FeatureStore;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::io::{self, ErrorKind};
+use zeroize::Zeroizing;
+
+/// PBKDF2 label separating the signing key from the other keys derived from the salt
+const LABEL: &[u8] = b"SaltPass-Store-Signature";
+
+/// Derive the signing key from the master salt
+pub fn derive_key(salt: &str) -> StorageKey {
+    use pbkdf2::pbkdf2_hmac;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2_hmac::<Sha256>(salt.as_bytes(), LABEL, 100_000, key.as_mut());
+    key
+}
+
+/// Append the canonical JSON form of `value`
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Canonical serialization of a store as of schema `version`, without its signature
+fn canonical(store: &FeatureStore, version: u32) -> io::Result<String> {
+    let mut doc =
+        serde_json::to_value(store).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    if let Value::Object(map) = &mut doc {
+        map.remove("signature");
+        if version < CURRENT_SCHEMA_VERSION {
+            map.insert("schema_version".to_string(), Value::from(version));
+        }
+        // Feature IDs were added by the v1 → v2 migration
+        if version < 2 {
+            for field in ["features", "trash"] {
+                if let Some(Value::Array(features)) = map.get_mut(field) {
+                    for feature in features.iter_mut().filter_map(Value::as_object_mut) {
+                        feature.remove("id");
+                    }
+                }
+            }
+        }
+    }
+    let mut out = String::new();
+    write_canonical(&doc, &mut out);
+    Ok(out)
+}
+
+fn mac(key: &StorageKey, store: &FeatureStore, version: u32) -> io::Result<Hmac<Sha256>> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_ref()).expect("HMAC accepts keys of any size");
+    mac.update(canonical(store, version)?.as_bytes());
+    Ok(mac)
+}
+
+/// Signature of a store's content
+pub fn sign(key: &StorageKey, store: &FeatureStore) -> io::Result<String> {
+    Ok(hex::encode(
+        mac(key, store, CURRENT_SCHEMA_VERSION)?
+            .finalize()
+            .into_bytes(),
+    ))
+}
+
+/// Check a store's signature, `None` if it is not signed
+///
+/// `version` is the schema version the store had on disk, before it was migrated.
+pub fn verify(key: &StorageKey, store: &FeatureStore, version: u32) -> io::Result<Option<bool>> {
+    let Some(signature) = &store.signature else {
+        return Ok(None);
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return Ok(Some(false));
+    };
+    Ok(Some(
+        mac(key, store, version)?.verify_slice(&signature).is_ok(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Algorithm;
+    use crate::models::Feature;
+
+    #[test]
+    fn test_signature_detects_edits() {
+        let key = derive_key("salt");
+        let mut store = FeatureStore::new();
+        store
+            .add_feature(Feature::new(
+                "GitHub".to_string(),
+                "github.com".to_string(),
+                Algorithm::HmacSha256,
+                None,
+            ))
+            .unwrap();
+        let version = CURRENT_SCHEMA_VERSION;
+        assert_eq!(verify(&key, &store, version).unwrap(), None);
+        store.signature = Some(sign(&key, &store).unwrap());
+
+        // The format the store went through does not matter
+        let toml = toml::to_string_pretty(&store).unwrap();
+        let reloaded: FeatureStore = toml::from_str(&toml).unwrap();
+        assert_eq!(verify(&key, &reloaded, version).unwrap(), Some(true));
+        assert_eq!(
+            verify(&derive_key("other"), &reloaded, version).unwrap(),
+            Some(false)
+        );
+
+        let tampered: FeatureStore = toml::from_str(&toml.replace("HmacSha256", "Pbkdf2")).unwrap();
+        assert_eq!(verify(&key, &tampered, version).unwrap(), Some(false));
+
+        // Claiming an older schema version does not help
+        assert_eq!(verify(&key, &reloaded, 1).unwrap(), Some(false));
+
+        // A store signed before it had feature IDs still verifies once migrated
+        let mut legacy = reloaded.clone();
+        legacy.signature = Some(hex::encode(
+            mac(&key, &legacy, 1).unwrap().finalize().into_bytes(),
+        ));
+        assert_eq!(verify(&key, &legacy, 1).unwrap(), Some(true));
+        assert_eq!(verify(&key, &legacy, version).unwrap(), Some(false));
+    }
+}
@@ -0,0 +1,644 @@
+//! SQLite storage backend
+//!
+//! Features are stored one per row with their ID as the primary key and an index on
+//! their name and identifier, so single features are looked up through an index and a
+//! change only writes the affected rows, inside a transaction. Store-level fields live in
+//! a `meta` table next to a revision counter used to detect concurrent modification.
+//!
+//! In encrypted mode the content of every row is encrypted on its own, and the key
+//! columns hold keyed hashes of the ID, name and identifier instead of the plain values.
+
+use crate::backend::StorageBackend;
+use crate::crypto::{StorageCipher, StorageKey};
+use crate::migrations;
+use crate::models::{Feature, FeatureStore};
+use crate::storage::StoreChanged;
+use hmac::{Hmac, Mac};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, TransactionBehavior};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS features (
+    id_key TEXT PRIMARY KEY,
+    name_key TEXT NOT NULL,
+    feature_key TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS features_identity ON features (name_key, feature_key);";
+
+/// The features table of databases written before rows were keyed by ID
+const LEGACY_FEATURES: &str = "
+ALTER TABLE features RENAME TO legacy_features;
+CREATE TABLE features (
+    id_key TEXT PRIMARY KEY,
+    name_key TEXT NOT NULL,
+    feature_key TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    data TEXT NOT NULL
+);";
+
+fn db_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn json_error(e: serde_json::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+/// Assemble stored documents into a store migrated to the current schema
+fn to_store(fields: Map<String, Value>, features: Vec<Value>) -> io::Result<FeatureStore> {
+    let mut doc = fields;
+    doc.insert("features".to_string(), Value::Array(features));
+    serde_json::from_value(migrations::migrate(Value::Object(doc))?).map_err(json_error)
+}
+
+/// A features database
+pub struct SqliteBackend {
+    path: PathBuf,
+    conn: Connection,
+    key: Option<StorageKey>,
+    /// Revision last loaded or written through this handle
+    revision: Cell<Option<i64>>,
+}
+
+impl SqliteBackend {
+    /// Returns `true` if the file content starts with the SQLite header
+    pub fn looks_like_sqlite(content: &[u8]) -> bool {
+        content.starts_with(b"SQLite format 3\0")
+    }
+
+    /// Whether an existing database stores encrypted rows
+    pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(db_error)?;
+        let encrypted: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'encrypted'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        Ok(encrypted.as_deref() == Some("true"))
+    }
+
+    /// Open or create a database; `key` encrypts the rows
+    pub fn open(path: &Path, key: Option<StorageKey>) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(db_error)?;
+        conn.busy_timeout(Duration::from_secs(5))
+            .map_err(db_error)?;
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+
+        let encrypted = key.is_some().to_string();
+        conn.execute(
+            "INSERT OR IGNORE INTO meta (key, value) VALUES ('encrypted', ?1)",
+            [&encrypted],
+        )
+        .map_err(db_error)?;
+        let stored: String = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'encrypted'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        if stored != encrypted {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                if key.is_some() {
+                    "Database is stored in plain text but was opened as encrypted"
+                } else {
+                    "Database is encrypted but was opened as plain text"
+                },
+            ));
+        }
+
+        let db = Self {
+            path: path.to_path_buf(),
+            conn,
+            key,
+            revision: Cell::new(None),
+        };
+        db.key_rows_by_id()?;
+        // Rows are always written in the current schema, record it for a new database
+        let fields = serde_json::to_string(&serde_json::json!({
+            "schema_version": migrations::CURRENT_SCHEMA_VERSION
+        }))
+        .map_err(json_error)?;
+        db.conn
+            .execute(
+                "INSERT OR IGNORE INTO meta (key, value) VALUES ('store', ?1)",
+                [db.encode(fields)?],
+            )
+            .map_err(db_error)?;
+        Ok(db)
+    }
+
+    /// Value of a key column: the plain value, or a keyed hash in encrypted mode
+    fn index_key(&self, column: &str, value: &str) -> String {
+        match &self.key {
+            Some(key) => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_ref())
+                    .expect("HMAC can take key of any size");
+                mac.update(column.as_bytes());
+                mac.update(&[0]);
+                mac.update(value.as_bytes());
+                hex::encode(mac.finalize().into_bytes())
+            }
+            None => value.to_string(),
+        }
+    }
+
+    fn keys(&self, name: &str, feature: &str) -> (String, String) {
+        (
+            self.index_key("name", name),
+            self.index_key("feature", feature),
+        )
+    }
+
+    /// Rebuild the features table of a database keyed by name and identifier with the
+    /// feature IDs as keys; rows written before features had IDs get the ID the schema
+    /// migration derives for them
+    fn key_rows_by_id(&self) -> io::Result<()> {
+        let keyed_by_id = self
+            .conn
+            .prepare("SELECT id_key FROM features LIMIT 0")
+            .is_ok();
+        if keyed_by_id {
+            return Ok(());
+        }
+
+        let tx = self.begin()?;
+        tx.execute_batch(LEGACY_FEATURES).map_err(db_error)?;
+        let rows = {
+            let mut stmt = tx
+                .prepare("SELECT name_key, feature_key, position, data FROM legacy_features")
+                .map_err(db_error)?;
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?
+        };
+        for (name_key, feature_key, position, data) in rows {
+            let feature: Map<String, Value> =
+                serde_json::from_str(&self.decode(&data)?).map_err(json_error)?;
+            let id_key = self.index_key("id", &migrations::feature_id(&feature));
+            tx.execute(
+                "INSERT INTO features (id_key, name_key, feature_key, position, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                (&id_key, &name_key, &feature_key, position, &data),
+            )
+            .map_err(db_error)?;
+        }
+        tx.execute_batch(
+            "DROP TABLE legacy_features;
+             CREATE INDEX IF NOT EXISTS features_identity ON features (name_key, feature_key);",
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)
+    }
+
+    fn encode(&self, json: String) -> io::Result<String> {
+        match &self.key {
+            Some(key) => StorageCipher::encrypt_with_key(key, json.as_bytes())
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+            None => Ok(json),
+        }
+    }
+
+    fn decode(&self, data: &str) -> io::Result<String> {
+        match &self.key {
+            Some(key) => String::from_utf8(
+                StorageCipher::decrypt_with_key(key, data)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            )
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+            None => Ok(data.to_string()),
+        }
+    }
+
+    fn begin(&self) -> io::Result<Transaction<'_>> {
+        Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate).map_err(db_error)
+    }
+
+    fn read_revision(&self) -> io::Result<i64> {
+        let revision: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'revision'", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(db_error)?;
+        Ok(revision.and_then(|r| r.parse().ok()).unwrap_or(0))
+    }
+
+    /// Bump the revision inside a write transaction
+    fn bump_revision(&self, previous: i64) -> io::Result<i64> {
+        let next = previous + 1;
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('revision', ?1)",
+                [next.to_string()],
+            )
+            .map_err(db_error)?;
+        Ok(next)
+    }
+
+    /// Keep this handle in sync after a single-row write if it was before
+    fn follow_revision(&self, previous: i64, next: i64) {
+        if self.revision.get() == Some(previous) {
+            self.revision.set(Some(next));
+        }
+    }
+
+    /// Store-level fields, including `schema_version`
+    fn read_fields(&self) -> io::Result<Map<String, Value>> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT value FROM meta WHERE key = 'store'", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(db_error)?;
+        match data {
+            Some(data) => serde_json::from_str(&self.decode(&data)?).map_err(json_error),
+            None => Ok(Map::new()),
+        }
+    }
+
+    /// Read the whole store and its revision without updating this handle
+    pub fn read(&self) -> io::Result<(FeatureStore, i64)> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Deferred)
+            .map_err(db_error)?;
+        let revision = self.read_revision()?;
+        let store = self.read_store(&tx)?;
+        Ok((store, revision))
+    }
+
+    /// Read the whole store inside a transaction
+    fn read_store(&self, tx: &Transaction<'_>) -> io::Result<FeatureStore> {
+        let fields = self.read_fields()?;
+        let mut stmt = tx
+            .prepare("SELECT data FROM features ORDER BY position, rowid")
+            .map_err(db_error)?;
+        let features = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(db_error)?
+            .map(|data| {
+                let json = self.decode(&data.map_err(db_error)?)?;
+                serde_json::from_str(&json).map_err(json_error)
+            })
+            .collect::<io::Result<Vec<Value>>>()?;
+        to_store(fields, features)
+    }
+
+    /// Write the difference between `store` and the stored content
+    fn write_store(&self, tx: &Transaction<'_>, store: &FeatureStore) -> io::Result<()> {
+        let mut existing: HashMap<String, (i64, String)> = HashMap::new();
+        {
+            let mut stmt = tx
+                .prepare("SELECT id_key, position, data FROM features")
+                .map_err(db_error)?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))
+                .map_err(db_error)?;
+            for row in rows {
+                let (id_key, value) = row.map_err(db_error)?;
+                existing.insert(id_key, value);
+            }
+        }
+
+        // Keep the positions of rows that are still in order, so appending or removing a
+        // feature does not rewrite the others
+        let mut previous = 0;
+        for feature in &store.features {
+            let id_key = self.index_key("id", &feature.id);
+            let (name_key, feature_key) = self.keys(&feature.name, &feature.feature);
+            let json = serde_json::to_string(feature).map_err(json_error)?;
+            let unchanged = match existing.remove(&id_key) {
+                Some((position, data)) if position > previous => {
+                    previous = position;
+                    self.decode(&data)? == json
+                }
+                _ => {
+                    previous += 1;
+                    false
+                }
+            };
+            if !unchanged {
+                tx.execute(
+                    "INSERT OR REPLACE INTO features (id_key, name_key, feature_key, position, data)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    (&id_key, &name_key, &feature_key, previous, self.encode(json)?),
+                )
+                .map_err(db_error)?;
+            }
+        }
+        for id_key in existing.into_keys() {
+            tx.execute("DELETE FROM features WHERE id_key = ?1", [&id_key])
+                .map_err(db_error)?;
+        }
+
+        let mut fields = match serde_json::to_value(store).map_err(json_error)? {
+            Value::Object(map) => map,
+            _ => unreachable!("stores serialize to maps"),
+        };
+        fields.remove("features");
+        let fields = serde_json::to_string(&fields).map_err(json_error)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('store', ?1)",
+            [self.encode(fields)?],
+        )
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    fn check_revision(&self, current: i64) -> io::Result<()> {
+        match self.revision.get() {
+            Some(known) if known != current => {
+                Err(io::Error::other(StoreChanged::new(self.path.clone())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn load(&self) -> io::Result<FeatureStore> {
+        let (store, revision) = self.read()?;
+        self.revision.set(Some(revision));
+        Ok(store)
+    }
+
+    /// Write the difference to the stored content in a single transaction
+    fn save(&self, store: &FeatureStore) -> io::Result<()> {
+        let tx = self.begin()?;
+        let revision = self.read_revision()?;
+        self.check_revision(revision)?;
+        self.write_store(&tx, store)?;
+
+        let next = self.bump_revision(revision)?;
+        tx.commit().map_err(db_error)?;
+        self.revision.set(Some(next));
+        Ok(())
+    }
+
+    /// Apply the change to the current content in a single transaction
+    ///
+    /// Like a single-row write, this keeps concurrent changes and only keeps this handle
+    /// in sync if it was before.
+    fn update(&self, change: &mut dyn FnMut(&mut FeatureStore)) -> io::Result<()> {
+        let tx = self.begin()?;
+        let revision = self.read_revision()?;
+        let mut store = self.read_store(&tx)?;
+        change(&mut store);
+        self.write_store(&tx, &store)?;
+
+        let next = self.bump_revision(revision)?;
+        tx.commit().map_err(db_error)?;
+        self.follow_revision(revision, next);
+        Ok(())
+    }
+
+    fn has_changed(&self) -> io::Result<bool> {
+        let current = self.read_revision()?;
+        Ok(self.revision.get().is_some_and(|known| known != current))
+    }
+
+    fn current(&self) -> io::Result<FeatureStore> {
+        Ok(self.read()?.0)
+    }
+
+    fn get(&self, name: &str, feature: &str) -> io::Result<Option<Feature>> {
+        let (name_key, feature_key) = self.keys(name, feature);
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM features WHERE name_key = ?1 AND feature_key = ?2",
+                (&name_key, &feature_key),
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error)?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        let feature: Value = serde_json::from_str(&self.decode(&data)?).map_err(json_error)?;
+        let store = to_store(self.read_fields()?, vec![feature])?;
+        Ok(store.features.into_iter().next())
+    }
+
+    fn put(&self, feature: &Feature) -> io::Result<()> {
+        let tx = self.begin()?;
+        let revision = self.read_revision()?;
+        let id_key = self.index_key("id", &feature.id);
+        let (name_key, feature_key) = self.keys(&feature.name, &feature.feature);
+        let data = self.encode(serde_json::to_string(feature).map_err(json_error)?)?;
+        let row = (&id_key, &name_key, &feature_key, &data);
+
+        let mut updated = tx
+            .execute(
+                "UPDATE features SET name_key = ?2, feature_key = ?3, data = ?4 WHERE id_key = ?1",
+                row,
+            )
+            .map_err(db_error)?;
+        if updated == 0 {
+            updated = tx
+                .execute(
+                    "UPDATE features SET id_key = ?1, data = ?4
+                     WHERE name_key = ?2 AND feature_key = ?3",
+                    row,
+                )
+                .map_err(db_error)?;
+        }
+        if updated == 0 {
+            tx.execute(
+                "INSERT INTO features (id_key, name_key, feature_key, position, data)
+                 SELECT ?1, ?2, ?3, COALESCE(MAX(position), 0) + 1, ?4 FROM features",
+                row,
+            )
+            .map_err(db_error)?;
+        }
+
+        let next = self.bump_revision(revision)?;
+        tx.commit().map_err(db_error)?;
+        self.follow_revision(revision, next);
+        Ok(())
+    }
+
+    fn delete(&self, id: &str) -> io::Result<bool> {
+        let tx = self.begin()?;
+        let revision = self.read_revision()?;
+
+        let deleted = tx
+            .execute(
+                "DELETE FROM features WHERE id_key = ?1",
+                [self.index_key("id", id)],
+            )
+            .map_err(db_error)?;
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        let next = self.bump_revision(revision)?;
+        tx.commit().map_err(db_error)?;
+        self.follow_revision(revision, next);
+        Ok(true)
+    }
+
+    /// Copy the store into a new database under the new key and replace the file with it
+    fn reencrypt(&mut self, _password: &str, key: &StorageKey) -> io::Result<()> {
+        if self.has_changed()? {
+            return Err(io::Error::other(StoreChanged::new(self.path.clone())));
+        }
+        let (store, _) = self.read()?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".rekey");
+        let tmp_path = PathBuf::from(tmp_path);
+        let _ = fs::remove_file(&tmp_path);
+        Self::open(&tmp_path, Some(key.clone()))?.save(&store)?;
+
+        // Close the old connection before replacing the file
+        let conn = Connection::open_in_memory().map_err(db_error)?;
+        drop(std::mem::replace(&mut self.conn, conn));
+        fs::rename(&tmp_path, &self.path)?;
+        *self = Self::open(&self.path, Some(key.clone()))?;
+        self.load()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+
+    fn names(store: &FeatureStore) -> Vec<&str> {
+        store.features.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rows_and_concurrent_changes() {
+        let path = std::env::temp_dir().join("saltpass_test_backend.db");
+        let _ = fs::remove_file(&path);
+
+        let first = SqliteBackend::open(&path, None).unwrap();
+        let second = SqliteBackend::open(&path, None).unwrap();
+        let mut store = first.load().unwrap();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        store.add_feature(test_feature("Bank")).unwrap();
+        first.save(&store).unwrap();
+        store.remove_feature(1);
+        store.add_feature(test_feature("Shop")).unwrap();
+        first.save(&store).unwrap();
+        assert_eq!(names(&second.load().unwrap()), ["GitHub", "Bank", "Shop"]);
+
+        // Single-row writes keep concurrent changes and do not invalidate other handles
+        second.put(&test_feature("Mail")).unwrap();
+        assert!(first.get("Mail", "mail.com").unwrap().is_some());
+        let bank = store.features[1].id.clone();
+        assert!(second.delete(&bank).unwrap());
+        assert!(!second.delete(&bank).unwrap());
+
+        // `first` has not seen those changes
+        let err = first.save(&store).unwrap_err();
+        assert!(crate::storage::is_store_changed(&err));
+        assert_eq!(names(&first.load().unwrap()), ["GitHub", "Shop", "Mail"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rows_are_keyed_by_id() {
+        let path = std::env::temp_dir().join("saltpass_test_backend_ids.db");
+        let _ = fs::remove_file(&path);
+
+        // A database from before rows were keyed by ID, with a feature from before IDs
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            r#"CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE features (
+                name_key TEXT NOT NULL,
+                feature_key TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (name_key, feature_key)
+            );
+            INSERT INTO meta VALUES ('encrypted', 'false'), ('store', '{"schema_version":1}');
+            INSERT INTO features VALUES ('GitHub', 'github.com', 1,
+                '{"name":"GitHub","feature":"github.com","created":"2026-01-01T00:00:00Z","hint":null}');"#,
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = SqliteBackend::open(&path, None).unwrap();
+        let mut github = db.get("GitHub", "github.com").unwrap().unwrap();
+        let mut store = db.load().unwrap();
+        assert_eq!(store.features[0].id, github.id);
+
+        // Renaming keeps the row, and deleting goes by ID
+        github.name = "GitHub (work)".to_string();
+        db.put(&github).unwrap();
+        assert!(db.get("GitHub", "github.com").unwrap().is_none());
+        store.features[0].hint = Some("work".to_string());
+        store.features[0].name = "GitHub (work)".to_string();
+        db.save(&store).unwrap();
+        assert_eq!(names(&db.load().unwrap()), ["GitHub (work)"]);
+        assert!(db.delete(&github.id).unwrap());
+        assert!(db.load().unwrap().features.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_rows() {
+        let path = std::env::temp_dir().join("saltpass_test_backend_enc.db");
+        let _ = fs::remove_file(&path);
+
+        let db = SqliteBackend::open(&path, Some(StorageCipher::derive_key("salt"))).unwrap();
+        db.put(&test_feature("GitHub")).unwrap();
+        assert_eq!(
+            db.get("GitHub", "github.com").unwrap().unwrap().feature,
+            "github.com"
+        );
+        drop(db);
+
+        let raw = fs::read(&path).unwrap();
+        assert!(SqliteBackend::looks_like_sqlite(&raw));
+        assert!(!raw.windows(6).any(|w| w == b"GitHub"));
+        assert!(SqliteBackend::is_encrypted(&path).unwrap());
+        assert!(SqliteBackend::open(&path, None).is_err());
+        assert!(
+            SqliteBackend::open(&path, Some(StorageCipher::derive_key("wrong")))
+                .unwrap()
+                .load()
+                .is_err()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
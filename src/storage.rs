@@ -1,20 +1,43 @@
 //! Storage layer for feature persistence
 //!
-//! This module handles loading and saving features to disk in JSON or TOML format,
-//! with optional AES-256-GCM encryption.
+//! This module handles loading and saving features in JSON or TOML format, as an
+//! append-only operation log (see [`crate::oplog`]) or in a SQLite database, with
+//! optional encryption. [`Storage`] hands the store itself over to a [`StorageBackend`]
+//! for its format, [`FileBackend`] or [`SqliteBackend`], and manages the files kept
+//! next to it.
+//!
+//! Writes are guarded by an advisory lock on a `<file>.lock` sidecar, and refuse to
+//! overwrite a store that was changed by another process since it was last loaded.
+//!
+//! Stores can also be pushed to and pulled from a `saltpass-sync` server through a
+//! [`SyncClient`]; the state of the last sync is kept in a `<file>.sync` sidecar.
 
-use crate::crypto::StorageCipher;
-use crate::models::FeatureStore;
-use std::fs;
+use crate::audit::{AuditAction, AuditLog};
+use crate::backend::StorageBackend;
+use crate::crypto::{StorageCipher, StorageKey};
+use crate::file::FileBackend;
+use crate::models::{Feature, FeatureStore};
+use crate::salt_change::SaltChange;
+use crate::sqlite::SqliteBackend;
+use crate::sync::SyncClient;
+use crate::{migrations, oplog};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, ErrorKind};
 use std::path::{Path, PathBuf};
 
 /// Storage format for features
-#[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum StorageFormat {
     Json,
     Toml,
+    /// Append-only operation log, one JSON entry per line
+    Log,
+    /// SQLite database with one row per feature
+    Sqlite,
 }
 
 impl StorageFormat {
@@ -22,27 +45,221 @@ impl StorageFormat {
         match self {
             StorageFormat::Json => "json",
             StorageFormat::Toml => "toml",
+            StorageFormat::Log => "log",
+            StorageFormat::Sqlite => "db",
         }
     }
 
-    #[allow(dead_code)]
     pub fn from_extension(ext: &str) -> Option<Self> {
         match ext.to_lowercase().as_str() {
             "json" => Some(StorageFormat::Json),
             "toml" => Some(StorageFormat::Toml),
+            "log" => Some(StorageFormat::Log),
+            "db" | "sqlite" => Some(StorageFormat::Sqlite),
             _ => None,
         }
     }
+
+    /// Guess the format from a store file name such as `features.json.enc`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = name.strip_suffix(".enc").unwrap_or(name);
+        Self::from_extension(Path::new(name).extension()?.to_str()?)
+    }
+
+    /// Whether stores in this format can carry a signature: plain TOML and JSON
+    pub fn supports_signature(self, encrypted: bool) -> bool {
+        !encrypted && matches!(self, StorageFormat::Json | StorageFormat::Toml)
+    }
+
+    /// Guess the format of a serialized (decrypted) store from its content
+    pub fn sniff(content: &str) -> Self {
+        let first_line = content.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        if serde_json::from_str::<oplog::Entry>(first_line).is_ok() {
+            StorageFormat::Log
+        } else if content.trim_start().starts_with('{') {
+            StorageFormat::Json
+        } else {
+            StorageFormat::Toml
+        }
+    }
+
+    /// Parse a serialized store, migrating it to the current schema version
+    ///
+    /// `key` decrypts the entries of an encrypted operation log; snapshots are decrypted
+    /// as a whole before parsing.
+    pub fn parse(self, content: &str, key: Option<&StorageKey>) -> io::Result<FeatureStore> {
+        serde_json::from_value(migrations::migrate(self.document(content, key)?)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Parse a serialized store into a document, without migrating it
+    pub fn document(
+        self,
+        content: &str,
+        key: Option<&StorageKey>,
+    ) -> io::Result<serde_json::Value> {
+        Ok(match self {
+            StorageFormat::Json => serde_json::from_str(content)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            StorageFormat::Toml => {
+                toml::from_str(content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+            }
+            StorageFormat::Log => oplog::fold(&oplog::parse(content, key)?)?,
+            StorageFormat::Sqlite => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "SQLite databases cannot be parsed from text",
+                ));
+            }
+        })
+    }
+}
+
+/// Error returned by [`Storage::save`] when the file was modified since it was loaded
+#[derive(Debug)]
+pub struct StoreChanged {
+    path: PathBuf,
+}
+
+impl fmt::Display for StoreChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} was modified by another process since it was loaded",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for StoreChanged {}
+
+impl StoreChanged {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+/// Returns `true` if the error was caused by a concurrent modification of the store file
+pub fn is_store_changed(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|e| e.is::<StoreChanged>())
+}
+
+/// Error returned when a signed plain store does not match its signature, or when it
+/// lacks one although it should be signed
+#[derive(Debug)]
+pub struct SignatureMismatch {
+    path: PathBuf,
+    missing: bool,
+}
+
+impl fmt::Display for SignatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.missing {
+            write!(
+                f,
+                "{} is not signed although signing is enabled or it was signed before: its \
+                 signature may have been removed outside SaltPass. Re-sign it if the content \
+                 is your own.",
+                self.path.display()
+            )
+        } else {
+            write!(
+                f,
+                "{} does not match its signature: it was modified outside SaltPass or signed \
+                 with a different salt. Re-sign it if the changes are your own.",
+                self.path.display()
+            )
+        }
+    }
+}
+
+impl std::error::Error for SignatureMismatch {}
+
+impl SignatureMismatch {
+    pub fn new(path: PathBuf, missing: bool) -> Self {
+        Self { path, missing }
+    }
+}
+
+/// Returns `true` if loading failed because the store does not match its signature
+pub fn is_signature_mismatch(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|e| e.is::<SignatureMismatch>())
+}
+
+/// Exclusive advisory lock on a store file, released when dropped
+pub struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    /// Take the lock of the store at `store_path`, see [`Storage::lock`]
+    pub fn acquire(store_path: &Path) -> io::Result<Self> {
+        if let Some(parent) = store_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut lock_path = store_path.to_path_buf().into_os_string();
+        lock_path.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(PathBuf::from(lock_path))?;
+        file.lock()?;
+        Ok(StoreLock { file })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Last sync of a store with a server
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    url: String,
+    revision: u64,
+    /// Sealed store as of `revision`, the common ancestor for merges
+    base: Option<String>,
+}
+
+/// Result of [`Storage::pull`]
+pub struct Pulled {
+    /// Revision on the server, 0 if the vault is empty
+    pub revision: u64,
+    /// Revision of the last sync of this store with the server
+    pub synced_revision: u64,
+    pub remote: Option<FeatureStore>,
+    /// The store as of the last sync, to merge local and remote changes
+    pub base: Option<FeatureStore>,
+    blob: Option<String>,
 }
 
 /// Storage handler for feature persistence
 ///
-/// Manages loading and saving features to disk in the specified format.
+/// Manages loading and saving features in the specified format through its backend.
+/// The backend is opened on first use with the settings made so far; changing a setting
+/// afterwards opens it again.
 pub struct Storage {
     file_path: PathBuf,
     format: StorageFormat,
     encrypted: bool,
     encryption_password: Option<String>,
+    /// Device ID written to operation log entries, see [`oplog::local_device_id`]
+    device_id: Option<String>,
+    /// Backend of the store's format, opened on first use
+    backend: OnceCell<Box<dyn StorageBackend>>,
+    /// Derived from the password on first use, see [`Storage::record_key`]
+    record_key: OnceCell<StorageKey>,
+    /// Key verifying and signing plain stores, see [`Storage::set_signature_key`]
+    signature_key: Option<StorageKey>,
+    /// Sign stores that are not signed yet
+    sign: bool,
+    /// Key of the audit log's hash chain, see [`Storage::set_audit_key`]
+    audit_key: Option<StorageKey>,
 }
 
 impl Storage {
@@ -52,20 +269,91 @@ impl Storage {
             format,
             encrypted,
             encryption_password: None,
+            device_id: None,
+            backend: OnceCell::new(),
+            record_key: OnceCell::new(),
+            signature_key: None,
+            sign: false,
+            audit_key: None,
         }
     }
 
     /// Set the encryption password for encrypted storage
     pub fn set_password(&mut self, password: String) {
         self.encryption_password = Some(password);
+        self.record_key = OnceCell::new();
+        self.backend = OnceCell::new();
+    }
+
+    /// Verify signed stores with `key` and keep them signed on write
+    ///
+    /// With `sign`, every store must be signed: one without a signature is reported as a
+    /// [`SignatureMismatch`] like a store whose signature was removed, and can be signed
+    /// with [`Storage::resign`]. Only plain TOML and JSON stores are signed, see
+    /// [`Storage::supports_signature`].
+    pub fn set_signature_key(&mut self, key: StorageKey, sign: bool) {
+        self.signature_key = Some(key);
+        self.sign = sign;
+        self.backend = OnceCell::new();
+    }
+
+    /// Key the hash chain of the audit log with `key`, see [`crate::audit::derive_key`]
+    pub fn set_audit_key(&mut self, key: StorageKey) {
+        self.audit_key = Some(key);
+    }
+
+    /// Whether this store can carry a signature (plain TOML or JSON)
+    pub fn supports_signature(&self) -> bool {
+        self.format.supports_signature(self.encrypted)
+    }
+
+    /// Override the device ID used for operation log entries
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn set_device_id(&mut self, device_id: String) {
+        self.device_id = Some(device_id);
+        self.backend = OnceCell::new();
+    }
+
+    /// Open an existing store file, detecting its format and encryption from the content
+    ///
+    /// Encryption is sniffed from the file content. The format of a plain store is sniffed
+    /// from its content too, while an encrypted store falls back to its file name until it
+    /// is decrypted on [`Storage::load`]. An operation log is encrypted line by line, and a
+    /// SQLite database records its encryption mode itself.
+    pub fn open(file_path: PathBuf) -> io::Result<Self> {
+        let content = fs::read(&file_path)?;
+        if SqliteBackend::looks_like_sqlite(&content) {
+            let encrypted = SqliteBackend::is_encrypted(&file_path)?;
+            return Ok(Self::new(file_path, StorageFormat::Sqlite, encrypted));
+        }
+
+        let content =
+            String::from_utf8(content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let named = StorageFormat::from_path(&file_path);
+        let encrypted = StorageCipher::looks_encrypted(&content)
+            || (matches!(named, Some(StorageFormat::Log))
+                && content
+                    .lines()
+                    .next()
+                    .is_some_and(StorageCipher::looks_encrypted));
+        let format = if encrypted {
+            named.unwrap_or(StorageFormat::Toml)
+        } else {
+            StorageFormat::sniff(&content)
+        };
+        Ok(Self::new(file_path, format, encrypted))
     }
 
-    #[allow(dead_code)]
     pub fn is_encrypted(&self) -> bool {
         self.encrypted
     }
 
-    pub fn default_path(format: StorageFormat, encrypted: bool) -> io::Result<PathBuf> {
+    pub fn format(&self) -> StorageFormat {
+        self.format
+    }
+
+    /// Directory holding SaltPass data (`~/.saltpass`), created if missing
+    pub fn config_dir() -> io::Result<PathBuf> {
         let home = dirs::home_dir()
             .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Home directory not found"))?;
 
@@ -73,76 +361,349 @@ impl Storage {
         if !config_dir.exists() {
             fs::create_dir_all(&config_dir)?;
         }
+        Ok(config_dir)
+    }
 
-        let ext = if encrypted {
-            format!("{}.enc", format.extension())
+    /// File name of a store with the given format and encryption mode
+    ///
+    /// SQLite databases encrypt their rows and keep the same name in both modes.
+    pub fn file_name(format: StorageFormat, encrypted: bool) -> String {
+        if encrypted && !matches!(format, StorageFormat::Sqlite) {
+            format!("features.{}.enc", format.extension())
         } else {
-            format.extension().to_string()
-        };
-        Ok(config_dir.join(format!("features.{}", ext)))
+            format!("features.{}", format.extension())
+        }
     }
 
-    pub fn load(&self) -> io::Result<FeatureStore> {
-        if !self.file_path.exists() {
-            return Ok(FeatureStore::new());
-        }
-
-        let content = fs::read_to_string(&self.file_path)?;
-
-        if self.encrypted {
-            let password = self.encryption_password.as_ref().ok_or_else(|| {
-                io::Error::new(ErrorKind::NotFound, "Encryption password not set")
-            })?;
-            let decrypted = StorageCipher::decrypt(password, &content)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            let decrypted_string = String::from_utf8(decrypted)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            match self.format {
-                StorageFormat::Json => serde_json::from_str(&decrypted_string)
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
-                StorageFormat::Toml => toml::from_str(&decrypted_string)
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+    pub fn default_path(format: StorageFormat, encrypted: bool) -> io::Result<PathBuf> {
+        Ok(Self::config_dir()?.join(Self::file_name(format, encrypted)))
+    }
+
+    /// List the existing `features.{toml,json,log}[.enc]` and `features.db` store files in a
+    /// directory
+    pub fn discover(dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        for encrypted in [false, true] {
+            for format in [
+                StorageFormat::Toml,
+                StorageFormat::Json,
+                StorageFormat::Log,
+                StorageFormat::Sqlite,
+            ] {
+                let path = dir.join(Self::file_name(format, encrypted));
+                if path.is_file() && !found.contains(&path) {
+                    found.push(path);
+                }
             }
+        }
+        Ok(found)
+    }
+
+    /// Take an exclusive advisory lock on the store
+    ///
+    /// Blocks until any other SaltPass process holding the lock releases it.
+    pub fn lock(&self) -> io::Result<StoreLock> {
+        StoreLock::acquire(&self.file_path)
+    }
+
+    fn password(&self) -> io::Result<&str> {
+        self.encryption_password
+            .as_deref()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Encryption password not set"))
+    }
+
+    /// Key used to encrypt single records: operation log entries, database rows and audit
+    /// records
+    fn record_key(&self) -> io::Result<Option<StorageKey>> {
+        Ok(if self.encrypted {
+            let password = self.password()?;
+            Some(
+                self.record_key
+                    .get_or_init(|| StorageCipher::derive_key(password))
+                    .clone(),
+            )
         } else {
-            match self.format {
-                StorageFormat::Json => serde_json::from_str(&content)
-                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
-                StorageFormat::Toml => {
-                    toml::from_str(&content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+            None
+        })
+    }
+
+    /// The backend of the store's format, opened on first use
+    fn backend(&self) -> io::Result<&dyn StorageBackend> {
+        if let Some(backend) = self.backend.get() {
+            return Ok(backend.as_ref());
+        }
+        let backend: Box<dyn StorageBackend> = match self.format {
+            StorageFormat::Sqlite => {
+                Box::new(SqliteBackend::open(&self.file_path, self.record_key()?)?)
+            }
+            format => {
+                let mut file = FileBackend::new(self.file_path.clone(), format, self.encrypted);
+                if let Some(password) = &self.encryption_password {
+                    let key = match format {
+                        StorageFormat::Log => self.record_key()?,
+                        _ => None,
+                    };
+                    file.set_password(password.clone(), key);
                 }
+                if let Some(key) = &self.signature_key {
+                    file.set_signature_key(key.clone(), self.sign);
+                }
+                if let Some(device_id) = &self.device_id {
+                    file.set_device_id(device_id.clone());
+                }
+                Box::new(file)
             }
+        };
+        Ok(self.backend.get_or_init(|| backend).as_ref())
+    }
+
+    /// Check whether the store changed since it was last loaded or saved
+    ///
+    /// Always `false` if the store was never loaded through this handle.
+    pub fn has_changed(&self) -> io::Result<bool> {
+        self.backend()?.has_changed()
+    }
+
+    /// Fail with [`StoreChanged`] if the store changed since it was last loaded or saved
+    pub fn ensure_unchanged(&self) -> io::Result<()> {
+        if self.has_changed()? {
+            return Err(io::Error::other(StoreChanged::new(self.file_path.clone())));
         }
+        Ok(())
+    }
+
+    pub fn load(&self) -> io::Result<FeatureStore> {
+        self.backend()?.load()
+    }
+
+    /// Load the store without checking its signature, see
+    /// [`StorageBackend::load_unverified`]
+    pub fn load_unverified(&self) -> io::Result<FeatureStore> {
+        self.backend()?.load_unverified()
+    }
+
+    /// Whether the store was loaded despite a bad signature and not re-signed since
+    pub fn is_tampered(&self) -> bool {
+        self.backend().is_ok_and(|backend| backend.is_tampered())
+    }
+
+    /// Sign the store and save it, accepting its current content
+    pub fn resign(&self, store: &FeatureStore) -> io::Result<()> {
+        self.backend()?.resign(store)
     }
 
+    /// Write the store
+    ///
+    /// If the store changed since it was loaded, nothing is written and a [`StoreChanged`]
+    /// error is returned (see [`is_store_changed`]) so the caller can reload and reapply
+    /// its change.
     pub fn save(&self, store: &FeatureStore) -> io::Result<()> {
-        if let Some(parent) = self.file_path.parent() {
-            fs::create_dir_all(parent)?;
+        self.backend()?.save(store)
+    }
+
+    /// Apply a change to the whole store on top of its current content
+    ///
+    /// For changes that touch more than a single feature, such as moving a feature to the
+    /// trash. Concurrent changes are kept as with [`StorageBackend::put`].
+    pub fn update<T>(&self, change: impl FnOnce(&mut FeatureStore) -> T) -> io::Result<T> {
+        let mut change = Some(change);
+        let mut result = None;
+        self.backend()?.update(&mut |store| {
+            if let Some(change) = change.take() {
+                result = Some(change(store));
+            }
+        })?;
+        Ok(result.expect("the backend applies the change"))
+    }
+
+    /// Look up a single feature by name and identifier
+    pub fn get(&self, name: &str, feature: &str) -> io::Result<Option<Feature>> {
+        self.backend()?.get(name, feature)
+    }
+
+    /// Write a single feature on top of the current store, see [`StorageBackend::put`]
+    pub fn put(&self, feature: &Feature) -> io::Result<()> {
+        self.backend()?.put(feature)
+    }
+
+    /// Delete the feature with the given ID, returning `false` if it did not exist
+    pub fn delete(&self, id: &str) -> io::Result<bool> {
+        self.backend()?.delete(id)
+    }
+
+    /// Compact an operation log store, returning the entry counts before and after
+    ///
+    /// Only the winning entry of each feature and store field is kept, see
+    /// [`oplog::compact`]. Fails with [`StoreChanged`] like [`Storage::save`].
+    pub fn compact(&self) -> io::Result<(usize, usize)> {
+        self.backend()?.compact()
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    /// Sidecar file next to the store, e.g. `features.toml.sync`
+    pub fn sidecar(&self, extension: &str) -> PathBuf {
+        let mut path = self.file_path.clone().into_os_string();
+        path.push(".");
+        path.push(extension);
+        PathBuf::from(path)
+    }
+
+    /// Audit log of this store, kept in `<file>.audit`
+    pub fn audit_log(&self) -> io::Result<AuditLog> {
+        let chain_key = self
+            .audit_key
+            .clone()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Audit key not set"))?;
+        Ok(AuditLog::new(
+            self.sidecar("audit"),
+            self.record_key()?,
+            chain_key,
+        ))
+    }
+
+    /// Append an audit record for a mutation of `feature`
+    ///
+    /// The record is appended under [`Storage::lock`], so records written by several
+    /// processes form one chain.
+    pub fn audit(&self, action: AuditAction, feature: &Feature) -> io::Result<()> {
+        let _lock = self.lock()?;
+        self.audit_log()?.append(action, feature)
+    }
+
+    /// Chain the audit log under a new key, e.g. when the master salt changes
+    ///
+    /// If the chain is broken under the current key, the log is left as it is and the
+    /// index of the first invalid record is returned instead.
+    pub fn rekey_audit(&mut self, key: StorageKey) -> io::Result<Result<(), usize>> {
+        let _lock = self.lock()?;
+        let entries = self.audit_log()?.read()?;
+        if let Err(index) = self.audit_log()?.verify(&entries)? {
+            return Ok(Err(index));
+        }
+        self.audit_key = Some(key);
+        let log = self.audit_log()?;
+        log.rewrite(&log.rechain(&entries)?)?;
+        Ok(Ok(()))
+    }
+
+    /// Progress of the master salt change in progress, if any
+    pub fn salt_change(&self) -> io::Result<Option<SaltChange>> {
+        SaltChange::load(&self.sidecar("salt-change"), self.record_key()?.as_ref())
+    }
+
+    pub fn set_salt_change(&self, change: &SaltChange) -> io::Result<()> {
+        change.save(&self.sidecar("salt-change"), self.record_key()?.as_ref())
+    }
+
+    /// Forget the salt change progress
+    pub fn clear_salt_change(&self) -> io::Result<()> {
+        match fs::remove_file(self.sidecar("salt-change")) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Encrypt the whole store and its audit log under a new password
+    ///
+    /// Used when the master salt changes. Fails with [`StoreChanged`] like
+    /// [`Storage::save`].
+    pub fn reencrypt(&mut self, password: String) -> io::Result<()> {
+        if !self.encrypted {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Only encrypted stores can be re-encrypted",
+            ));
         }
+        let audit = self.audit_log()?.read()?;
 
-        let data = match self.format {
-            StorageFormat::Json => serde_json::to_vec_pretty(store)
+        let key = StorageCipher::derive_key(&password);
+        self.backend()?;
+        let backend = self.backend.get_mut().expect("backend opened");
+        backend.reencrypt(&password, &key)?;
+        self.encryption_password = Some(password);
+        self.record_key = OnceCell::from(key);
+
+        self.audit_log()?.rewrite(&audit)
+    }
+
+    /// State of the last sync with the client's server
+    fn sync_state(&self, client: &SyncClient) -> io::Result<SyncState> {
+        let state: SyncState = match fs::read_to_string(self.sidecar("sync")) {
+            Ok(content) => serde_json::from_str(&content)
                 .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
-            StorageFormat::Toml => toml::to_string_pretty(store)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
-                .into_bytes(),
+            Err(e) if e.kind() == ErrorKind::NotFound => SyncState::default(),
+            Err(e) => return Err(e),
         };
-
-        let content = if self.encrypted {
-            let password = self.encryption_password.as_ref().ok_or_else(|| {
-                io::Error::new(ErrorKind::NotFound, "Encryption password not set")
-            })?;
-            StorageCipher::encrypt(password, &data)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+        Ok(if state.url == client.url() {
+            state
         } else {
-            String::from_utf8(data).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+            SyncState::default()
+        })
+    }
+
+    fn set_sync_state(&self, client: &SyncClient, revision: u64, blob: String) -> io::Result<()> {
+        let state = SyncState {
+            url: client.url().to_string(),
+            revision,
+            base: Some(blob),
+        };
+        let content =
+            serde_json::to_vec(&state).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let path = self.sidecar("sync");
+        let tmp_path = self.sidecar("sync.tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Download the store from the sync server
+    ///
+    /// Nothing is changed locally; merge the result with the local store if the server
+    /// changed since the last sync, then [`Storage::push`] it.
+    pub fn pull(&self, client: &SyncClient) -> io::Result<Pulled> {
+        let state = self.sync_state(client)?;
+        let base = state
+            .base
+            .as_deref()
+            .map(|b| client.unseal(b))
+            .transpose()?;
+        let (revision, remote, blob) = match client.pull()? {
+            Some((revision, blob)) => (revision, Some(client.unseal(&blob)?), Some(blob)),
+            None => (0, None, None),
         };
+        Ok(Pulled {
+            revision,
+            synced_revision: state.revision,
+            remote,
+            base,
+            blob,
+        })
+    }
 
-        fs::write(&self.file_path, content)?;
-        Ok(())
+    /// Upload `store` to the sync server, replacing revision `expected`
+    ///
+    /// Returns the new revision, or fails with a [`crate::sync::SyncConflict`] if the
+    /// server holds another revision.
+    pub fn push(
+        &self,
+        client: &SyncClient,
+        store: &FeatureStore,
+        expected: u64,
+    ) -> io::Result<u64> {
+        let blob = client.seal(store)?;
+        let revision = client.push(expected, &blob)?;
+        self.set_sync_state(client, revision, blob)?;
+        Ok(revision)
     }
 
-    pub fn file_path(&self) -> &Path {
-        &self.file_path
+    /// Record that the local store matches what was pulled, without uploading it again
+    pub fn mark_synced(&self, client: &SyncClient, pulled: &Pulled) -> io::Result<()> {
+        match &pulled.blob {
+            Some(blob) => self.set_sync_state(client, pulled.revision, blob.clone()),
+            None => Ok(()),
+        }
     }
 
     /// Export decrypted content as TOML string for viewing
@@ -154,45 +715,16 @@ impl Storage {
             ));
         }
 
-        let content = fs::read_to_string(&self.file_path)?;
-
-        if self.encrypted {
-            let password = self.encryption_password.as_ref().ok_or_else(|| {
-                io::Error::new(ErrorKind::NotFound, "Encryption password not set")
-            })?;
-            let decrypted = StorageCipher::decrypt(password, &content)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            let decrypted_string = String::from_utf8(decrypted)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            // Always show as TOML for consistency
-            match self.format {
-                StorageFormat::Json => {
-                    let store: FeatureStore = serde_json::from_str(&decrypted_string)
-                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-                    toml::to_string_pretty(&store)
-                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
-                }
-                StorageFormat::Toml => Ok(decrypted_string),
-            }
-        } else {
-            match self.format {
-                StorageFormat::Json => {
-                    // Parse JSON and convert to TOML for viewing
-                    let store: FeatureStore = serde_json::from_str(&content)
-                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-                    toml::to_string_pretty(&store)
-                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
-                }
-                StorageFormat::Toml => Ok(content),
-            }
-        }
+        self.backend()?.export_toml()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::Feature;
+    use crate::models::{Feature, test_feature};
+    use crate::{audit, signature};
+    use chrono::Utc;
     use std::fs;
 
     #[test]
@@ -206,12 +738,14 @@ mod tests {
 
         let storage = Storage::new(test_file.clone(), StorageFormat::Json, false);
         let mut store = FeatureStore::new();
-        store.add_feature(Feature::new(
-            "GitHub".to_string(),
-            "github.com".to_string(),
-            crate::crypto::Algorithm::HmacSha256,
-            Some("Main account".to_string()),
-        ));
+        store
+            .add_feature(Feature::new(
+                "GitHub".to_string(),
+                "github.com".to_string(),
+                crate::crypto::Algorithm::HmacSha256,
+                Some("Main account".to_string()),
+            ))
+            .unwrap();
 
         storage.save(&store).unwrap();
 
@@ -221,4 +755,379 @@ mod tests {
 
         fs::remove_file(&test_file).unwrap();
     }
+
+    #[test]
+    fn test_open_sniffs_format_and_encryption() {
+        let dir = std::env::temp_dir().join("saltpass_test_discover");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let json_path = dir.join(Storage::file_name(StorageFormat::Json, false));
+        Storage::new(json_path.clone(), StorageFormat::Json, false)
+            .save(&FeatureStore::new())
+            .unwrap();
+
+        let enc_path = dir.join(Storage::file_name(StorageFormat::Toml, true));
+        let mut encrypted = Storage::new(enc_path.clone(), StorageFormat::Toml, true);
+        encrypted.set_password("salt".to_string());
+        encrypted.save(&FeatureStore::new()).unwrap();
+
+        assert_eq!(
+            Storage::discover(&dir).unwrap(),
+            vec![json_path.clone(), enc_path.clone()]
+        );
+
+        let plain = Storage::open(json_path).unwrap();
+        assert!(!plain.is_encrypted());
+        assert!(matches!(plain.format(), StorageFormat::Json));
+
+        let mut sniffed = Storage::open(enc_path).unwrap();
+        assert!(sniffed.is_encrypted());
+        assert!(matches!(sniffed.format(), StorageFormat::Toml));
+        sniffed.set_password("salt".to_string());
+        assert!(sniffed.load().unwrap().features.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_operation_log_store() {
+        let dir = std::env::temp_dir().join("saltpass_test_oplog");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(Storage::file_name(StorageFormat::Log, true));
+        let mut laptop = Storage::new(path.clone(), StorageFormat::Log, true);
+        laptop.set_password("salt".to_string());
+        laptop.set_device_id("laptop".to_string());
+        let mut store = laptop.load().unwrap();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        store.add_feature(test_feature("Mail")).unwrap();
+        laptop.save(&store).unwrap();
+        store.remove_feature(1);
+        laptop.save(&store).unwrap();
+        laptop.save(&store).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        // A copy edited on another device merges by concatenating the files
+        let copy = dir.join("copy.log.enc");
+        fs::copy(&path, &copy).unwrap();
+        let mut phone = Storage::open(copy.clone()).unwrap();
+        assert!(phone.is_encrypted());
+        assert!(matches!(phone.format(), StorageFormat::Log));
+        phone.set_password("salt".to_string());
+        phone.set_device_id("phone".to_string());
+        let mut phone_store = phone.load().unwrap();
+        phone_store.add_feature(test_feature("Bank")).unwrap();
+        phone.save(&phone_store).unwrap();
+        store.add_feature(test_feature("Shop")).unwrap();
+        laptop.save(&store).unwrap();
+
+        let mut combined = fs::read_to_string(&path).unwrap();
+        combined.push_str(&fs::read_to_string(&copy).unwrap());
+        fs::write(&path, combined).unwrap();
+        let merged = laptop.load().unwrap();
+        let names: Vec<&str> = merged.features.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["GitHub", "Shop", "Bank"]);
+
+        let (before, after) = laptop.compact().unwrap();
+        assert_eq!((before, after), (8, 4));
+        let compacted = laptop.load().unwrap();
+        assert_eq!(compacted.features.len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_single_feature_writes() {
+        let dir = std::env::temp_dir().join("saltpass_test_backends");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let feature = |name: &str, hint: Option<&str>| Feature {
+            hint: hint.map(str::to_string),
+            ..test_feature(name)
+        };
+
+        for format in [StorageFormat::Toml, StorageFormat::Sqlite] {
+            let path = dir.join(Storage::file_name(format, false));
+            let ours = Storage::new(path.clone(), format, false);
+            let theirs = Storage::new(path.clone(), format, false);
+            ours.load().unwrap();
+            theirs.load().unwrap();
+
+            ours.put(&feature("GitHub", None)).unwrap();
+            assert!(!ours.has_changed().unwrap());
+            ours.put(&feature("GitHub", Some("work"))).unwrap();
+
+            // A single-feature write keeps the other handle's change but not its sync state
+            theirs.put(&feature("Mail", None)).unwrap();
+            assert!(theirs.has_changed().unwrap());
+            assert!(ours.has_changed().unwrap());
+            assert!(is_store_changed(&ours.ensure_unchanged().unwrap_err()));
+            assert!(is_store_changed(
+                &ours.save(&FeatureStore::new()).unwrap_err()
+            ));
+
+            let store = ours.load().unwrap();
+            assert_eq!(store.features.len(), 2);
+            assert_eq!(store.features[0].hint.as_deref(), Some("work"));
+            let mail = store.find("Mail", "mail.com").unwrap().id.clone();
+            assert!(ours.delete(&mail).unwrap());
+            assert!(ours.get("Mail", "mail.com").unwrap().is_none());
+            assert!(ours.get("GitHub", "github.com").unwrap().is_some());
+
+            // Whole-store updates such as moving a feature to the trash
+            let trashed = ours
+                .update(|store| {
+                    let id = store.find("GitHub", "github.com").unwrap().id.clone();
+                    store.trash_feature(&id, Utc::now())
+                })
+                .unwrap();
+            assert!(trashed.is_some());
+            assert!(!ours.has_changed().unwrap());
+            let store = theirs.load().unwrap();
+            assert!(store.features.is_empty());
+            assert_eq!(store.trash[0].feature.hint.as_deref(), Some("work"));
+        }
+
+        let db = Storage::open(dir.join("features.db")).unwrap();
+        assert!(matches!(db.format(), StorageFormat::Sqlite));
+        assert!(!db.is_encrypted());
+        assert!(db.export_decrypted().unwrap().contains("github.com"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_fields_survive_round_trip() {
+        let test_file = std::env::temp_dir().join("test_features_future.toml");
+        fs::write(
+            &test_file,
+            r#"schema_version = 1
+sync_token = "abc"
+
+[[features]]
+name = "GitHub"
+feature = "github.com"
+algorithm = "HmacSha256"
+created = "2026-01-01T00:00:00Z"
+color = "blue"
+"#,
+        )
+        .unwrap();
+
+        let storage = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        let store = storage.load().unwrap();
+        storage.save(&store).unwrap();
+
+        let saved = fs::read_to_string(&test_file).unwrap();
+        assert!(saved.contains(r#"sync_token = "abc""#));
+        assert!(saved.contains(r#"color = "blue""#));
+
+        fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn test_save_detects_concurrent_modification() {
+        let test_file = std::env::temp_dir().join("test_features_concurrent.toml");
+        let _ = fs::remove_file(&test_file);
+
+        let first = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        let second = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        let mut store_a = first.load().unwrap();
+        let mut store_b = second.load().unwrap();
+
+        store_a
+            .add_feature(Feature::new(
+                "GitHub".to_string(),
+                "github.com".to_string(),
+                crate::crypto::Algorithm::HmacSha256,
+                None,
+            ))
+            .unwrap();
+        first.save(&store_a).unwrap();
+
+        store_b
+            .add_feature(Feature::new(
+                "GitLab".to_string(),
+                "gitlab.com".to_string(),
+                crate::crypto::Algorithm::HmacSha256,
+                None,
+            ))
+            .unwrap();
+        let err = second.save(&store_b).unwrap_err();
+        assert!(is_store_changed(&err));
+
+        // Reloading picks up the other change and allows saving again
+        let mut reloaded = second.load().unwrap();
+        assert_eq!(reloaded.features.len(), 1);
+        reloaded.add_feature(store_b.features[0].clone()).unwrap();
+        second.save(&reloaded).unwrap();
+        assert_eq!(first.load().unwrap().features.len(), 2);
+
+        fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn test_signed_store_detects_tampering() {
+        let test_file = std::env::temp_dir().join("test_features_signed.toml");
+        let _ = fs::remove_file(&test_file);
+
+        let mut storage = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        storage.set_signature_key(signature::derive_key("salt"), true);
+        storage.load().unwrap();
+        storage
+            .put(&Feature::new(
+                "GitHub".to_string(),
+                "github.com".to_string(),
+                crate::crypto::Algorithm::HmacSha256,
+                None,
+            ))
+            .unwrap();
+        assert!(storage.load().unwrap().signature.is_some());
+
+        let content = fs::read_to_string(&test_file).unwrap();
+        fs::write(&test_file, content.replace("HmacSha256", "Pbkdf2")).unwrap();
+        assert!(is_signature_mismatch(&storage.load().unwrap_err()));
+        assert!(is_signature_mismatch(
+            &storage.get("GitHub", "github.com").unwrap_err()
+        ));
+
+        // Re-signing accepts the edit
+        let store = storage.load_unverified().unwrap();
+        storage.resign(&store).unwrap();
+        let store = storage.load().unwrap();
+        assert_eq!(
+            store.features[0].algorithm,
+            crate::crypto::Algorithm::Pbkdf2
+        );
+
+        // Without the key the signature is not checked
+        let unkeyed = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        assert!(unkeyed.load().is_ok());
+
+        fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn test_removed_signature_is_tampering() {
+        let test_file = std::env::temp_dir().join("test_features_unsigned.toml");
+        let _ = fs::remove_file(&test_file);
+        let strip = || {
+            let content = fs::read_to_string(&test_file).unwrap();
+            let stripped: Vec<&str> = content
+                .lines()
+                .filter(|line| !line.starts_with("signature"))
+                .collect();
+            fs::write(&test_file, stripped.join("\n")).unwrap();
+        };
+
+        let mut signing = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        signing.set_signature_key(signature::derive_key("salt"), true);
+        signing.load().unwrap();
+        signing.put(&test_feature("GitHub")).unwrap();
+
+        // A handle that saw the signature, even without signing on, refuses the stripped file
+        let mut storage = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        storage.set_signature_key(signature::derive_key("salt"), false);
+        storage.load().unwrap();
+        strip();
+        assert!(is_signature_mismatch(&storage.load().unwrap_err()));
+        assert!(is_signature_mismatch(
+            &storage.put(&test_feature("Mail")).unwrap_err()
+        ));
+        assert!(is_signature_mismatch(&signing.load().unwrap_err()));
+
+        // Opened anyway, it cannot be written until it is re-signed
+        let store = signing.load_unverified().unwrap();
+        assert!(signing.is_tampered());
+        assert!(is_signature_mismatch(&signing.save(&store).unwrap_err()));
+        signing.resign(&store).unwrap();
+        assert!(!signing.is_tampered());
+        assert!(signing.load().unwrap().signature.is_some());
+        signing.put(&test_feature("Mail")).unwrap();
+        assert_eq!(storage.load().unwrap().features.len(), 2);
+
+        fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn test_signed_store_survives_merge() {
+        let test_file = std::env::temp_dir().join("test_features_signed_merge.toml");
+        let _ = fs::remove_file(&test_file);
+
+        let mut storage = Storage::new(test_file.clone(), StorageFormat::Toml, false);
+        storage.set_signature_key(signature::derive_key("salt"), true);
+        storage.load().unwrap();
+        storage.put(&test_feature("GitHub")).unwrap();
+        let base = storage.load().unwrap();
+        storage.put(&test_feature("Bank")).unwrap();
+        let ours = storage.load().unwrap();
+        let mut theirs = base.clone();
+        theirs.features.push(test_feature("Mail"));
+
+        let merged = crate::merge::three_way_merge(&base, &ours, &theirs)
+            .unwrap()
+            .resolve(|_| unreachable!())
+            .unwrap();
+        assert!(merged.signature.is_none());
+
+        // Written like `SaltPass merge` does, then opened on the next launch
+        let mut output = Storage::open(test_file.clone()).unwrap();
+        output.load().unwrap();
+        output.set_signature_key(signature::derive_key("salt"), true);
+        output.save(&merged).unwrap();
+        let mut reopened = Storage::open(test_file.clone()).unwrap();
+        reopened.set_signature_key(signature::derive_key("salt"), true);
+        assert_eq!(reopened.load().unwrap().features.len(), 3);
+
+        fs::remove_file(&test_file).unwrap();
+    }
+
+    #[test]
+    fn test_reencrypt_under_new_salt() {
+        let dir = std::env::temp_dir().join("saltpass_test_reencrypt");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let feature = Feature::new(
+            "GitHub".to_string(),
+            "github.com".to_string(),
+            crate::crypto::Algorithm::HmacSha256,
+            None,
+        );
+
+        for format in [
+            StorageFormat::Toml,
+            StorageFormat::Log,
+            StorageFormat::Sqlite,
+        ] {
+            let path = dir.join(Storage::file_name(format, true));
+            let mut storage = Storage::new(path.clone(), format, true);
+            storage.set_password("old".to_string());
+            storage.set_device_id("laptop".to_string());
+            storage.load().unwrap();
+            storage.set_audit_key(audit::derive_key("old"));
+            storage.put(&feature).unwrap();
+            storage.audit(AuditAction::Add, &feature).unwrap();
+
+            assert_eq!(
+                storage.rekey_audit(audit::derive_key("new")).unwrap(),
+                Ok(())
+            );
+            storage.reencrypt("new".to_string()).unwrap();
+            assert_eq!(storage.load().unwrap().features[0].name, "GitHub");
+            let log = storage.audit_log().unwrap();
+            let entries = log.read().unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(log.verify(&entries).unwrap(), Ok(()));
+
+            let mut reopened = Storage::open(path.clone()).unwrap();
+            reopened.set_password("old".to_string());
+            assert!(reopened.load().is_err());
+            reopened.set_password("new".to_string());
+            assert_eq!(reopened.load().unwrap().features.len(), 1);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,228 @@
+//! Sync client for `saltpass-sync` servers
+//!
+//! Stores are end-to-end encrypted before they leave the machine. The vault ID and the
+//! encryption key are both derived from the master salt with separate labels, so the
+//! server only ever sees a pseudonymous ID and an opaque blob. Uploads name the revision
+//! they replace; if someone else uploaded in between, the push fails with
+//! [`SyncConflict`] and the caller pulls, merges and tries again.
+
+use crate::crypto::{StorageCipher, StorageKey};
+use crate::migrations;
+use crate::models::FeatureStore;
+use sha2::Sha256;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Header carrying revision numbers, see `sync_server.rs` for the protocol
+const REVISION_HEADER: &str = "X-Revision";
+
+/// PBKDF2 iterations for the vault ID and key, slow enough to make guessing salts from
+/// the vault ID impractical
+const KDF_ITERATIONS: u32 = 100_000;
+
+/// Error returned by [`SyncClient::push`] when the server holds a newer revision
+#[derive(Debug)]
+pub struct SyncConflict {
+    pub revision: u64,
+}
+
+impl fmt::Display for SyncConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The server has a newer revision ({}) of this store",
+            self.revision
+        )
+    }
+}
+
+impl std::error::Error for SyncConflict {}
+
+/// Returns the revision held by the server if the push failed because of a conflict
+pub fn conflict_revision(err: &io::Error) -> Option<u64> {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<SyncConflict>())
+        .map(|c| c.revision)
+}
+
+fn http_error(e: ureq::Error) -> io::Error {
+    match e {
+        ureq::Error::Status(code, response) => io::Error::other(format!(
+            "Sync server answered {} {}",
+            code,
+            response.status_text()
+        )),
+        ureq::Error::Transport(e) => io::Error::new(ErrorKind::ConnectionRefused, e.to_string()),
+    }
+}
+
+fn revision_of(response: &ureq::Response) -> io::Result<u64> {
+    response
+        .header(REVISION_HEADER)
+        .and_then(|r| r.parse().ok())
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing revision from server"))
+}
+
+pub struct SyncClient {
+    url: String,
+    vault_id: String,
+    key: StorageKey,
+    agent: ureq::Agent,
+}
+
+impl SyncClient {
+    /// Connect to the server at `url` (e.g. `http://192.168.1.10:7878`) for the vault of
+    /// this master salt
+    pub fn new(url: &str, salt: &str) -> Self {
+        use pbkdf2::pbkdf2_hmac;
+
+        let mut id = [0u8; 16];
+        pbkdf2_hmac::<Sha256>(
+            salt.as_bytes(),
+            b"SaltPass-Sync-Vault",
+            KDF_ITERATIONS,
+            &mut id,
+        );
+        let mut key = Zeroizing::new([0u8; 32]);
+        pbkdf2_hmac::<Sha256>(
+            salt.as_bytes(),
+            b"SaltPass-Sync-Key",
+            KDF_ITERATIONS,
+            key.as_mut(),
+        );
+
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            vault_id: hex::encode(id),
+            key,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn vault_url(&self) -> String {
+        format!("{}/v1/vaults/{}", self.url, self.vault_id)
+    }
+
+    /// Download the current blob and its revision, `None` if the vault is empty
+    pub fn pull(&self) -> io::Result<Option<(u64, String)>> {
+        match self.agent.get(&self.vault_url()).call() {
+            Ok(response) => {
+                let revision = revision_of(&response)?;
+                Ok(Some((revision, response.into_string()?)))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    /// Upload a blob replacing revision `expected` (0 for an empty vault)
+    ///
+    /// Returns the new revision, or a [`SyncConflict`] error.
+    pub fn push(&self, expected: u64, blob: &str) -> io::Result<u64> {
+        let result = self
+            .agent
+            .put(&self.vault_url())
+            .set("If-Match", &expected.to_string())
+            .send_string(blob);
+        match result {
+            Ok(response) => revision_of(&response),
+            Err(ureq::Error::Status(409, response)) => Err(io::Error::other(SyncConflict {
+                revision: revision_of(&response)?,
+            })),
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    /// Encrypt a store for upload
+    pub fn seal(&self, store: &FeatureStore) -> io::Result<String> {
+        let json =
+            serde_json::to_vec(store).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        StorageCipher::encrypt_with_key(&self.key, &json)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Decrypt a downloaded store
+    pub fn unseal(&self, blob: &str) -> io::Result<FeatureStore> {
+        let json = StorageCipher::decrypt_with_key(&self.key, blob).map_err(|e| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot decrypt the synced store (different salt?): {}", e),
+            )
+        })?;
+        let doc =
+            serde_json::from_slice(&json).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        serde_json::from_value(migrations::migrate(doc)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::test_feature;
+    use crate::storage::{Storage, StorageFormat};
+    use crate::sync_server::SyncServer;
+    use std::fs;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_push_pull_through_server() {
+        let dir = std::env::temp_dir().join("saltpass_test_sync");
+        let _ = fs::remove_dir_all(&dir);
+
+        let server = Arc::new(SyncServer::bind("127.0.0.1:0", &dir.join("server")).unwrap());
+        let url = format!("http://{}", server.local_addr().unwrap());
+        let handle = thread::spawn({
+            let server = Arc::clone(&server);
+            move || server.run()
+        });
+
+        let client = SyncClient::new(&url, "salt");
+        let laptop = Storage::new(dir.join("laptop.toml"), StorageFormat::Toml, false);
+        let phone = Storage::new(dir.join("phone.toml"), StorageFormat::Toml, false);
+
+        let mut store = FeatureStore::new();
+        store.add_feature(test_feature("GitHub")).unwrap();
+        assert!(laptop.pull(&client).unwrap().remote.is_none());
+        assert_eq!(laptop.push(&client, &store, 0).unwrap(), 1);
+
+        let pulled = phone.pull(&client).unwrap();
+        assert_eq!(pulled.revision, 1);
+        let mut phone_store = pulled.remote.unwrap();
+        assert_eq!(phone_store.features[0].name, "GitHub");
+        phone_store.add_feature(test_feature("Mail")).unwrap();
+        assert_eq!(
+            phone.push(&client, &phone_store, pulled.revision).unwrap(),
+            2
+        );
+
+        // The laptop's push is based on revision 1 and must merge first
+        store.add_feature(test_feature("Bank")).unwrap();
+        let err = laptop.push(&client, &store, 1).unwrap_err();
+        assert_eq!(conflict_revision(&err), Some(2));
+        let pulled = laptop.pull(&client).unwrap();
+        assert_eq!(pulled.synced_revision, 1);
+        assert_eq!(pulled.base.unwrap().features.len(), 1);
+        assert_eq!(pulled.remote.unwrap().features.len(), 2);
+
+        // The server only stores ciphertext
+        let stored =
+            fs::read_to_string(dir.join("server").join(format!("{}.json", client.vault_id)))
+                .unwrap();
+        assert!(!stored.contains("GitHub"));
+        assert!(SyncClient::new(&url, "other").pull().unwrap().is_none());
+
+        server.stop();
+        handle.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Sync server storing opaque blobs with revision numbers
+//!
+//! The server never sees plaintext or keys: clients upload feature stores encrypted on
+//! their side, under a vault ID derived from the master salt (see `sync.rs`). Each vault
+//! holds a single blob and a revision number; an upload must name the revision it
+//! replaces and is rejected with `409 Conflict` otherwise.
+//!
+//! ```text
+//! GET /v1/vaults/<id>   200 + blob and X-Revision, or 404
+//! PUT /v1/vaults/<id>   If-Match: <revision> (0 to create)
+//!                       200 + new X-Revision, or 409 + current X-Revision
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tiny_http::{Header, Method, Request, Response};
+
+/// Largest accepted blob
+const MAX_BLOB_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Header carrying revision numbers in responses
+const REVISION_HEADER: &str = "X-Revision";
+
+/// A stored vault
+#[derive(Serialize, Deserialize)]
+struct Vault {
+    revision: u64,
+    blob: String,
+}
+
+pub struct SyncServer {
+    http: tiny_http::Server,
+    data_dir: PathBuf,
+    /// Serializes the read-compare-write of uploads
+    write_lock: Mutex<()>,
+}
+
+impl SyncServer {
+    /// Listen on `addr`, keeping vaults in `data_dir`
+    pub fn bind(addr: &str, data_dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let http = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+        Ok(Self {
+            http,
+            data_dir: data_dir.to_path_buf(),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Serve requests until [`SyncServer::stop`] is called
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            let response = self.handle(request);
+            if let Err(e) = response {
+                eprintln!("❌ Failed to answer request: {}", e);
+            }
+        }
+    }
+
+    /// Make [`SyncServer::run`] return (used by the in-process client tests)
+    #[allow(dead_code)]
+    pub fn stop(&self) {
+        self.http.unblock();
+    }
+
+    fn vault_path(&self, id: &str) -> Option<PathBuf> {
+        let valid = (16..=128).contains(&id.len())
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        valid.then(|| self.data_dir.join(format!("{}.json", id)))
+    }
+
+    fn read_vault(path: &Path) -> io::Result<Option<Vault>> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn handle(&self, mut request: Request) -> io::Result<()> {
+        let path = request
+            .url()
+            .strip_prefix("/v1/vaults/")
+            .and_then(|id| self.vault_path(id));
+        let Some(path) = path else {
+            return request.respond(Response::from_string("Not found").with_status_code(404));
+        };
+
+        match request.method() {
+            Method::Get => match Self::read_vault(&path) {
+                Ok(Some(vault)) => request.respond(
+                    Response::from_string(vault.blob).with_header(revision_header(vault.revision)),
+                ),
+                Ok(None) => {
+                    request.respond(Response::from_string("No such vault").with_status_code(404))
+                }
+                Err(e) => request.respond(server_error(&e)),
+            },
+            Method::Put => {
+                let expected = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("If-Match"))
+                    .and_then(|h| h.value.as_str().trim_matches('"').parse::<u64>().ok());
+                let Some(expected) = expected else {
+                    return request.respond(
+                        Response::from_string("If-Match revision required").with_status_code(428),
+                    );
+                };
+
+                let mut blob = String::new();
+                let read = request
+                    .as_reader()
+                    .take(MAX_BLOB_SIZE + 1)
+                    .read_to_string(&mut blob);
+                if read.is_err() || blob.len() as u64 > MAX_BLOB_SIZE {
+                    return request.respond(
+                        Response::from_string("Invalid or oversized blob").with_status_code(413),
+                    );
+                }
+
+                let response = self.store(&path, expected, blob);
+                request.respond(match response {
+                    Ok(Ok(revision)) => Response::from_string(revision.to_string())
+                        .with_header(revision_header(revision)),
+                    Ok(Err(current)) => Response::from_string("Revision conflict")
+                        .with_status_code(409)
+                        .with_header(revision_header(current)),
+                    Err(e) => server_error(&e),
+                })
+            }
+            _ => request.respond(Response::from_string("Method not allowed").with_status_code(405)),
+        }
+    }
+
+    /// Replace the vault if it is at revision `expected`
+    ///
+    /// Returns the new revision, or `Err` with the current one on conflict.
+    fn store(&self, path: &Path, expected: u64, blob: String) -> io::Result<Result<u64, u64>> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let current = Self::read_vault(path)?.map_or(0, |v| v.revision);
+        if current != expected {
+            return Ok(Err(current));
+        }
+
+        let vault = Vault {
+            revision: current + 1,
+            blob,
+        };
+        let content =
+            serde_json::to_vec(&vault).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(Ok(vault.revision))
+    }
+}
+
+fn revision_header(revision: u64) -> Header {
+    Header::from_bytes(REVISION_HEADER.as_bytes(), revision.to_string().as_bytes())
+        .expect("valid header")
+}
+
+fn server_error(e: &io::Error) -> Response<io::Cursor<Vec<u8>>> {
+    eprintln!("❌ {}", e);
+    Response::from_string("Internal error").with_status_code(500)
+}